@@ -0,0 +1,17 @@
+-- Create password_history table
+CREATE TABLE password_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    password_hash VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_password_history_user_id_created_at ON password_history(user_id, created_at DESC);
+
+-- Add comments for documentation
+COMMENT ON TABLE password_history IS 'Previous password hashes per user, used to prevent password reuse';
+COMMENT ON COLUMN password_history.id IS 'Unique identifier for the history entry';
+COMMENT ON COLUMN password_history.user_id IS 'User the password belonged to';
+COMMENT ON COLUMN password_history.password_hash IS 'Bcrypt hash of the replaced password';
+COMMENT ON COLUMN password_history.created_at IS 'Timestamp when the password was replaced';
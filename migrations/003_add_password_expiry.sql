@@ -0,0 +1,16 @@
+-- Track password age and forced password changes
+ALTER TABLE auth_users ADD COLUMN password_changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE auth_users ADD COLUMN password_expires BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE auth_users ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT false;
+
+-- Administrators can manage other accounts
+ALTER TABLE auth_users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT false;
+
+-- Existing passwords are considered changed when they were created
+UPDATE auth_users SET password_changed_at = created_at;
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.password_changed_at IS 'Timestamp when the password was last set';
+COMMENT ON COLUMN auth_users.password_expires IS 'Whether the password expires after PASSWORD_MAX_AGE_DAYS';
+COMMENT ON COLUMN auth_users.must_change_password IS 'Whether the user must set a new password before using the API';
+COMMENT ON COLUMN auth_users.is_admin IS 'Whether the user can access the admin API';
@@ -0,0 +1,17 @@
+-- Email verification tokens now expire
+ALTER TABLE auth_users ADD COLUMN verification_token_expires TIMESTAMPTZ;
+
+-- Store only SHA-256 hashes of verification and reset tokens
+UPDATE auth_users
+SET verification_token = encode(sha256(convert_to(verification_token, 'UTF8')), 'hex'),
+    verification_token_expires = NOW() + INTERVAL '24 hours'
+WHERE verification_token IS NOT NULL;
+
+UPDATE auth_users
+SET reset_token = encode(sha256(convert_to(reset_token, 'UTF8')), 'hex')
+WHERE reset_token IS NOT NULL;
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.verification_token IS 'SHA-256 hex digest of the email verification token';
+COMMENT ON COLUMN auth_users.verification_token_expires IS 'Expiration timestamp for email verification token';
+COMMENT ON COLUMN auth_users.reset_token IS 'SHA-256 hex digest of the password reset token';
@@ -0,0 +1,5 @@
+-- Preferred locale for transactional emails, taken from Accept-Language at registration
+ALTER TABLE auth_users ADD COLUMN locale VARCHAR(35);
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.locale IS 'Preferred locale (BCP 47 tag) for transactional emails';
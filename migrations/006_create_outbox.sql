@@ -0,0 +1,29 @@
+-- Create outbox table for notifications written in the same transaction as the user change
+CREATE TABLE outbox (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind VARCHAR(50) NOT NULL,
+    recipient VARCHAR(255) NOT NULL,
+    payload JSONB,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_outbox_pending ON outbox(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_outbox_status_created_at ON outbox(status, created_at DESC);
+
+-- Add comments for documentation
+COMMENT ON TABLE outbox IS 'Outgoing notifications delivered by the background outbox worker';
+COMMENT ON COLUMN outbox.kind IS 'Notification type, e.g. the email template name';
+COMMENT ON COLUMN outbox.recipient IS 'Address the notification is sent to';
+COMMENT ON COLUMN outbox.payload IS 'Rendered message; cleared once delivered because it may contain tokens';
+COMMENT ON COLUMN outbox.status IS 'pending, sent, or dead after exhausting all delivery attempts';
+COMMENT ON COLUMN outbox.attempts IS 'Number of delivery attempts so far';
+COMMENT ON COLUMN outbox.next_attempt_at IS 'Earliest time of the next delivery attempt';
+COMMENT ON COLUMN outbox.last_error IS 'Error returned by the last failed delivery attempt';
+COMMENT ON COLUMN outbox.sent_at IS 'Timestamp of successful delivery';
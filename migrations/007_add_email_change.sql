@@ -0,0 +1,9 @@
+-- Pending email address changes, confirmed through a token sent to the new address
+ALTER TABLE auth_users ADD COLUMN pending_email VARCHAR(255);
+ALTER TABLE auth_users ADD COLUMN email_change_token VARCHAR(255);
+ALTER TABLE auth_users ADD COLUMN email_change_token_expires TIMESTAMPTZ;
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.pending_email IS 'Requested new email address awaiting confirmation';
+COMMENT ON COLUMN auth_users.email_change_token IS 'SHA-256 hex digest of the email change confirmation token';
+COMMENT ON COLUMN auth_users.email_change_token_expires IS 'Expiration timestamp for email change token';
@@ -0,0 +1,15 @@
+-- Emails identify accounts case-insensitively.
+-- If this migration fails with a unique violation, run `rust-web-service normalize-emails`
+-- to list the accounts whose addresses differ only in case and resolve them first.
+CREATE UNIQUE INDEX idx_auth_users_email_lower ON auth_users (LOWER(email));
+
+-- Lookups use LOWER(email), which the unique index above serves
+DROP INDEX IF EXISTS idx_auth_users_email;
+
+-- Allow internationalized (Unicode) domains; the application validates the format
+ALTER TABLE auth_users DROP CONSTRAINT chk_email_format;
+ALTER TABLE auth_users ADD CONSTRAINT chk_email_format
+    CHECK (email ~ '^[^@\s]+@[^@\s]+\.[^@\s]{2,}$');
+
+-- Add comments for documentation
+COMMENT ON INDEX idx_auth_users_email_lower IS 'Case-insensitive uniqueness of email addresses';
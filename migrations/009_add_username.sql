@@ -0,0 +1,11 @@
+-- Optional username that can be used instead of the email to log in
+ALTER TABLE auth_users ADD COLUMN username VARCHAR(30);
+
+-- Usernames are unique regardless of case
+CREATE UNIQUE INDEX idx_auth_users_username_lower ON auth_users (LOWER(username));
+
+ALTER TABLE auth_users ADD CONSTRAINT chk_username_format
+    CHECK (username ~ '^[A-Za-z][A-Za-z0-9._-]{1,28}[A-Za-z0-9]$');
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.username IS 'Optional unique login handle, matched case-insensitively';
@@ -0,0 +1,9 @@
+-- Self-service profile fields; locale was added in 005 and is now user-editable
+ALTER TABLE auth_users ADD COLUMN display_name VARCHAR(100);
+ALTER TABLE auth_users ADD COLUMN timezone VARCHAR(64);
+ALTER TABLE auth_users ADD COLUMN avatar_url VARCHAR(2048);
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.display_name IS 'Name shown to other users';
+COMMENT ON COLUMN auth_users.timezone IS 'IANA timezone name, e.g. Europe/Madrid';
+COMMENT ON COLUMN auth_users.avatar_url IS 'HTTPS URL of the profile picture';
@@ -0,0 +1,9 @@
+-- Self-service account deletion: the account is deactivated and erased after a grace period
+ALTER TABLE auth_users ADD COLUMN deletion_scheduled_at TIMESTAMPTZ;
+
+-- The purge job scans for accounts whose grace period has ended
+CREATE INDEX idx_auth_users_deletion_scheduled_at ON auth_users(deletion_scheduled_at)
+    WHERE deletion_scheduled_at IS NOT NULL;
+
+-- Add comments for documentation
+COMMENT ON COLUMN auth_users.deletion_scheduled_at IS 'When a self-deleted account is erased; cleared when the user logs in again';
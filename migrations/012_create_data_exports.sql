@@ -0,0 +1,24 @@
+-- Personal data exports, generated in the background and downloaded through signed links
+CREATE TABLE data_exports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    archive JSONB,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT chk_data_export_status CHECK (status IN ('pending', 'ready', 'failed'))
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_data_exports_user_id ON data_exports(user_id, created_at DESC);
+CREATE INDEX idx_data_exports_pending ON data_exports(created_at) WHERE status = 'pending';
+CREATE INDEX idx_data_exports_expires_at ON data_exports(expires_at);
+
+-- Add comments for documentation
+COMMENT ON TABLE data_exports IS 'Personal data exports requested by users';
+COMMENT ON COLUMN data_exports.status IS 'pending, ready or failed';
+COMMENT ON COLUMN data_exports.archive IS 'Generated JSON archive, deleted with the row when it expires';
+COMMENT ON COLUMN data_exports.expires_at IS 'When the export and its download link expire';
@@ -0,0 +1,7 @@
+-- Admin user listing: newest-first cursor pagination and email prefix search
+CREATE INDEX idx_auth_users_created_at_id ON auth_users(created_at DESC, id DESC);
+CREATE INDEX idx_auth_users_email_lower_prefix ON auth_users(LOWER(email) text_pattern_ops);
+
+-- Add comments for documentation
+COMMENT ON INDEX idx_auth_users_created_at_id IS 'Keyset pagination of the admin user list';
+COMMENT ON INDEX idx_auth_users_email_lower_prefix IS 'Case-insensitive email prefix search (LIKE ''prefix%'')';
@@ -0,0 +1,16 @@
+-- Token buckets of the Postgres rate limit backend, shared by all instances
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    full_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_rate_limit_buckets_full_at ON rate_limit_buckets(full_at);
+
+-- Add comments for documentation
+COMMENT ON TABLE rate_limit_buckets IS 'Rate limit token buckets, used when RATE_LIMIT_BACKEND is postgres';
+COMMENT ON COLUMN rate_limit_buckets.key IS 'Route and client IP or hashed account the bucket limits';
+COMMENT ON COLUMN rate_limit_buckets.tokens IS 'Tokens left as of updated_at';
+COMMENT ON COLUMN rate_limit_buckets.full_at IS 'When the bucket refills completely and can be deleted';
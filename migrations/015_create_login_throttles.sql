@@ -0,0 +1,21 @@
+-- Failed logins per account and client IP, for progressive login backoff
+CREATE TABLE login_throttles (
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    ip_address TEXT NOT NULL,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    blocked_until TIMESTAMPTZ,
+    last_success_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, ip_address)
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_login_throttles_updated_at ON login_throttles(updated_at)
+    WHERE last_success_at IS NULL;
+
+-- Add comments for documentation
+COMMENT ON TABLE login_throttles IS 'Login backoff state per account and client IP';
+COMMENT ON COLUMN login_throttles.blocked_until IS 'Logins from this IP to this account are refused until then';
+COMMENT ON COLUMN login_throttles.last_success_at IS 'Last successful login from this IP; known IPs skip the account-wide backoff';
+COMMENT ON COLUMN auth_users.locked_until IS 'Logins from IPs without a previous successful login are refused until then';
@@ -0,0 +1,42 @@
+-- Sessions started by successful logins; their id is carried in the access token
+CREATE TABLE user_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    device_key TEXT NOT NULL,
+    device TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+-- Login attempts against existing accounts
+CREATE TABLE login_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    success BOOLEAN NOT NULL,
+    failure_reason VARCHAR(50),
+    method VARCHAR(50) NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    device TEXT NOT NULL,
+    session_id UUID REFERENCES user_sessions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_user_sessions_user_id ON user_sessions(user_id, last_seen_at DESC)
+    WHERE revoked_at IS NULL;
+CREATE INDEX idx_user_sessions_last_seen_at ON user_sessions(last_seen_at);
+CREATE INDEX idx_login_events_user_id ON login_events(user_id, created_at DESC, id DESC);
+CREATE INDEX idx_login_events_created_at ON login_events(created_at);
+
+-- Add comments for documentation
+COMMENT ON TABLE user_sessions IS 'Login sessions, listed to users as devices';
+COMMENT ON COLUMN user_sessions.device_key IS 'Groups the sessions of one device; hash of the user agent';
+COMMENT ON COLUMN user_sessions.device IS 'Browser and OS parsed from the user agent';
+COMMENT ON COLUMN user_sessions.revoked_at IS 'Tokens of revoked sessions are rejected';
+COMMENT ON TABLE login_events IS 'Login history shown to users and included in data exports';
+COMMENT ON COLUMN login_events.failure_reason IS 'invalid_credentials, throttled or inactive; NULL on success';
+COMMENT ON COLUMN login_events.method IS 'email_password or username_password';
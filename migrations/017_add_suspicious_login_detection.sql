@@ -0,0 +1,30 @@
+-- Outcome of the suspicious-login checks on each login attempt
+ALTER TABLE login_events ADD COLUMN location TEXT;
+ALTER TABLE login_events ADD COLUMN anomalies TEXT[] NOT NULL DEFAULT '{}';
+
+-- Suspicious logins waiting for the code emailed to the account owner
+CREATE TABLE login_challenges (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES auth_users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    method VARCHAR(50) NOT NULL,
+    device_key TEXT NOT NULL,
+    anomalies TEXT[] NOT NULL DEFAULT '{}',
+    location TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_user_sessions_device_key ON user_sessions(user_id, device_key);
+CREATE INDEX idx_login_challenges_expires_at ON login_challenges(expires_at);
+
+-- Add comments for documentation
+COMMENT ON COLUMN login_events.location IS 'City and country of the client IP, if a GeoIP database is configured';
+COMMENT ON COLUMN login_events.anomalies IS 'new_device and/or unusual_location for suspicious logins';
+COMMENT ON COLUMN user_sessions.device_key IS 'Groups the sessions of one device; hash of the device cookie and user agent';
+COMMENT ON TABLE login_challenges IS 'Email codes required to complete suspicious logins';
+COMMENT ON COLUMN login_challenges.code_hash IS 'SHA-256 of the challenge id and code';
+COMMENT ON COLUMN login_challenges.device_key IS 'Only the device that entered the password can complete the login';
@@ -0,0 +1,54 @@
+-- Append-only log of security-relevant actions; outlives the accounts it refers to
+CREATE TABLE audit_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    action VARCHAR(64) NOT NULL,
+    actor_id UUID,
+    target_id UUID,
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    request_id VARCHAR(128),
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Reject deletes, and updates other than stripping personal data when an account is erased
+CREATE OR REPLACE FUNCTION reject_audit_event_changes()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND (NEW.id, NEW.action, NEW.actor_id, NEW.target_id, NEW.request_id, NEW.created_at)
+            IS NOT DISTINCT FROM
+            (OLD.id, OLD.action, OLD.actor_id, OLD.target_id, OLD.request_id, OLD.created_at)
+        AND NEW.ip_address IS NULL
+        AND NEW.user_agent IS NULL
+        AND OLD.metadata @> NEW.metadata
+    THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW
+    EXECUTE FUNCTION reject_audit_event_changes();
+
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT
+    EXECUTE FUNCTION reject_audit_event_changes();
+
+-- Create indexes for performance
+CREATE INDEX idx_audit_events_created_at ON audit_events(created_at DESC, id DESC);
+CREATE INDEX idx_audit_events_actor_id ON audit_events(actor_id, created_at DESC, id DESC);
+CREATE INDEX idx_audit_events_target_id ON audit_events(target_id, created_at DESC, id DESC);
+CREATE INDEX idx_audit_events_action ON audit_events(action, created_at DESC, id DESC);
+
+-- Add comments for documentation
+COMMENT ON TABLE audit_events IS 'Append-only audit log of security-relevant actions';
+COMMENT ON COLUMN audit_events.action IS 'e.g. login.failed or password.changed';
+COMMENT ON COLUMN audit_events.actor_id IS 'User who acted; NULL for unauthenticated requests';
+COMMENT ON COLUMN audit_events.target_id IS 'Account acted on; no foreign key, so entries survive its deletion';
+COMMENT ON COLUMN audit_events.request_id IS 'X-Request-Id of the request, or one generated for it';
+COMMENT ON COLUMN audit_events.metadata IS 'Details of the action; email addresses are removed when the account is erased';
@@ -0,0 +1,44 @@
+-- Chain each audit entry to the previous one with an HMAC, so edits can be detected
+ALTER TABLE audit_events ADD COLUMN sequence BIGINT;
+ALTER TABLE audit_events ADD COLUMN personal_hmac TEXT;
+ALTER TABLE audit_events ADD COLUMN prev_hash TEXT;
+ALTER TABLE audit_events ADD COLUMN hash TEXT;
+
+-- Entries written before the chain existed are numbered but stay unchained; the database
+-- doesn't know the key to hash them
+ALTER TABLE audit_events DISABLE TRIGGER audit_events_append_only;
+UPDATE audit_events SET sequence = numbered.sequence
+FROM (
+    SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, id) AS sequence FROM audit_events
+) numbered
+WHERE audit_events.id = numbered.id;
+ALTER TABLE audit_events ENABLE TRIGGER audit_events_append_only;
+
+ALTER TABLE audit_events ALTER COLUMN sequence SET NOT NULL;
+ALTER TABLE audit_events ADD CONSTRAINT uq_audit_events_sequence UNIQUE (sequence);
+
+-- Same rule as before; the chain columns can't change either
+CREATE OR REPLACE FUNCTION reject_audit_event_changes()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND (NEW.id, NEW.action, NEW.actor_id, NEW.target_id, NEW.request_id, NEW.created_at,
+             NEW.sequence, NEW.personal_hmac, NEW.prev_hash, NEW.hash)
+            IS NOT DISTINCT FROM
+            (OLD.id, OLD.action, OLD.actor_id, OLD.target_id, OLD.request_id, OLD.created_at,
+             OLD.sequence, OLD.personal_hmac, OLD.prev_hash, OLD.hash)
+        AND NEW.ip_address IS NULL
+        AND NEW.user_agent IS NULL
+        AND OLD.metadata @> NEW.metadata
+    THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ language 'plpgsql';
+
+-- Add comments for documentation
+COMMENT ON COLUMN audit_events.sequence IS 'Position in the hash chain, without gaps';
+COMMENT ON COLUMN audit_events.personal_hmac IS 'HMAC of the IP, user agent and email addresses, which are erased with the account';
+COMMENT ON COLUMN audit_events.prev_hash IS 'hash of the previous entry; empty for the first chained entry';
+COMMENT ON COLUMN audit_events.hash IS 'HMAC-SHA256 over the entry and prev_hash; NULL for entries from before the chain';
@@ -0,0 +1,60 @@
+-- Create webhook subscriptions of external services to identity events
+CREATE TABLE webhook_subscriptions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    url TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    description VARCHAR(255),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create deliveries, queued in the same transaction as the event they report
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    subscription_id UUID NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_status_code INTEGER,
+    last_error TEXT,
+    replay_of UUID REFERENCES webhook_deliveries(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+-- Create the log of delivery attempts
+CREATE TABLE webhook_delivery_attempts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    delivery_id UUID NOT NULL REFERENCES webhook_deliveries(id) ON DELETE CASCADE,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER,
+    error TEXT,
+    response_body TEXT,
+    duration_ms INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_webhook_deliveries_pending ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_webhook_deliveries_subscription ON webhook_deliveries(subscription_id, created_at DESC, id DESC);
+CREATE INDEX idx_webhook_deliveries_user ON webhook_deliveries((payload->'data'->>'user_id'));
+CREATE INDEX idx_webhook_delivery_attempts_delivery ON webhook_delivery_attempts(delivery_id, attempt);
+
+-- Add comments for documentation
+COMMENT ON TABLE webhook_subscriptions IS 'Endpoints notified of identity events, managed by admins';
+COMMENT ON COLUMN webhook_subscriptions.events IS 'Event types sent to the endpoint, e.g. user.registered';
+COMMENT ON COLUMN webhook_subscriptions.secret IS 'Key of the HMAC-SHA256 payload signatures; kept in clear to sign with';
+COMMENT ON COLUMN webhook_subscriptions.is_active IS 'Inactive subscriptions get no new events and their queued deliveries wait';
+COMMENT ON TABLE webhook_deliveries IS 'Events queued for a subscription, delivered by the background webhook worker';
+COMMENT ON COLUMN webhook_deliveries.event_id IS 'Id of the event, shared by its deliveries and their replays';
+COMMENT ON COLUMN webhook_deliveries.status IS 'pending, delivered, or dead after exhausting all delivery attempts';
+COMMENT ON COLUMN webhook_deliveries.last_status_code IS 'HTTP status returned by the last attempt, NULL if no response was received';
+COMMENT ON COLUMN webhook_deliveries.replay_of IS 'Delivery this one was replayed from by an admin';
+COMMENT ON TABLE webhook_delivery_attempts IS 'Log of every attempt to deliver a webhook';
+COMMENT ON COLUMN webhook_delivery_attempts.response_body IS 'Start of the response body, for troubleshooting';
@@ -0,0 +1,19 @@
+-- Failed logins to emails and usernames without an account, backed off by the same rules as
+-- logins to existing accounts so that responses don't reveal which accounts exist
+CREATE TABLE unknown_login_throttles (
+    identifier_hash VARCHAR(64) NOT NULL,
+    ip_address TEXT NOT NULL,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    blocked_until TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (identifier_hash, ip_address)
+);
+
+-- Create indexes for performance
+CREATE INDEX idx_unknown_login_throttles_updated_at ON unknown_login_throttles(updated_at);
+
+-- Add comments for documentation
+COMMENT ON TABLE unknown_login_throttles IS 'Login backoff state of identifiers without an account, per client IP and overall';
+COMMENT ON COLUMN unknown_login_throttles.identifier_hash IS 'SHA-256 of the lowercased email or username, so the identifiers tried are not stored';
+COMMENT ON COLUMN unknown_login_throttles.ip_address IS 'Client IP, or empty for failures from any IP (the account-wide backoff)';
@@ -0,0 +1,30 @@
+use sqlx::{Pool, Postgres};
+
+pub mod normalize_emails;
+pub mod verify_audit_log;
+
+/// Run a one-off maintenance command (`rust-web-service <command> [args]`).
+///
+/// Commands run before migrations, so they also work against a database that a pending
+/// migration would reject. Returns the process exit code.
+pub async fn run(command: &str, args: &[String], db_pool: &Pool<Postgres>) -> i32 {
+    match command {
+        "normalize-emails" => {
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            normalize_emails::run(db_pool, dry_run).await
+        }
+        "verify-audit-log" => {
+            let checkpoints = args
+                .iter()
+                .position(|arg| arg == "--checkpoints")
+                .and_then(|index| args.get(index + 1));
+            verify_audit_log::run(db_pool, checkpoints.map(String::as_str)).await
+        }
+        other => {
+            eprintln!("Unknown command: {other}");
+            eprintln!("Available commands: normalize-emails [--dry-run]");
+            eprintln!("                    verify-audit-log [--checkpoints FILE]");
+            2
+        }
+    }
+}
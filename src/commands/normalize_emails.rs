@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, Pool, Postgres};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::{
+    errors::ServiceResult,
+    models::email::{email_identity_key, normalize_email},
+};
+
+#[derive(Debug, Clone, FromRow)]
+struct EmailRow {
+    id: Uuid,
+    email: String,
+    created_at: DateTime<Utc>,
+    last_login: Option<DateTime<Utc>>,
+}
+
+/// Detect accounts whose emails only differ in case or normalization, then normalize
+/// the stored addresses if there are none.
+///
+/// Duplicates must be resolved by hand (merge or delete accounts) because picking the
+/// surviving account is a product decision. Exits with 1 when duplicates are found.
+pub async fn run(db_pool: &Pool<Postgres>, dry_run: bool) -> i32 {
+    match normalize(db_pool, dry_run).await {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("normalize-emails failed: {e}");
+            2
+        }
+    }
+}
+
+// Returns the number of duplicate groups found
+async fn normalize(db_pool: &Pool<Postgres>, dry_run: bool) -> ServiceResult<usize> {
+    let rows = sqlx::query_as::<_, EmailRow>(
+        "SELECT id, email, created_at, last_login FROM auth_users ORDER BY created_at",
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    let duplicates = find_duplicates(&rows);
+    if !duplicates.is_empty() {
+        println!(
+            "Found {} email addresses shared by more than one account:",
+            duplicates.len()
+        );
+        for (key, accounts) in &duplicates {
+            println!("\n{key}");
+            for account in accounts {
+                println!(
+                    "  {}  {}  created {}  last login {}",
+                    account.id,
+                    account.email,
+                    account.created_at.to_rfc3339(),
+                    account
+                        .last_login
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_else(|| "never".to_string())
+                );
+            }
+        }
+        println!("\nResolve these accounts before running the server; no emails were changed.");
+        return Ok(duplicates.len());
+    }
+
+    let changes: Vec<(Uuid, String, String)> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let normalized = normalize_email(&row.email);
+            (normalized != row.email).then_some((row.id, row.email, normalized))
+        })
+        .collect();
+
+    for (id, email, normalized) in &changes {
+        println!("{id}: {email} -> {normalized}");
+    }
+
+    if dry_run {
+        println!("{} emails would be normalized (dry run)", changes.len());
+        return Ok(0);
+    }
+
+    let mut tx = db_pool.begin().await?;
+    for (id, _, normalized) in &changes {
+        sqlx::query("UPDATE auth_users SET email = $1, updated_at = NOW() WHERE id = $2")
+            .bind(normalized)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    println!("{} emails normalized, no duplicates found", changes.len());
+    Ok(0)
+}
+
+// Group accounts by identity key, keeping only keys used by more than one account
+fn find_duplicates(rows: &[EmailRow]) -> BTreeMap<String, Vec<EmailRow>> {
+    let mut groups: BTreeMap<String, Vec<EmailRow>> = BTreeMap::new();
+    for row in rows {
+        groups
+            .entry(email_identity_key(&row.email))
+            .or_default()
+            .push(row.clone());
+    }
+
+    groups.retain(|_, accounts| accounts.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(email: &str) -> EmailRow {
+        EmailRow {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            created_at: Utc::now(),
+            last_login: None,
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let rows = vec![
+            row("Alice@Example.com"),
+            row("alice@example.com "),
+            row("bob@example.com"),
+            row("carol@example.com"),
+        ];
+
+        let duplicates = find_duplicates(&rows);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates["alice@example.com"].len(), 2);
+    }
+}
@@ -0,0 +1,72 @@
+use sqlx::{Pool, Postgres};
+use std::path::Path;
+
+use crate::{
+    config::CONFIG,
+    services::{audit_checkpoint_worker::read_checkpoints, AuditService},
+};
+
+/// Walk the audit log's hash chain and report the first broken link, checking it against
+/// the checkpoints in `checkpoint_file` (default `AUDIT_CHECKPOINT_FILE`).
+///
+/// Exits with 1 when the chain is broken.
+pub async fn run(db_pool: &Pool<Postgres>, checkpoint_file: Option<&str>) -> i32 {
+    if let Some(path) = checkpoint_file.filter(|path| !Path::new(path).exists()) {
+        eprintln!("Checkpoint file {path} does not exist");
+        return 2;
+    }
+    let checkpoints = match checkpoint_file.or(CONFIG.audit_checkpoint_file.as_deref()) {
+        Some(path) => match read_checkpoints(Path::new(path)) {
+            Ok(checkpoints) => checkpoints,
+            Err(e) => {
+                eprintln!("Reading checkpoints from {path} failed: {e}");
+                return 2;
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let report = match AuditService::new(db_pool.clone())
+        .verify(&checkpoints)
+        .await
+    {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("verify-audit-log failed: {e}");
+            return 2;
+        }
+    };
+
+    println!(
+        "Verified {} chained entries ({} from before the chain) against {} of {} checkpoints",
+        report.entries,
+        report.unchained,
+        report.checkpoints,
+        checkpoints.len()
+    );
+    match (&report.broken_link, &report.head) {
+        (Some(link), _) => {
+            let reason = serde_json::to_value(link.reason).unwrap_or_default();
+            println!(
+                "Broken link at sequence {} (entry {}): {}",
+                link.sequence,
+                link.id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| "missing".to_string()),
+                reason.as_str().unwrap_or_default()
+            );
+            1
+        }
+        (None, Some(head)) => {
+            println!(
+                "Chain intact up to sequence {}, hash {}",
+                head.sequence, head.hash
+            );
+            0
+        }
+        (None, None) => {
+            println!("Chain is empty");
+            0
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use ipnet::IpNet;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
-use std::env;
+use std::{collections::HashMap, env, net::IpAddr};
+
+use crate::rate_limit::RateLimitRule;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -10,11 +13,80 @@ pub struct Config {
     pub server_host: String,
     pub server_port: u16,
     pub bcrypt_cost: u32,
+    pub password_peppers: HashMap<u32, String>, // pepper version -> secret
+    pub password_pepper_version: u32,           // 0 = hash without pepper
+    pub password_min_score: u8,                 // zxcvbn score, 0-4
+    pub password_history_size: i64,
+    pub password_max_age_days: i64, // applies to accounts flagged with password_expires
+    pub password_change_token_expiration: i64, // in seconds
+    pub verification_token_expiration: i64, // in seconds
+    pub email_change_token_expiration: i64, // in seconds
+    pub email_idn_punycode: bool,   // store internationalized email domains as punycode
+    pub mailer_backend: String,     // log, file or smtp
+    pub mail_from: String,
+    pub mail_dir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: String,        // starttls, tls or none
+    pub public_base_url: String, // used to build links in emails
+    pub app_name: String,
+    pub email_template_dir: String,
+    pub email_default_locale: String,
+    pub login_notifications: bool, // email users after each successful login
+    pub outbox_poll_interval: u64, // in seconds
+    pub outbox_batch_size: i64,
+    pub outbox_max_attempts: i32, // deliveries are dead-lettered after this many attempts
+    pub outbox_retry_base: i64,   // first retry delay in seconds, doubled per attempt
+    pub outbox_retry_max: i64,    // in seconds
+    pub outbox_dead_letter_retention_days: i32, // dead-lettered messages are deleted after this
+    pub account_deletion_grace_days: i64, // deactivated accounts are erased after this many days
+    pub account_purge_interval: u64, // in seconds
+    pub api_base_url: String,     // public URL of this API, used for signed download links
+    pub data_export_expiration: i64, // in seconds; archives and their links expire together
+    pub data_export_poll_interval: u64, // in seconds
+    pub data_export_signing_key: String, // keys download link signatures, apart from JWT_SECRET
+    pub rate_limit_backend: String, // memory, postgres or none
+    pub rate_limit_login_ip: RateLimitRule,
+    pub rate_limit_login_ip_account: RateLimitRule, // per client IP and account
+    pub rate_limit_register_ip: RateLimitRule,
+    pub rate_limit_register_account: RateLimitRule,
+    pub rate_limit_password_reset_ip: RateLimitRule,
+    pub rate_limit_password_reset_account: RateLimitRule,
+    pub rate_limit_resend_verification_ip: RateLimitRule,
+    pub rate_limit_resend_verification_account: RateLimitRule,
+    pub rate_limit_token_ip: RateLimitRule, // email verification and password reset confirmation
+    pub trusted_proxies: Vec<IpNet>,        // proxies whose X-Forwarded-For is believed
+    pub login_backoff_base: i64,            // in seconds, doubled per further failed login
+    pub login_backoff_account_threshold: i32, // failures from any IP before backoff
+    pub login_backoff_account_max: i64,     // in seconds
+    pub login_backoff_ip_threshold: i32,    // failures from one IP before backoff
+    pub login_backoff_ip_max: i64,          // in seconds
+    pub conceal_registered_emails: bool, // register answers alike for taken emails, emailing the owner
+    pub login_history_retention_days: i32, // login events and ended sessions are kept this long
+    pub geoip_database: Option<String>,  // MaxMind-format database for locating client IPs
+    pub suspicious_login_distance_km: f64, // logins this far from all previous ones are unusual
+    pub suspicious_login_otp: bool,      // suspicious logins must be completed with an emailed code
+    pub login_challenge_expiration: i64, // in seconds
+    pub audit_hmac_key: String,          // keys the audit log hash chain; defaults to JWT_SECRET
+    pub audit_checkpoint_file: Option<String>, // signed audit checkpoints are appended here
+    pub audit_checkpoint_interval: u64,  // in seconds
+    pub webhook_poll_interval: u64,      // in seconds
+    pub webhook_timeout: u64,            // seconds to wait for a webhook receiver
+    pub webhook_batch_size: i64,         // deliveries claimed and sent concurrently per run
+    pub webhook_max_attempts: i32,       // deliveries are dead-lettered after this many attempts
+    pub webhook_retry_base: i64,         // first retry delay in seconds, doubled per attempt
+    pub webhook_retry_max: i64,          // in seconds
+    pub webhook_delivery_retention_days: i32, // finished deliveries are kept this long
     pub log_level: String,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, env::VarError> {
+        let password_peppers = parse_peppers(&env::var("PASSWORD_PEPPERS").unwrap_or_default());
+        let latest_pepper_version = password_peppers.keys().copied().max().unwrap_or(0);
+
         Ok(Config {
             database_url: env::var("DATABASE_URL")?,
             jwt_secret: env::var("JWT_SECRET")?,
@@ -31,6 +103,230 @@ impl Config {
                 .unwrap_or_else(|_| "12".to_string())
                 .parse()
                 .unwrap_or(12),
+            password_pepper_version: env::var("PASSWORD_PEPPER_VERSION")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(latest_pepper_version),
+            password_peppers,
+            password_min_score: env::var("PASSWORD_MIN_SCORE")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            password_history_size: env::var("PASSWORD_HISTORY_SIZE")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            password_max_age_days: env::var("PASSWORD_MAX_AGE_DAYS")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .unwrap_or(90),
+            password_change_token_expiration: env::var("PASSWORD_CHANGE_TOKEN_EXPIRATION")
+                .unwrap_or_else(|_| "900".to_string()) // 15 minutes default
+                .parse()
+                .unwrap_or(900),
+            verification_token_expiration: env::var("VERIFICATION_TOKEN_EXPIRATION")
+                .unwrap_or_else(|_| "86400".to_string()) // 24 hours default
+                .parse()
+                .unwrap_or(86400),
+            email_change_token_expiration: env::var("EMAIL_CHANGE_TOKEN_EXPIRATION")
+                .unwrap_or_else(|_| "86400".to_string()) // 24 hours default
+                .parse()
+                .unwrap_or(86400),
+            email_idn_punycode: env::var("EMAIL_IDN_PUNYCODE")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            mailer_backend: env::var("MAILER_BACKEND").unwrap_or_else(|_| "file".to_string()),
+            mail_from: env::var("FROM_EMAIL").unwrap_or_else(|_| "noreply@localhost".to_string()),
+            mail_dir: env::var("MAIL_DIR").unwrap_or_else(|_| "./mail".to_string()),
+            smtp_host: env::var("SMTP_HOST").unwrap_or_default(),
+            smtp_port: env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .unwrap_or(587),
+            smtp_username: env::var("SMTP_USERNAME").ok(),
+            smtp_password: env::var("SMTP_PASSWORD").ok(),
+            smtp_tls: env::var("SMTP_TLS").unwrap_or_else(|_| "starttls".to_string()),
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            app_name: env::var("APP_NAME").unwrap_or_else(|_| "Rust Web Service".to_string()),
+            email_template_dir: env::var("EMAIL_TEMPLATE_DIR")
+                .unwrap_or_else(|_| "./templates/emails".to_string()),
+            email_default_locale: env::var("EMAIL_DEFAULT_LOCALE")
+                .unwrap_or_else(|_| "en".to_string()),
+            login_notifications: env::var("LOGIN_NOTIFICATIONS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            outbox_poll_interval: env::var("OUTBOX_POLL_INTERVAL")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            outbox_batch_size: env::var("OUTBOX_BATCH_SIZE")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .unwrap_or(20),
+            outbox_max_attempts: env::var("OUTBOX_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .unwrap_or(8),
+            outbox_retry_base: env::var("OUTBOX_RETRY_BASE")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            outbox_retry_max: env::var("OUTBOX_RETRY_MAX")
+                .unwrap_or_else(|_| "3600".to_string()) // 1 hour default
+                .parse()
+                .unwrap_or(3600),
+            outbox_dead_letter_retention_days: env::var("OUTBOX_DEAD_LETTER_RETENTION_DAYS")
+                .unwrap_or_else(|_| "7".to_string())
+                .parse()
+                .unwrap_or(7),
+            account_deletion_grace_days: env::var("ACCOUNT_DELETION_GRACE_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            account_purge_interval: env::var("ACCOUNT_PURGE_INTERVAL")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .unwrap_or(3600),
+            api_base_url: env::var("API_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:8080".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            data_export_expiration: env::var("DATA_EXPORT_EXPIRATION")
+                .unwrap_or_else(|_| "86400".to_string()) // 24 hours default
+                .parse()
+                .unwrap_or(86400),
+            data_export_poll_interval: env::var("DATA_EXPORT_POLL_INTERVAL")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            data_export_signing_key: env::var("DATA_EXPORT_SIGNING_KEY")?,
+            rate_limit_backend: env::var("RATE_LIMIT_BACKEND")
+                .unwrap_or_else(|_| "memory".to_string()),
+            rate_limit_login_ip: env::var("RATE_LIMIT_LOGIN_IP")
+                .unwrap_or_else(|_| "20/60".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(20, 60)),
+            rate_limit_login_ip_account: env::var("RATE_LIMIT_LOGIN_IP_ACCOUNT")
+                .unwrap_or_else(|_| "10/300".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(10, 300)),
+            rate_limit_register_ip: env::var("RATE_LIMIT_REGISTER_IP")
+                .unwrap_or_else(|_| "10/3600".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(10, 3600)),
+            rate_limit_register_account: env::var("RATE_LIMIT_REGISTER_ACCOUNT")
+                .unwrap_or_else(|_| "3/3600".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(3, 3600)),
+            rate_limit_password_reset_ip: env::var("RATE_LIMIT_PASSWORD_RESET_IP")
+                .unwrap_or_else(|_| "10/900".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(10, 900)),
+            rate_limit_password_reset_account: env::var("RATE_LIMIT_PASSWORD_RESET_ACCOUNT")
+                .unwrap_or_else(|_| "3/900".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(3, 900)),
+            rate_limit_resend_verification_ip: env::var("RATE_LIMIT_RESEND_VERIFICATION_IP")
+                .unwrap_or_else(|_| "10/3600".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(10, 3600)),
+            rate_limit_resend_verification_account: env::var(
+                "RATE_LIMIT_RESEND_VERIFICATION_ACCOUNT",
+            )
+            .unwrap_or_else(|_| "3/3600".to_string())
+            .parse()
+            .unwrap_or(RateLimitRule::new(3, 3600)),
+            rate_limit_token_ip: env::var("RATE_LIMIT_TOKEN_IP")
+                .unwrap_or_else(|_| "20/900".to_string())
+                .parse()
+                .unwrap_or(RateLimitRule::new(20, 900)),
+            trusted_proxies: parse_trusted_proxies(
+                &env::var("TRUSTED_PROXIES").unwrap_or_default(),
+            ),
+            login_backoff_base: env::var("LOGIN_BACKOFF_BASE")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .unwrap_or(1),
+            login_backoff_account_threshold: env::var("LOGIN_BACKOFF_ACCOUNT_THRESHOLD")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            login_backoff_account_max: env::var("LOGIN_BACKOFF_ACCOUNT_MAX")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .unwrap_or(3600),
+            login_backoff_ip_threshold: env::var("LOGIN_BACKOFF_IP_THRESHOLD")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            login_backoff_ip_max: env::var("LOGIN_BACKOFF_IP_MAX")
+                .unwrap_or_else(|_| "900".to_string())
+                .parse()
+                .unwrap_or(900),
+            conceal_registered_emails: env::var("CONCEAL_REGISTERED_EMAILS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            login_history_retention_days: env::var("LOGIN_HISTORY_RETENTION_DAYS")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .unwrap_or(90),
+            geoip_database: env::var("GEOIP_DATABASE")
+                .ok()
+                .filter(|path| !path.trim().is_empty()),
+            suspicious_login_distance_km: env::var("SUSPICIOUS_LOGIN_DISTANCE_KM")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .unwrap_or(500.0),
+            suspicious_login_otp: env::var("SUSPICIOUS_LOGIN_OTP")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            login_challenge_expiration: env::var("LOGIN_CHALLENGE_EXPIRATION")
+                .unwrap_or_else(|_| "600".to_string()) // 10 minutes default
+                .parse()
+                .unwrap_or(600),
+            audit_hmac_key: env::var("AUDIT_HMAC_KEY").or_else(|_| env::var("JWT_SECRET"))?,
+            audit_checkpoint_file: env::var("AUDIT_CHECKPOINT_FILE")
+                .ok()
+                .filter(|path| !path.trim().is_empty()),
+            audit_checkpoint_interval: env::var("AUDIT_CHECKPOINT_INTERVAL")
+                .unwrap_or_else(|_| "3600".to_string()) // 1 hour default
+                .parse()
+                .unwrap_or(3600),
+            webhook_poll_interval: env::var("WEBHOOK_POLL_INTERVAL")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .unwrap_or(5),
+            webhook_timeout: env::var("WEBHOOK_TIMEOUT")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            webhook_batch_size: env::var("WEBHOOK_BATCH_SIZE")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .unwrap_or(20),
+            webhook_max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .unwrap_or(8),
+            webhook_retry_base: env::var("WEBHOOK_RETRY_BASE")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            webhook_retry_max: env::var("WEBHOOK_RETRY_MAX")
+                .unwrap_or_else(|_| "3600".to_string()) // 1 hour default
+                .parse()
+                .unwrap_or(3600),
+            webhook_delivery_retention_days: env::var("WEBHOOK_DELIVERY_RETENTION_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
             log_level: env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
         })
     }
@@ -44,6 +340,31 @@ impl Config {
     }
 }
 
+// Parse "version:secret" pairs, e.g. "1:old_secret,2:new_secret"
+fn parse_peppers(value: &str) -> HashMap<u32, String> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (version, secret) = entry.trim().split_once(':')?;
+            Some((version.trim().parse().ok()?, secret.trim().to_string()))
+        })
+        .collect()
+}
+
+// Parse a list of networks and addresses, e.g. "10.0.0.0/8, 192.168.1.10"
+fn parse_trusted_proxies(value: &str) -> Vec<IpNet> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            entry
+                .parse()
+                .ok()
+                .or_else(|| entry.parse::<IpAddr>().ok().map(IpNet::from))
+        })
+        .collect()
+}
+
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
     dotenv::dotenv().ok();
     Config::from_env().expect("Failed to load configuration from environment variables")
@@ -68,6 +389,184 @@ impl Config {
             return Err("BCRYPT_COST must be between 4 and 31".to_string());
         }
 
+        if self.password_pepper_version != 0
+            && !self
+                .password_peppers
+                .contains_key(&self.password_pepper_version)
+        {
+            return Err(
+                "PASSWORD_PEPPER_VERSION must refer to an entry in PASSWORD_PEPPERS".to_string(),
+            );
+        }
+
+        if self
+            .password_peppers
+            .values()
+            .any(|secret| secret.len() < 32)
+        {
+            return Err("PASSWORD_PEPPERS secrets must be at least 32 characters long".to_string());
+        }
+
+        if self.password_min_score > 4 {
+            return Err("PASSWORD_MIN_SCORE must be between 0 and 4".to_string());
+        }
+
+        if self.password_history_size < 0 {
+            return Err("PASSWORD_HISTORY_SIZE cannot be negative".to_string());
+        }
+
+        if self.password_max_age_days < 0 {
+            return Err("PASSWORD_MAX_AGE_DAYS cannot be negative".to_string());
+        }
+
+        if self.password_change_token_expiration <= 0 {
+            return Err("PASSWORD_CHANGE_TOKEN_EXPIRATION must be positive".to_string());
+        }
+
+        if self.verification_token_expiration <= 0 {
+            return Err("VERIFICATION_TOKEN_EXPIRATION must be positive".to_string());
+        }
+
+        if self.email_change_token_expiration <= 0 {
+            return Err("EMAIL_CHANGE_TOKEN_EXPIRATION must be positive".to_string());
+        }
+
+        if !["log", "file", "smtp"].contains(&self.mailer_backend.as_str()) {
+            return Err("MAILER_BACKEND must be one of log, file or smtp".to_string());
+        }
+
+        if self.mailer_backend == "smtp" && self.smtp_host.is_empty() {
+            return Err("SMTP_HOST is required when MAILER_BACKEND is smtp".to_string());
+        }
+
+        if !["starttls", "tls", "none"].contains(&self.smtp_tls.as_str()) {
+            return Err("SMTP_TLS must be one of starttls, tls or none".to_string());
+        }
+
+        if !self.public_base_url.starts_with("http://")
+            && !self.public_base_url.starts_with("https://")
+        {
+            return Err("PUBLIC_BASE_URL must start with http:// or https://".to_string());
+        }
+
+        if self.email_default_locale.is_empty() {
+            return Err("EMAIL_DEFAULT_LOCALE cannot be empty".to_string());
+        }
+
+        if self.outbox_poll_interval == 0 {
+            return Err("OUTBOX_POLL_INTERVAL must be positive".to_string());
+        }
+
+        if self.outbox_batch_size <= 0 {
+            return Err("OUTBOX_BATCH_SIZE must be positive".to_string());
+        }
+
+        if self.outbox_max_attempts <= 0 {
+            return Err("OUTBOX_MAX_ATTEMPTS must be positive".to_string());
+        }
+
+        if self.outbox_retry_base <= 0 || self.outbox_retry_max < self.outbox_retry_base {
+            return Err(
+                "OUTBOX_RETRY_BASE must be positive and not exceed OUTBOX_RETRY_MAX".to_string(),
+            );
+        }
+
+        if self.outbox_dead_letter_retention_days <= 0 {
+            return Err("OUTBOX_DEAD_LETTER_RETENTION_DAYS must be positive".to_string());
+        }
+
+        if self.account_deletion_grace_days < 0 {
+            return Err("ACCOUNT_DELETION_GRACE_DAYS cannot be negative".to_string());
+        }
+
+        if self.account_purge_interval == 0 {
+            return Err("ACCOUNT_PURGE_INTERVAL must be positive".to_string());
+        }
+
+        if !self.api_base_url.starts_with("http://") && !self.api_base_url.starts_with("https://") {
+            return Err("API_BASE_URL must start with http:// or https://".to_string());
+        }
+
+        if self.data_export_expiration <= 0 {
+            return Err("DATA_EXPORT_EXPIRATION must be positive".to_string());
+        }
+
+        if self.data_export_poll_interval == 0 {
+            return Err("DATA_EXPORT_POLL_INTERVAL must be positive".to_string());
+        }
+
+        if self.data_export_signing_key.len() < 32 {
+            return Err("DATA_EXPORT_SIGNING_KEY must be at least 32 characters long".to_string());
+        }
+
+        if self.data_export_signing_key == self.jwt_secret {
+            return Err("DATA_EXPORT_SIGNING_KEY must differ from JWT_SECRET".to_string());
+        }
+
+        if !["memory", "postgres", "none"].contains(&self.rate_limit_backend.as_str()) {
+            return Err("RATE_LIMIT_BACKEND must be one of memory, postgres or none".to_string());
+        }
+
+        if self.login_backoff_account_threshold <= 0 || self.login_backoff_ip_threshold <= 0 {
+            return Err("LOGIN_BACKOFF_*_THRESHOLD must be positive".to_string());
+        }
+
+        if self.login_backoff_base <= 0
+            || self.login_backoff_account_max < self.login_backoff_base
+            || self.login_backoff_ip_max < self.login_backoff_base
+        {
+            return Err(
+                "LOGIN_BACKOFF_BASE must be positive and not exceed LOGIN_BACKOFF_*_MAX"
+                    .to_string(),
+            );
+        }
+
+        if self.login_history_retention_days <= 0 {
+            return Err("LOGIN_HISTORY_RETENTION_DAYS must be positive".to_string());
+        }
+
+        if self.suspicious_login_distance_km <= 0.0 {
+            return Err("SUSPICIOUS_LOGIN_DISTANCE_KM must be positive".to_string());
+        }
+
+        if self.login_challenge_expiration <= 0 {
+            return Err("LOGIN_CHALLENGE_EXPIRATION must be positive".to_string());
+        }
+
+        if self.audit_hmac_key.len() < 32 {
+            return Err("AUDIT_HMAC_KEY must be at least 32 characters long".to_string());
+        }
+
+        if self.audit_checkpoint_interval == 0 {
+            return Err("AUDIT_CHECKPOINT_INTERVAL must be positive".to_string());
+        }
+
+        if self.webhook_poll_interval == 0 {
+            return Err("WEBHOOK_POLL_INTERVAL must be positive".to_string());
+        }
+
+        if self.webhook_timeout == 0 {
+            return Err("WEBHOOK_TIMEOUT must be positive".to_string());
+        }
+
+        if self.webhook_batch_size <= 0 {
+            return Err("WEBHOOK_BATCH_SIZE must be positive".to_string());
+        }
+
+        if self.webhook_max_attempts <= 0 {
+            return Err("WEBHOOK_MAX_ATTEMPTS must be positive".to_string());
+        }
+
+        if self.webhook_retry_base <= 0 || self.webhook_retry_max < self.webhook_retry_base {
+            return Err(
+                "WEBHOOK_RETRY_BASE must be positive and not exceed WEBHOOK_RETRY_MAX".to_string(),
+            );
+        }
+
+        if self.webhook_delivery_retention_days <= 0 {
+            return Err("WEBHOOK_DELIVERY_RETENTION_DAYS must be positive".to_string());
+        }
+
         Ok(())
     }
 }
@@ -85,11 +584,84 @@ mod tests {
             server_host: "127.0.0.1".to_string(),
             server_port: 8080,
             bcrypt_cost: 12,
+            password_peppers: HashMap::new(),
+            password_pepper_version: 0,
+            password_min_score: 3,
+            password_history_size: 5,
+            password_max_age_days: 90,
+            password_change_token_expiration: 900,
+            verification_token_expiration: 86400,
+            email_change_token_expiration: 86400,
+            email_idn_punycode: false,
+            mailer_backend: "log".to_string(),
+            mail_from: "noreply@example.com".to_string(),
+            mail_dir: "./mail".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: "starttls".to_string(),
+            public_base_url: "https://app.example.com".to_string(),
+            app_name: "Test App".to_string(),
+            email_template_dir: "./templates/emails".to_string(),
+            email_default_locale: "en".to_string(),
+            login_notifications: false,
+            outbox_poll_interval: 5,
+            outbox_batch_size: 20,
+            outbox_max_attempts: 8,
+            outbox_retry_base: 30,
+            outbox_retry_max: 3600,
+            outbox_dead_letter_retention_days: 7,
+            account_deletion_grace_days: 30,
+            account_purge_interval: 3600,
+            api_base_url: "http://localhost:8080".to_string(),
+            data_export_expiration: 86400,
+            data_export_poll_interval: 10,
+            data_export_signing_key: "this_is_a_very_long_secret_key_for_export_links".to_string(),
+            rate_limit_backend: "memory".to_string(),
+            rate_limit_login_ip: RateLimitRule::new(20, 60),
+            rate_limit_login_ip_account: RateLimitRule::new(10, 300),
+            rate_limit_register_ip: RateLimitRule::new(10, 3600),
+            rate_limit_register_account: RateLimitRule::new(3, 3600),
+            rate_limit_password_reset_ip: RateLimitRule::new(10, 900),
+            rate_limit_password_reset_account: RateLimitRule::new(3, 900),
+            rate_limit_resend_verification_ip: RateLimitRule::new(10, 3600),
+            rate_limit_resend_verification_account: RateLimitRule::new(3, 3600),
+            rate_limit_token_ip: RateLimitRule::new(20, 900),
+            trusted_proxies: Vec::new(),
+            login_backoff_base: 1,
+            login_backoff_account_threshold: 10,
+            login_backoff_account_max: 3600,
+            login_backoff_ip_threshold: 3,
+            login_backoff_ip_max: 900,
+            conceal_registered_emails: false,
+            login_history_retention_days: 90,
+            geoip_database: None,
+            suspicious_login_distance_km: 500.0,
+            suspicious_login_otp: false,
+            login_challenge_expiration: 600,
+            audit_hmac_key: "this_is_a_very_long_secret_key_for_audit_chain".to_string(),
+            audit_checkpoint_file: None,
+            audit_checkpoint_interval: 3600,
+            webhook_poll_interval: 5,
+            webhook_timeout: 10,
+            webhook_batch_size: 20,
+            webhook_max_attempts: 8,
+            webhook_retry_base: 30,
+            webhook_retry_max: 3600,
+            webhook_delivery_retention_days: 30,
             log_level: "info".to_string(),
         };
 
         assert!(valid_config.validate().is_ok());
 
+        // Export links must not be signed with the JWT secret
+        let shared_key_config = Config {
+            data_export_signing_key: valid_config.jwt_secret.clone(),
+            ..valid_config
+        };
+        assert!(shared_key_config.validate().is_err());
+
         let invalid_config = Config {
             database_url: "".to_string(),
             jwt_secret: "short".to_string(),
@@ -97,12 +669,99 @@ mod tests {
             server_host: "127.0.0.1".to_string(),
             server_port: 8080,
             bcrypt_cost: 2,
+            password_peppers: HashMap::from([(1, "short".to_string())]),
+            password_pepper_version: 2,
+            password_min_score: 5,
+            password_history_size: -1,
+            password_max_age_days: -1,
+            password_change_token_expiration: 0,
+            verification_token_expiration: 0,
+            email_change_token_expiration: 0,
+            email_idn_punycode: false,
+            mailer_backend: "log".to_string(),
+            mail_from: "noreply@example.com".to_string(),
+            mail_dir: "./mail".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: "starttls".to_string(),
+            public_base_url: "app.example.com".to_string(),
+            app_name: "Test App".to_string(),
+            email_template_dir: "./templates/emails".to_string(),
+            email_default_locale: "en".to_string(),
+            login_notifications: false,
+            outbox_poll_interval: 0,
+            outbox_batch_size: 20,
+            outbox_max_attempts: 8,
+            outbox_retry_base: 30,
+            outbox_retry_max: 3600,
+            outbox_dead_letter_retention_days: 7,
+            account_deletion_grace_days: -1,
+            account_purge_interval: 0,
+            api_base_url: "http://localhost:8080".to_string(),
+            data_export_expiration: 86400,
+            data_export_poll_interval: 10,
+            data_export_signing_key: "this_is_a_very_long_secret_key_for_export_links".to_string(),
+            rate_limit_backend: "redis".to_string(),
+            rate_limit_login_ip: RateLimitRule::new(20, 60),
+            rate_limit_login_ip_account: RateLimitRule::new(10, 300),
+            rate_limit_register_ip: RateLimitRule::new(10, 3600),
+            rate_limit_register_account: RateLimitRule::new(3, 3600),
+            rate_limit_password_reset_ip: RateLimitRule::new(10, 900),
+            rate_limit_password_reset_account: RateLimitRule::new(3, 900),
+            rate_limit_resend_verification_ip: RateLimitRule::new(10, 3600),
+            rate_limit_resend_verification_account: RateLimitRule::new(3, 3600),
+            rate_limit_token_ip: RateLimitRule::new(20, 900),
+            trusted_proxies: Vec::new(),
+            login_backoff_base: 1,
+            login_backoff_account_threshold: 10,
+            login_backoff_account_max: 3600,
+            login_backoff_ip_threshold: 0,
+            login_backoff_ip_max: 900,
+            conceal_registered_emails: false,
+            login_history_retention_days: 0,
+            geoip_database: None,
+            suspicious_login_distance_km: 0.0,
+            suspicious_login_otp: false,
+            login_challenge_expiration: 600,
+            audit_hmac_key: "this_is_a_very_long_secret_key_for_audit_chain".to_string(),
+            audit_checkpoint_file: None,
+            audit_checkpoint_interval: 3600,
+            webhook_poll_interval: 5,
+            webhook_timeout: 10,
+            webhook_batch_size: 20,
+            webhook_max_attempts: 8,
+            webhook_retry_base: 30,
+            webhook_retry_max: 3600,
+            webhook_delivery_retention_days: 30,
             log_level: "info".to_string(),
         };
 
         assert!(invalid_config.validate().is_err());
     }
 
+    #[test]
+    fn test_parse_peppers() {
+        let peppers = parse_peppers("1:first_secret, 2:second:secret,bad,x:y");
+
+        assert_eq!(peppers.len(), 2);
+        assert_eq!(peppers[&1], "first_secret");
+        assert_eq!(peppers[&2], "second:secret");
+        assert!(parse_peppers("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        let proxies = parse_trusted_proxies("10.0.0.0/8, 192.168.1.10,bogus, ::1");
+
+        assert_eq!(proxies.len(), 3);
+        assert!(proxies[0].contains(&"10.1.2.3".parse::<IpAddr>().unwrap()));
+        assert_eq!(proxies[1].to_string(), "192.168.1.10/32");
+        assert_eq!(proxies[2].to_string(), "::1/128");
+        assert!(parse_trusted_proxies("").is_empty());
+    }
+
     #[test]
     fn test_server_address() {
         let config = Config {
@@ -112,6 +771,72 @@ mod tests {
             server_host: "0.0.0.0".to_string(),
             server_port: 3000,
             bcrypt_cost: 12,
+            password_peppers: HashMap::new(),
+            password_pepper_version: 0,
+            password_min_score: 3,
+            password_history_size: 5,
+            password_max_age_days: 90,
+            password_change_token_expiration: 900,
+            verification_token_expiration: 86400,
+            email_change_token_expiration: 86400,
+            email_idn_punycode: false,
+            mailer_backend: "log".to_string(),
+            mail_from: "noreply@example.com".to_string(),
+            mail_dir: "./mail".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: "starttls".to_string(),
+            public_base_url: "https://app.example.com".to_string(),
+            app_name: "Test App".to_string(),
+            email_template_dir: "./templates/emails".to_string(),
+            email_default_locale: "en".to_string(),
+            login_notifications: false,
+            outbox_poll_interval: 5,
+            outbox_batch_size: 20,
+            outbox_max_attempts: 8,
+            outbox_retry_base: 30,
+            outbox_retry_max: 3600,
+            outbox_dead_letter_retention_days: 7,
+            account_deletion_grace_days: 30,
+            account_purge_interval: 3600,
+            api_base_url: "http://localhost:8080".to_string(),
+            data_export_expiration: 86400,
+            data_export_poll_interval: 10,
+            data_export_signing_key: "this_is_a_very_long_secret_key_for_export_links".to_string(),
+            rate_limit_backend: "memory".to_string(),
+            rate_limit_login_ip: RateLimitRule::new(20, 60),
+            rate_limit_login_ip_account: RateLimitRule::new(10, 300),
+            rate_limit_register_ip: RateLimitRule::new(10, 3600),
+            rate_limit_register_account: RateLimitRule::new(3, 3600),
+            rate_limit_password_reset_ip: RateLimitRule::new(10, 900),
+            rate_limit_password_reset_account: RateLimitRule::new(3, 900),
+            rate_limit_resend_verification_ip: RateLimitRule::new(10, 3600),
+            rate_limit_resend_verification_account: RateLimitRule::new(3, 3600),
+            rate_limit_token_ip: RateLimitRule::new(20, 900),
+            trusted_proxies: Vec::new(),
+            login_backoff_base: 1,
+            login_backoff_account_threshold: 10,
+            login_backoff_account_max: 3600,
+            login_backoff_ip_threshold: 3,
+            login_backoff_ip_max: 900,
+            conceal_registered_emails: false,
+            login_history_retention_days: 90,
+            geoip_database: None,
+            suspicious_login_distance_km: 500.0,
+            suspicious_login_otp: false,
+            login_challenge_expiration: 600,
+            audit_hmac_key: "this_is_a_very_long_secret_key_for_audit_chain".to_string(),
+            audit_checkpoint_file: None,
+            audit_checkpoint_interval: 3600,
+            webhook_poll_interval: 5,
+            webhook_timeout: 10,
+            webhook_batch_size: 20,
+            webhook_max_attempts: 8,
+            webhook_retry_base: 30,
+            webhook_retry_max: 3600,
+            webhook_delivery_retention_days: 30,
             log_level: "info".to_string(),
         };
 
@@ -1,4 +1,4 @@
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{http::header, HttpResponse, ResponseError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,6 +22,9 @@ pub enum ServiceError {
     #[error("User already exists")]
     UserAlreadyExists,
 
+    #[error("Username is already taken")]
+    UsernameTaken,
+
     #[error("Invalid credentials")]
     InvalidCredentials,
 
@@ -37,11 +40,20 @@ pub enum ServiceError {
     #[error("Internal server error")]
     InternalError,
 
+    #[error("Password was used recently and cannot be reused")]
+    PasswordReused,
+
     #[error("Password hashing error")]
     PasswordHashError,
 
+    #[error("Email delivery error: {0}")]
+    MailerError(String),
+
     #[error("JWT error: {0}")]
     JwtError(String),
+
+    #[error("Too many requests, retry in {0} seconds")]
+    RateLimited(u64), // seconds until the next attempt is allowed
 }
 
 impl ResponseError for ServiceError {
@@ -67,10 +79,18 @@ impl ResponseError for ServiceError {
                 error: "validation_error".to_string(),
                 message: self.to_string(),
             }),
+            ServiceError::PasswordReused => HttpResponse::BadRequest().json(ErrorResponse {
+                error: "password_reused".to_string(),
+                message: self.to_string(),
+            }),
             ServiceError::UserAlreadyExists => HttpResponse::Conflict().json(ErrorResponse {
                 error: "user_already_exists".to_string(),
                 message: self.to_string(),
             }),
+            ServiceError::UsernameTaken => HttpResponse::Conflict().json(ErrorResponse {
+                error: "username_taken".to_string(),
+                message: self.to_string(),
+            }),
             ServiceError::InvalidCredentials => HttpResponse::Unauthorized().json(ErrorResponse {
                 error: "invalid_credentials".to_string(),
                 message: self.to_string(),
@@ -101,10 +121,22 @@ impl ResponseError for ServiceError {
                     message: "Password processing failed".to_string(),
                 })
             }
+            ServiceError::MailerError(_) => {
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "email_error".to_string(),
+                    message: "Email delivery failed".to_string(),
+                })
+            }
             ServiceError::JwtError(_) => HttpResponse::InternalServerError().json(ErrorResponse {
                 error: "jwt_error".to_string(),
                 message: "Token processing failed".to_string(),
             }),
+            ServiceError::RateLimited(retry_after) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .json(ErrorResponse {
+                    error: "rate_limited".to_string(),
+                    message: self.to_string(),
+                }),
         }
     }
 }
@@ -121,6 +153,9 @@ impl From<sqlx::Error> for ServiceError {
         match error {
             sqlx::Error::RowNotFound => ServiceError::NotFound,
             sqlx::Error::Database(db_err) => {
+                if db_err.constraint() == Some("idx_auth_users_username_lower") {
+                    return ServiceError::UsernameTaken;
+                }
                 if let Some(code) = db_err.code() {
                     match code.as_ref() {
                         "23505" => ServiceError::UserAlreadyExists, // PostgreSQL unique violation
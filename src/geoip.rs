@@ -0,0 +1,109 @@
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::{net::IpAddr, path::Path};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Where an IP address is, as far as the database knows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Great-circle distance in kilometres (haversine formula)
+    pub fn distance_km(&self, other: &Location) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+
+    /// Human-readable place, e.g. "Berlin, Germany"
+    pub fn describe(&self) -> String {
+        match (&self.city, &self.country) {
+            (Some(city), Some(country)) => format!("{city}, {country}"),
+            (Some(place), None) | (None, Some(place)) => place.clone(),
+            (None, None) => format!("{:.2}, {:.2}", self.latitude, self.longitude),
+        }
+    }
+}
+
+/// IP geolocation from a local database in MaxMind's format, such as GeoLite2 City
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            reader: Reader::open_readfile(path)?,
+        })
+    }
+
+    /// Location of an address; None for addresses the database has no coordinates for
+    pub fn locate(&self, ip: IpAddr) -> Option<Location> {
+        let record: geoip2::City = self.reader.lookup(ip).ok()?;
+        let coordinates = record.location?;
+
+        Some(Location {
+            city: english_name(record.city.and_then(|city| city.names)),
+            country: english_name(record.country.and_then(|country| country.names)),
+            latitude: coordinates.latitude?,
+            longitude: coordinates.longitude?,
+        })
+    }
+
+    /// Like `locate`, for an address in text form
+    pub fn locate_str(&self, ip: &str) -> Option<Location> {
+        self.locate(ip.parse().ok()?)
+    }
+}
+
+fn english_name(names: Option<std::collections::BTreeMap<&str, &str>>) -> Option<String> {
+    names?.get("en").map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(latitude: f64, longitude: f64) -> Location {
+        Location {
+            city: None,
+            country: None,
+            latitude,
+            longitude,
+        }
+    }
+
+    #[test]
+    fn test_distance_km() {
+        let berlin = location(52.52, 13.405);
+        let paris = location(48.8566, 2.3522);
+
+        assert!((berlin.distance_km(&paris) - 878.0).abs() < 5.0);
+        assert_eq!(berlin.distance_km(&berlin), 0.0);
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut place = location(52.52, 13.4);
+        assert_eq!(place.describe(), "52.52, 13.40");
+
+        place.country = Some("Germany".to_string());
+        assert_eq!(place.describe(), "Germany");
+
+        place.city = Some("Berlin".to_string());
+        assert_eq!(place.describe(), "Berlin, Germany");
+    }
+
+    #[test]
+    fn test_open_missing_database() {
+        assert!(GeoIp::open("/nonexistent/GeoLite2-City.mmdb").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web_validator::{Json, Query};
+use uuid::Uuid;
+
+use crate::{
+    errors::ServiceResult,
+    middleware::auth::AuthenticatedUserExt,
+    models::{
+        admin::{UpdatePasswordPolicyRequest, UserListQuery},
+        audit::AuditEventQuery,
+        bulk_user::{ExportQuery, ImportQuery},
+        outbox::OutboxListQuery,
+        webhook::{
+            CreateWebhookSubscriptionRequest, UpdateWebhookSubscriptionRequest,
+            WebhookDeliveryQuery,
+        },
+    },
+    services::{
+        audit_checkpoint_worker::configured_checkpoints, AdminService, AuditService,
+        BulkUserService, OutboxService, WebhookService,
+    },
+};
+
+/// List users with filters and cursor pagination (requires admin)
+pub async fn list_users(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    Query(query): Query<UserListQuery>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = admin_service.list_users(query).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get a single user (requires admin)
+pub async fn get_user(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = admin_service.get_user(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Reactivate a user's account (requires admin)
+pub async fn activate_user(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!("Admin {} activating user {}", admin.email, user_id);
+
+    let response = admin_service
+        .set_active(admin.user_id, user_id, true)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Deactivate a user's account (requires admin)
+pub async fn deactivate_user(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!("Admin {} deactivating user {}", admin.email, user_id);
+
+    let response = admin_service
+        .set_active(admin.user_id, user_id, false)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Mark a user's email as verified (requires admin)
+pub async fn verify_user(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!("Admin {} verifying the email of {}", admin.email, user_id);
+
+    let response = admin_service.force_verify(user_id).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Clear a user's failed login attempts and lockout (requires admin)
+pub async fn unlock_user(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!("Admin {} unlocking user {}", admin.email, user_id);
+
+    let response = admin_service.unlock(user_id).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Send a password reset link to a user (requires admin)
+pub async fn trigger_password_reset(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!(
+        "Admin {} triggering a password reset for {}",
+        admin.email,
+        user_id
+    );
+
+    let response = admin_service.trigger_password_reset(user_id).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Import users from a streamed CSV or JSON Lines upload (requires admin)
+pub async fn import_users(
+    req: HttpRequest,
+    bulk_user_service: web::Data<BulkUserService>,
+    Query(query): Query<ImportQuery>,
+    body: web::Payload,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let dry_run = query.dry_run.unwrap_or(false);
+
+    log::info!(
+        "Admin {} importing users ({}{})",
+        admin.email,
+        query.format.extension(),
+        if dry_run { ", dry run" } else { "" }
+    );
+
+    let report = bulk_user_service
+        .import(query.format, dry_run, body)
+        .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Stream all users as CSV or JSON Lines (requires admin)
+pub async fn export_users(
+    req: HttpRequest,
+    bulk_user_service: web::Data<BulkUserService>,
+    Query(query): Query<ExportQuery>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+
+    log::info!("Admin {} exporting users", admin.email);
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"users.{}\"",
+                query.format.extension()
+            ),
+        ))
+        .insert_header(("Cache-Control", "no-store"))
+        .streaming(bulk_user_service.export(query.format)))
+}
+
+/// Update a user's password policy flags (requires admin)
+pub async fn update_password_policy(
+    req: HttpRequest,
+    admin_service: web::Data<AdminService>,
+    path: web::Path<Uuid>,
+    Json(request): Json<UpdatePasswordPolicyRequest>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let user_id = path.into_inner();
+
+    log::info!(
+        "Admin {} updating password policy of {}",
+        admin.email,
+        user_id
+    );
+
+    let response = admin_service
+        .update_password_policy(user_id, request)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// List outbox messages, dead-lettered ones by default (requires admin)
+pub async fn list_outbox_messages(
+    req: HttpRequest,
+    outbox_service: web::Data<OutboxService>,
+    Query(query): Query<OutboxListQuery>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = outbox_service.list(query).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Search the audit log by user, action and time range (requires admin)
+pub async fn list_audit_events(
+    req: HttpRequest,
+    audit_service: web::Data<AuditService>,
+    Query(query): Query<AuditEventQuery>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = audit_service.list(query).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Verify the audit log's hash chain and the exported checkpoints (requires admin)
+pub async fn verify_audit_log(
+    req: HttpRequest,
+    audit_service: web::Data<AuditService>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let checkpoints = configured_checkpoints()?;
+    let response = audit_service.verify(&checkpoints).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Re-queue a dead-lettered outbox message (requires admin)
+pub async fn retry_outbox_message(
+    req: HttpRequest,
+    outbox_service: web::Data<OutboxService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let message_id = path.into_inner();
+
+    log::info!(
+        "Admin {} retrying outbox message {}",
+        admin.email,
+        message_id
+    );
+
+    let response = outbox_service.retry(message_id).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// List webhook subscriptions (requires admin)
+pub async fn list_webhook_subscriptions(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = webhook_service.list_subscriptions().await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Subscribe an endpoint to identity events; the response holds the signing secret (requires admin)
+pub async fn create_webhook_subscription(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    Json(request): Json<CreateWebhookSubscriptionRequest>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+
+    log::info!(
+        "Admin {} subscribing {} to webhooks",
+        admin.email,
+        request.url
+    );
+
+    let response = webhook_service.create_subscription(request).await?;
+    Ok(HttpResponse::Created().json(response))
+}
+
+/// Get a webhook subscription (requires admin)
+pub async fn get_webhook_subscription(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = webhook_service.get_subscription(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Update a webhook subscription (requires admin)
+pub async fn update_webhook_subscription(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+    Json(request): Json<UpdateWebhookSubscriptionRequest>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let subscription_id = path.into_inner();
+
+    log::info!(
+        "Admin {} updating webhook subscription {}",
+        admin.email,
+        subscription_id
+    );
+
+    let response = webhook_service
+        .update_subscription(subscription_id, request)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Delete a webhook subscription and its delivery log (requires admin)
+pub async fn delete_webhook_subscription(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let subscription_id = path.into_inner();
+
+    log::info!(
+        "Admin {} deleting webhook subscription {}",
+        admin.email,
+        subscription_id
+    );
+
+    webhook_service.delete_subscription(subscription_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// List deliveries of a webhook subscription, newest first (requires admin)
+pub async fn list_webhook_deliveries(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+    Query(query): Query<WebhookDeliveryQuery>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = webhook_service
+        .list_deliveries(path.into_inner(), query)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get a webhook delivery with the log of its attempts (requires admin)
+pub async fn get_webhook_delivery(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    req.require_admin_user()?;
+
+    let response = webhook_service.get_delivery(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Queue a webhook delivery again (requires admin)
+pub async fn replay_webhook_delivery(
+    req: HttpRequest,
+    webhook_service: web::Data<WebhookService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let admin = req.require_admin_user()?;
+    let delivery_id = path.into_inner();
+
+    log::info!(
+        "Admin {} replaying webhook delivery {}",
+        admin.email,
+        delivery_id
+    );
+
+    let response = webhook_service.replay_delivery(delivery_id).await?;
+    Ok(HttpResponse::Accepted().json(response))
+}
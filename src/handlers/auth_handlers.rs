@@ -1,71 +1,126 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use actix_web_validator::{Json, Query};
+use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    config::CONFIG,
     errors::ServiceResult,
     middleware::auth::AuthenticatedUserExt,
-    models::auth_user::{
-        ChangePasswordRequest, ConfirmResetPasswordRequest, LoginRequest, RegisterRequest,
-        ResetPasswordRequest, VerifyEmailRequest,
+    models::{
+        auth_user::{
+            ChangePasswordRequest, ConfirmEmailChangeRequest, ConfirmResetPasswordRequest,
+            DeleteAccountRequest, LoginRequest, RegisterRequest, RequestEmailChangeRequest,
+            ResendVerificationRequest, ResetPasswordRequest, SetUsernameRequest,
+            UpdateProfileRequest, VerifyEmailRequest,
+        },
+        data_export::DownloadQuery,
+        login_event::LoginHistoryQuery,
+        login_risk::{LoginOutcome, VerifyLoginRequest},
+        password_strength::{email_user_inputs, PasswordStrengthRequest, PasswordStrengthResponse},
+        request_context::RequestContext,
+        session::device_cookie,
+        token::generate_token,
     },
-    services::AuthService,
+    services::{AuthService, DataExportService, SessionService},
 };
 
 /// Register a new user
 pub async fn register(
     auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
     Json(request): Json<RegisterRequest>,
 ) -> ServiceResult<impl Responder> {
     // Validate the request
     request.validate()?;
 
-    let response = auth_service.register(request).await?;
+    let response = auth_service.register(request, &ctx).await?;
     Ok(HttpResponse::Created().json(response))
 }
 
 /// Login a user
 pub async fn login(
     auth_service: web::Data<AuthService>,
+    mut ctx: RequestContext,
     Json(request): Json<LoginRequest>,
 ) -> ServiceResult<impl Responder> {
     // Validate the request
     request.validate()?;
 
-    let response = auth_service.login(request).await?;
+    // Issue a device cookie to browsers without one, so their next login is recognized
+    let device_id = ctx.device_id.get_or_insert_with(generate_token).clone();
+
+    let outcome = auth_service.login(request, &ctx).await?;
+    let mut response = match outcome {
+        LoginOutcome::Authenticated(_) => HttpResponse::Ok(),
+        LoginOutcome::ChallengeRequired(_) => HttpResponse::Accepted(),
+    };
+    response.cookie(device_cookie(&device_id));
+    Ok(match outcome {
+        LoginOutcome::Authenticated(body) => response.json(body),
+        LoginOutcome::ChallengeRequired(body) => response.json(body),
+    })
+}
+
+/// Complete a suspicious login with the code sent by email
+pub async fn verify_login(
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+    Json(request): Json<VerifyLoginRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let response = auth_service.verify_login(request, &ctx).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
 /// Verify email address
 pub async fn verify_email(
     auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
     Query(request): Query<VerifyEmailRequest>,
 ) -> ServiceResult<impl Responder> {
-    let response = auth_service.verify_email(request).await?;
+    let response = auth_service.verify_email(request, &ctx).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Resend the email verification link
+pub async fn resend_verification(
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+    Json(request): Json<ResendVerificationRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let response = auth_service.resend_verification(request, &ctx).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
 /// Request password reset
 pub async fn request_password_reset(
     auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
     Json(request): Json<ResetPasswordRequest>,
 ) -> ServiceResult<impl Responder> {
     // Validate the request
     request.validate()?;
 
-    let response = auth_service.request_password_reset(request).await?;
+    let response = auth_service.request_password_reset(request, &ctx).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
 /// Confirm password reset
 pub async fn confirm_password_reset(
     auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
     Json(request): Json<ConfirmResetPasswordRequest>,
 ) -> ServiceResult<impl Responder> {
     // Validate the request
     request.validate()?;
 
-    let response = auth_service.confirm_password_reset(request).await?;
+    let response = auth_service.confirm_password_reset(request, &ctx).await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
@@ -73,16 +128,138 @@ pub async fn confirm_password_reset(
 pub async fn change_password(
     req: HttpRequest,
     auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
     Json(request): Json<ChangePasswordRequest>,
 ) -> ServiceResult<impl Responder> {
     // Validate the request
     request.validate()?;
 
     let user = req.require_authenticated_user()?;
-    let response = auth_service.change_password(user.user_id, request).await?;
+    let response = auth_service
+        .change_password(user.user_id, request, &ctx)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Request a change of email address (requires authentication)
+pub async fn request_email_change(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+    Json(request): Json<RequestEmailChangeRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user = req.require_authenticated_user()?;
+    let response = auth_service
+        .request_email_change(user.user_id, request, &ctx)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Confirm a change of email address (requires authentication)
+pub async fn confirm_email_change(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+    Json(request): Json<ConfirmEmailChangeRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user = req.require_authenticated_user()?;
+    let response = auth_service
+        .confirm_email_change(user.user_id, user.session_id, request, &ctx)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Set or remove the current user's username (requires authentication)
+pub async fn set_username(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    Json(request): Json<SetUsernameRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user = req.require_authenticated_user()?;
+    let response = auth_service.set_username(user.user_id, request).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Partially update the current user's profile (requires authentication)
+pub async fn update_profile(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    Json(request): Json<UpdateProfileRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user = req.require_authenticated_user()?;
+    let response = auth_service.update_profile(user.user_id, request).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Deactivate the current user's account and schedule its deletion (requires authentication)
+pub async fn delete_account(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+    Json(request): Json<DeleteAccountRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user = req.require_authenticated_user()?;
+    let response = auth_service
+        .request_account_deletion(user.user_id, request, &ctx)
+        .await?;
+    Ok(HttpResponse::Accepted().json(response))
+}
+
+/// Request an export of the current user's personal data (requires authentication)
+pub async fn request_data_export(
+    req: HttpRequest,
+    data_export_service: web::Data<DataExportService>,
+) -> ServiceResult<impl Responder> {
+    let user = req.require_authenticated_user()?;
+    let response = data_export_service.request_export(user.user_id).await?;
+    Ok(HttpResponse::Accepted().json(response))
+}
+
+/// Get the status and download link of a data export (requires authentication)
+pub async fn get_data_export(
+    req: HttpRequest,
+    data_export_service: web::Data<DataExportService>,
+    path: web::Path<Uuid>,
+) -> ServiceResult<impl Responder> {
+    let user = req.require_authenticated_user()?;
+    let response = data_export_service
+        .get_export(user.user_id, path.into_inner())
+        .await?;
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Download a data export through its signed link (no authentication required)
+pub async fn download_data_export(
+    data_export_service: web::Data<DataExportService>,
+    path: web::Path<Uuid>,
+    web::Query(query): web::Query<DownloadQuery>,
+) -> ServiceResult<impl Responder> {
+    let export_id = path.into_inner();
+    let archive = data_export_service.download(export_id, &query).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"personal-data-{export_id}.json\""),
+        ))
+        .insert_header(("Cache-Control", "no-store"))
+        .json(archive))
+}
+
 /// Get current user info (requires authentication)
 pub async fn get_user_info(
     req: HttpRequest,
@@ -99,7 +276,67 @@ pub async fn refresh_token(
     auth_service: web::Data<AuthService>,
 ) -> ServiceResult<impl Responder> {
     let user = req.require_authenticated_user()?;
-    let response = auth_service.refresh_token(user.user_id).await?;
+    let response = auth_service
+        .refresh_token(user.user_id, user.session_id)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// List the current user's recent login attempts (requires authentication)
+pub async fn get_login_history(
+    req: HttpRequest,
+    session_service: web::Data<SessionService>,
+    Query(query): Query<LoginHistoryQuery>,
+) -> ServiceResult<impl Responder> {
+    let user = req.require_authenticated_user()?;
+    let response = session_service.login_history(user.user_id, query).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// List the devices the current user is logged in on (requires authentication)
+pub async fn get_devices(
+    req: HttpRequest,
+    session_service: web::Data<SessionService>,
+) -> ServiceResult<impl Responder> {
+    let user = req.require_authenticated_user()?;
+    let response = session_service
+        .list_devices(user.user_id, user.session_id)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Log a device out by revoking its sessions (requires authentication)
+pub async fn revoke_device(
+    req: HttpRequest,
+    session_service: web::Data<SessionService>,
+    path: web::Path<String>,
+) -> ServiceResult<impl Responder> {
+    let user = req.require_authenticated_user()?;
+    let response = session_service
+        .revoke_device(user.user_id, &path.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Estimate password strength for live signup feedback (no authentication required)
+pub async fn password_strength(
+    Json(request): Json<PasswordStrengthRequest>,
+) -> ServiceResult<impl Responder> {
+    // Validate the request
+    request.validate()?;
+
+    let user_inputs = request
+        .email
+        .as_deref()
+        .map(email_user_inputs)
+        .unwrap_or_default();
+    let user_inputs: Vec<&str> = user_inputs.iter().map(String::as_str).collect();
+
+    let response = PasswordStrengthResponse::evaluate(
+        &request.password,
+        &user_inputs,
+        CONFIG.password_min_score,
+    );
     Ok(HttpResponse::Ok().json(response))
 }
 
@@ -112,22 +349,18 @@ pub async fn health_check() -> impl Responder {
 }
 
 /// Logout endpoint (requires authentication)
-/// Note: With JWT, logout is typically handled client-side by removing the token
-/// This endpoint can be used for logging purposes or token blacklisting
-pub async fn logout(req: HttpRequest) -> ServiceResult<impl Responder> {
+/// Revokes the token's session, so neither it nor tokens refreshed from it work anymore
+pub async fn logout(
+    req: HttpRequest,
+    auth_service: web::Data<AuthService>,
+    ctx: RequestContext,
+) -> ServiceResult<impl Responder> {
     let user = req.require_authenticated_user()?;
 
-    // Log the logout event
-    log::info!("User {} logged out", user.email);
-
-    // In a more sophisticated implementation, you might:
-    // - Add the token to a blacklist
-    // - Store logout events in the database
-    // - Send notifications
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Logged out successfully"
-    })))
+    let response = auth_service
+        .logout(user.user_id, user.session_id, &ctx)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[cfg(test)]
@@ -147,11 +380,31 @@ mod tests {
         assert!(resp.status().is_success());
     }
 
+    #[actix_web::test]
+    async fn test_password_strength() {
+        let app = test::init_service(
+            App::new().route("/password-strength", web::post().to(password_strength)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/password-strength")
+            .set_json(serde_json::json!({ "password": "Password1!" }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["acceptable"], false);
+        assert!(body["score"].as_u64().unwrap() < 3);
+        assert!(body["crack_time_display"].is_string());
+        assert!(body["suggestions"].is_array());
+    }
+
     #[tokio::test]
     async fn test_register_request_validation() {
         let invalid_request = RegisterRequest {
             email: "invalid-email".to_string(),
             password: "weak".to_string(),
+            username: None,
         };
 
         let validation_result = invalid_request.validate();
@@ -161,7 +414,8 @@ mod tests {
     #[tokio::test]
     async fn test_login_request_validation() {
         let invalid_request = LoginRequest {
-            email: "invalid-email".to_string(),
+            email: Some("invalid-email".to_string()),
+            username: None,
             password: "".to_string(),
         };
 
@@ -169,6 +423,46 @@ mod tests {
         assert!(validation_result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_login_request_identifier() {
+        let request: LoginRequest = serde_json::from_value(serde_json::json!({
+            "username": " alice ",
+            "password": "secret"
+        }))
+        .unwrap();
+        assert!(request.validate().is_ok());
+        assert_eq!(request.username.as_deref(), Some("alice"));
+
+        let request: LoginRequest =
+            serde_json::from_value(serde_json::json!({ "password": "secret" })).unwrap();
+        assert!(request.validate().is_err());
+
+        let request: LoginRequest = serde_json::from_value(serde_json::json!({
+            "email": "alice@example.com",
+            "username": "alice",
+            "password": "secret"
+        }))
+        .unwrap();
+        assert!(request.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_username_request_validation() {
+        let request = SetUsernameRequest {
+            username: Some("admin".to_string()),
+        };
+        assert!(request.validate().is_err());
+
+        let request = SetUsernameRequest {
+            username: Some("alice_42".to_string()),
+        };
+        assert!(request.validate().is_ok());
+
+        // Removing the username
+        let request = SetUsernameRequest { username: None };
+        assert!(request.validate().is_ok());
+    }
+
     #[tokio::test]
     async fn test_change_password_request_validation() {
         let invalid_request = ChangePasswordRequest {
@@ -180,6 +474,21 @@ mod tests {
         assert!(validation_result.is_err());
     }
 
+    #[actix_web::test]
+    async fn test_email_change_request_validation() {
+        let invalid_request = RequestEmailChangeRequest {
+            new_email: "not-an-email".to_string(),
+            current_password: "".to_string(),
+        };
+        assert!(invalid_request.validate().is_err());
+
+        let valid_request = RequestEmailChangeRequest {
+            new_email: "new@example.com".to_string(),
+            current_password: "Quartz!Lantern7&Meadow".to_string(),
+        };
+        assert!(valid_request.validate().is_ok());
+    }
+
     #[tokio::test]
     async fn test_reset_password_request_validation() {
         let invalid_request = ResetPasswordRequest {
@@ -190,6 +499,16 @@ mod tests {
         assert!(validation_result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_resend_verification_request_validation() {
+        let invalid_request = ResendVerificationRequest {
+            email: "invalid-email".to_string(),
+        };
+
+        let validation_result = invalid_request.validate();
+        assert!(validation_result.is_err());
+    }
+
     #[tokio::test]
     async fn test_confirm_reset_password_request_validation() {
         let invalid_request = ConfirmResetPasswordRequest {
@@ -1,3 +1,4 @@
+pub mod admin_handlers;
 pub mod auth_handlers;
 
 // pub use auth_handlers::*; // Commented out due to unused import
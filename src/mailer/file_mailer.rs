@@ -0,0 +1,68 @@
+use futures_util::future::BoxFuture;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::Path;
+
+use super::{build_message, EmailMessage, Mailer};
+use crate::errors::{ServiceError, ServiceResult};
+
+/// Writes each email as an `.eml` file into a directory, for local development
+pub struct FileMailer {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl AsRef<Path>, from: &str) -> ServiceResult<Self> {
+        std::fs::create_dir_all(dir.as_ref())
+            .map_err(|e| ServiceError::MailerError(format!("Cannot create mail directory: {e}")))?;
+
+        Ok(Self {
+            transport: AsyncFileTransport::new(dir),
+            from: from.to_string(),
+        })
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, ServiceResult<()>> {
+        Box::pin(async move {
+            let email = build_message(&self.from, message)?;
+            let id = self
+                .transport
+                .send(email)
+                .await
+                .map_err(|e| ServiceError::MailerError(e.to_string()))?;
+
+            log::debug!("Email to {} written as {id}.eml", message.to);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_mailer_writes_eml() {
+        let dir = std::env::temp_dir().join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::new(&dir, "noreply@example.com").unwrap();
+
+        let message = EmailMessage {
+            template: "verification".to_string(),
+            to: "user@example.com".to_string(),
+            subject: "Hello".to_string(),
+            text_body: "Body".to_string(),
+            html_body: None,
+        };
+        mailer.send(&message).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains("To: user@example.com"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
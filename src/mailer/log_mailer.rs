@@ -0,0 +1,41 @@
+use futures_util::future::BoxFuture;
+
+use super::{EmailMessage, Mailer};
+use crate::errors::ServiceResult;
+
+/// Logs that an email would have been sent instead of delivering it (development only).
+///
+/// Bodies hold verification and reset tokens, so only the recipient and template are logged;
+/// use the `file` backend to read the emails themselves.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, ServiceResult<()>> {
+        Box::pin(async move {
+            log::info!(
+                "Email to {} from template {} not delivered (MAILER_BACKEND=log)",
+                message.to,
+                message.template
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_log_mailer_accepts_messages() {
+        let message = EmailMessage {
+            template: "verification".to_string(),
+            to: "user@example.com".to_string(),
+            subject: "Hello".to_string(),
+            text_body: "Body".to_string(),
+            html_body: None,
+        };
+
+        assert!(LogMailer.send(&message).await.is_ok());
+    }
+}
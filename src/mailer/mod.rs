@@ -0,0 +1,126 @@
+use futures_util::future::BoxFuture;
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart, SinglePart},
+    Message,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    config::Config,
+    errors::{ServiceError, ServiceResult},
+};
+
+pub mod file_mailer;
+pub mod log_mailer;
+pub mod smtp_mailer;
+pub mod templates;
+
+pub use file_mailer::FileMailer;
+pub use log_mailer::LogMailer;
+pub use smtp_mailer::SmtpMailer;
+
+/// A rendered email ready to be handed to a `Mailer`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailMessage {
+    #[serde(default)]
+    pub template: String, // name of the template it was rendered from, for logging
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+}
+
+/// Delivers emails; implementations are selected with `MAILER_BACKEND`
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, ServiceResult<()>>;
+}
+
+/// Build the mailer configured by `MAILER_BACKEND`
+pub fn mailer_from_config(config: &Config) -> ServiceResult<Arc<dyn Mailer>> {
+    let mailer: Arc<dyn Mailer> = match config.mailer_backend.as_str() {
+        "smtp" => Arc::new(SmtpMailer::from_config(config)?),
+        "file" => Arc::new(FileMailer::new(&config.mail_dir, &config.mail_from)?),
+        "log" => Arc::new(LogMailer),
+        other => {
+            return Err(ServiceError::MailerError(format!(
+                "Unknown mailer backend: {other}"
+            )))
+        }
+    };
+
+    Ok(mailer)
+}
+
+// Convert an `EmailMessage` into a MIME message for lettre-based transports
+fn build_message(from: &str, message: &EmailMessage) -> ServiceResult<Message> {
+    let from: Mailbox = from
+        .parse()
+        .map_err(|e| ServiceError::MailerError(format!("Invalid sender address: {e}")))?;
+    let to: Mailbox = message
+        .to
+        .parse()
+        .map_err(|e| ServiceError::MailerError(format!("Invalid recipient address: {e}")))?;
+
+    let builder = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(message.subject.clone());
+
+    let built = match &message.html_body {
+        Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+            message.text_body.clone(),
+            html.clone(),
+        )),
+        None => builder.singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(message.text_body.clone()),
+        ),
+    };
+
+    built.map_err(|e| ServiceError::MailerError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(html_body: Option<&str>) -> EmailMessage {
+        EmailMessage {
+            template: "verification".to_string(),
+            to: "user@example.com".to_string(),
+            subject: "Hello".to_string(),
+            text_body: "Plain body".to_string(),
+            html_body: html_body.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_build_plain_message() {
+        let built = build_message("noreply@example.com", &message(None)).unwrap();
+        let raw = String::from_utf8(built.formatted()).unwrap();
+
+        assert!(raw.contains("To: user@example.com"));
+        assert!(raw.contains("Subject: Hello"));
+        assert!(raw.contains("text/plain"));
+        assert!(raw.contains("Plain body"));
+    }
+
+    #[test]
+    fn test_build_alternative_message() {
+        let built = build_message("noreply@example.com", &message(Some("<p>Hi</p>"))).unwrap();
+        let raw = String::from_utf8(built.formatted()).unwrap();
+
+        assert!(raw.contains("multipart/alternative"));
+        assert!(raw.contains("<p>Hi</p>"));
+    }
+
+    #[test]
+    fn test_build_message_rejects_invalid_address() {
+        let mut invalid = message(None);
+        invalid.to = "not an address".to_string();
+
+        assert!(build_message("noreply@example.com", &invalid).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use futures_util::future::BoxFuture;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+
+use super::{build_message, EmailMessage, Mailer};
+use crate::{
+    config::Config,
+    errors::{ServiceError, ServiceResult},
+};
+
+/// Delivers emails through an SMTP relay
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn from_config(config: &Config) -> ServiceResult<Self> {
+        let host = config.smtp_host.as_str();
+        let builder = match config.smtp_tls.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            // Plain connection, e.g. a local SMTP sink
+            _ => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(|e| ServiceError::MailerError(e.to_string()))?
+        .port(config.smtp_port);
+
+        let builder = match (&config.smtp_username, &config.smtp_password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.mail_from.clone(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, ServiceResult<()>> {
+        Box::pin(async move {
+            let email = build_message(&self.from, message)?;
+            self.transport
+                .send(email)
+                .await
+                .map_err(|e| ServiceError::MailerError(e.to_string()))?;
+
+            log::debug!("Email to {} sent via SMTP", message.to);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG;
+
+    #[tokio::test]
+    #[ignore] // Requires a local SMTP sink, e.g. `docker compose up mailpit`
+    async fn test_smtp_mailer_delivers_to_local_sink() {
+        let mut config = CONFIG.clone();
+        config.smtp_host = "localhost".to_string();
+        config.smtp_port = 1025;
+        config.smtp_tls = "none".to_string();
+        config.smtp_username = None;
+        config.smtp_password = None;
+
+        let mailer = SmtpMailer::from_config(&config).unwrap();
+        let message = EmailMessage {
+            template: "verification".to_string(),
+            to: "user@example.com".to_string(),
+            subject: "SMTP test".to_string(),
+            text_body: "Delivered through the local sink".to_string(),
+            html_body: Some("<p>Delivered through the local sink</p>".to_string()),
+        };
+
+        assert!(mailer.send(&message).await.is_ok());
+    }
+}
@@ -0,0 +1,425 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{collections::HashMap, fs, path::Path};
+
+use super::EmailMessage;
+use crate::errors::{ServiceError, ServiceResult};
+
+static VARIABLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").unwrap());
+
+/// Transactional emails that can be rendered from templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmailTemplate {
+    Verification,
+    PasswordReset,
+    PasswordChanged,
+    NewLogin,
+    EmailChange,
+    EmailChangeNotice,
+    AccountDeletion,
+    RegistrationAttempt,
+    SuspiciousLogin,
+    LoginChallenge,
+}
+
+impl EmailTemplate {
+    pub const ALL: [EmailTemplate; 10] = [
+        EmailTemplate::Verification,
+        EmailTemplate::PasswordReset,
+        EmailTemplate::PasswordChanged,
+        EmailTemplate::NewLogin,
+        EmailTemplate::EmailChange,
+        EmailTemplate::EmailChangeNotice,
+        EmailTemplate::AccountDeletion,
+        EmailTemplate::RegistrationAttempt,
+        EmailTemplate::SuspiciousLogin,
+        EmailTemplate::LoginChallenge,
+    ];
+
+    /// File name stem used in the template directory
+    pub fn name(self) -> &'static str {
+        match self {
+            EmailTemplate::Verification => "verification",
+            EmailTemplate::PasswordReset => "password_reset",
+            EmailTemplate::PasswordChanged => "password_changed",
+            EmailTemplate::NewLogin => "new_login",
+            EmailTemplate::EmailChange => "email_change",
+            EmailTemplate::EmailChangeNotice => "email_change_notice",
+            EmailTemplate::AccountDeletion => "account_deletion",
+            EmailTemplate::RegistrationAttempt => "registration_attempt",
+            EmailTemplate::SuspiciousLogin => "suspicious_login",
+            EmailTemplate::LoginChallenge => "login_challenge",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TemplateSet {
+    subject: String,
+    text: String,
+    html: String,
+}
+
+// Built-in English templates, used when the template directory does not override them
+const BUILTIN_LOCALE: &str = "en";
+const BUILTIN_TEMPLATES: [(EmailTemplate, &str, &str, &str); 10] = [
+    (
+        EmailTemplate::Verification,
+        include_str!("../../templates/emails/en/verification.subject.txt"),
+        include_str!("../../templates/emails/en/verification.txt"),
+        include_str!("../../templates/emails/en/verification.html"),
+    ),
+    (
+        EmailTemplate::PasswordReset,
+        include_str!("../../templates/emails/en/password_reset.subject.txt"),
+        include_str!("../../templates/emails/en/password_reset.txt"),
+        include_str!("../../templates/emails/en/password_reset.html"),
+    ),
+    (
+        EmailTemplate::PasswordChanged,
+        include_str!("../../templates/emails/en/password_changed.subject.txt"),
+        include_str!("../../templates/emails/en/password_changed.txt"),
+        include_str!("../../templates/emails/en/password_changed.html"),
+    ),
+    (
+        EmailTemplate::NewLogin,
+        include_str!("../../templates/emails/en/new_login.subject.txt"),
+        include_str!("../../templates/emails/en/new_login.txt"),
+        include_str!("../../templates/emails/en/new_login.html"),
+    ),
+    (
+        EmailTemplate::EmailChange,
+        include_str!("../../templates/emails/en/email_change.subject.txt"),
+        include_str!("../../templates/emails/en/email_change.txt"),
+        include_str!("../../templates/emails/en/email_change.html"),
+    ),
+    (
+        EmailTemplate::EmailChangeNotice,
+        include_str!("../../templates/emails/en/email_change_notice.subject.txt"),
+        include_str!("../../templates/emails/en/email_change_notice.txt"),
+        include_str!("../../templates/emails/en/email_change_notice.html"),
+    ),
+    (
+        EmailTemplate::AccountDeletion,
+        include_str!("../../templates/emails/en/account_deletion.subject.txt"),
+        include_str!("../../templates/emails/en/account_deletion.txt"),
+        include_str!("../../templates/emails/en/account_deletion.html"),
+    ),
+    (
+        EmailTemplate::RegistrationAttempt,
+        include_str!("../../templates/emails/en/registration_attempt.subject.txt"),
+        include_str!("../../templates/emails/en/registration_attempt.txt"),
+        include_str!("../../templates/emails/en/registration_attempt.html"),
+    ),
+    (
+        EmailTemplate::SuspiciousLogin,
+        include_str!("../../templates/emails/en/suspicious_login.subject.txt"),
+        include_str!("../../templates/emails/en/suspicious_login.txt"),
+        include_str!("../../templates/emails/en/suspicious_login.html"),
+    ),
+    (
+        EmailTemplate::LoginChallenge,
+        include_str!("../../templates/emails/en/login_challenge.subject.txt"),
+        include_str!("../../templates/emails/en/login_challenge.txt"),
+        include_str!("../../templates/emails/en/login_challenge.html"),
+    ),
+];
+
+/// Localized email templates with HTML and plain-text variants.
+///
+/// Templates live in `<dir>/<locale>/<name>.subject.txt`, `<name>.txt` and `<name>.html`
+/// and use `{{variable}}` placeholders. Values are HTML-escaped in the HTML variant.
+pub struct EmailTemplates {
+    templates: HashMap<(String, EmailTemplate), TemplateSet>,
+    default_locale: String,
+}
+
+impl EmailTemplates {
+    pub fn builtin() -> Self {
+        let templates = BUILTIN_TEMPLATES
+            .iter()
+            .map(|(template, subject, text, html)| {
+                (
+                    (BUILTIN_LOCALE.to_string(), *template),
+                    TemplateSet {
+                        subject: subject.trim().to_string(),
+                        text: text.to_string(),
+                        html: html.to_string(),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            templates,
+            default_locale: BUILTIN_LOCALE.to_string(),
+        }
+    }
+
+    /// Load templates from a directory on top of the built-in ones
+    pub fn load(dir: &Path, default_locale: &str) -> ServiceResult<Self> {
+        let mut templates = Self::builtin();
+        templates.default_locale = normalize_locale(default_locale);
+
+        if dir.is_dir() {
+            let entries = fs::read_dir(dir).map_err(template_error)?;
+            for entry in entries {
+                let path = entry.map_err(template_error)?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+
+                let locale = match path.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => normalize_locale(name),
+                    None => continue,
+                };
+
+                for template in EmailTemplate::ALL {
+                    if let Some(set) = read_template_set(&path, template)? {
+                        templates.templates.insert((locale.clone(), template), set);
+                    }
+                }
+            }
+        } else {
+            log::warn!(
+                "Email template directory {} not found, using built-in templates",
+                dir.display()
+            );
+        }
+
+        for template in EmailTemplate::ALL {
+            if !templates.has_template(&templates.default_locale, template) {
+                return Err(ServiceError::MailerError(format!(
+                    "Template {} is missing for default locale {}",
+                    template.name(),
+                    templates.default_locale
+                )));
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// Pick the first preferred locale that has the template, falling back to the default
+    pub fn resolve_locale(&self, template: EmailTemplate, preferences: &[String]) -> String {
+        for preference in preferences {
+            let locale = normalize_locale(preference);
+            if self.has_template(&locale, template) {
+                return locale;
+            }
+
+            // "es-mx" falls back to "es"
+            if let Some((primary, _)) = locale.split_once('-') {
+                if self.has_template(primary, template) {
+                    return primary.to_string();
+                }
+            }
+        }
+
+        self.default_locale.clone()
+    }
+
+    pub fn render(
+        &self,
+        template: EmailTemplate,
+        preferences: &[String],
+        to: &str,
+        variables: &HashMap<&str, String>,
+    ) -> ServiceResult<EmailMessage> {
+        let locale = self.resolve_locale(template, preferences);
+        let set = self.templates.get(&(locale, template)).ok_or_else(|| {
+            ServiceError::MailerError(format!("Template {} not found", template.name()))
+        })?;
+
+        Ok(EmailMessage {
+            template: template.name().to_string(),
+            to: to.to_string(),
+            subject: render_str(&set.subject, variables, false),
+            text_body: render_str(&set.text, variables, false),
+            html_body: Some(render_str(&set.html, variables, true)),
+        })
+    }
+
+    fn has_template(&self, locale: &str, template: EmailTemplate) -> bool {
+        self.templates.contains_key(&(locale.to_string(), template))
+    }
+}
+
+fn read_template_set(dir: &Path, template: EmailTemplate) -> ServiceResult<Option<TemplateSet>> {
+    let name = template.name();
+    let files = [
+        dir.join(format!("{name}.subject.txt")),
+        dir.join(format!("{name}.txt")),
+        dir.join(format!("{name}.html")),
+    ];
+
+    match files.iter().filter(|file| file.is_file()).count() {
+        0 => Ok(None),
+        3 => {
+            let [subject, text, html] = files.map(fs::read_to_string);
+            Ok(Some(TemplateSet {
+                subject: subject.map_err(template_error)?.trim().to_string(),
+                text: text.map_err(template_error)?,
+                html: html.map_err(template_error)?,
+            }))
+        }
+        _ => Err(ServiceError::MailerError(format!(
+            "Template {name} in {} needs .subject.txt, .txt and .html files",
+            dir.display()
+        ))),
+    }
+}
+
+fn template_error(error: std::io::Error) -> ServiceError {
+    ServiceError::MailerError(format!("Cannot read email templates: {error}"))
+}
+
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+// Replace `{{name}}` placeholders; unknown variables render as empty strings
+fn render_str(template: &str, variables: &HashMap<&str, String>, escape: bool) -> String {
+    VARIABLE_REGEX
+        .replace_all(template, |captures: &regex::Captures| {
+            let value = variables
+                .get(&captures[1])
+                .map(String::as_str)
+                .unwrap_or_default();
+            if escape {
+                escape_html(value)
+            } else {
+                value.to_string()
+            }
+        })
+        .into_owned()
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("app_name", "Test App".to_string()),
+            ("email", "user@example.com".to_string()),
+            (
+                "action_url",
+                "https://app.example.com/verify-email?token=abc&x=1".to_string(),
+            ),
+            ("expires_at", "2030-01-01 00:00 UTC".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let templates = EmailTemplates::builtin();
+
+        for template in EmailTemplate::ALL {
+            let message = templates
+                .render(template, &[], "user@example.com", &variables())
+                .unwrap();
+            assert_eq!(message.template, template.name());
+            assert_eq!(message.to, "user@example.com");
+            assert!(message.subject.contains("Test App"));
+            assert!(!message.text_body.contains("{{"));
+            assert!(!message.html_body.unwrap().contains("{{"));
+        }
+    }
+
+    #[test]
+    fn test_html_variant_is_escaped() {
+        let templates = EmailTemplates::builtin();
+        let message = templates
+            .render(
+                EmailTemplate::Verification,
+                &[],
+                "user@example.com",
+                &variables(),
+            )
+            .unwrap();
+
+        assert!(message
+            .text_body
+            .contains("https://app.example.com/verify-email?token=abc&x=1"));
+        assert!(message
+            .html_body
+            .unwrap()
+            .contains("https://app.example.com/verify-email?token=abc&amp;x=1"));
+    }
+
+    #[test]
+    fn test_locale_resolution() {
+        let dir = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/emails"));
+        let templates = EmailTemplates::load(dir, "en").unwrap();
+        let prefs = |locales: &[&str]| locales.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            templates.resolve_locale(EmailTemplate::Verification, &prefs(&["es-MX", "en"])),
+            "es"
+        );
+        assert_eq!(
+            templates.resolve_locale(EmailTemplate::Verification, &prefs(&["fr", "es"])),
+            "es"
+        );
+        assert_eq!(
+            templates.resolve_locale(EmailTemplate::Verification, &prefs(&["fr"])),
+            "en"
+        );
+
+        let message = templates
+            .render(
+                EmailTemplate::PasswordReset,
+                &prefs(&["es"]),
+                "user@example.com",
+                &variables(),
+            )
+            .unwrap();
+        assert!(message.subject.starts_with("Restablece"));
+    }
+
+    #[test]
+    fn test_load_overrides_and_rejects_partial_templates() {
+        let dir = std::env::temp_dir().join(format!("templates-test-{}", uuid::Uuid::new_v4()));
+        let en = dir.join("en");
+        fs::create_dir_all(&en).unwrap();
+        fs::write(en.join("verification.subject.txt"), "Custom {{app_name}}\n").unwrap();
+        fs::write(en.join("verification.txt"), "Text {{action_url}}").unwrap();
+        fs::write(en.join("verification.html"), "<p>{{action_url}}</p>").unwrap();
+
+        let templates = EmailTemplates::load(&dir, "en").unwrap();
+        let message = templates
+            .render(
+                EmailTemplate::Verification,
+                &[],
+                "user@example.com",
+                &variables(),
+            )
+            .unwrap();
+        assert_eq!(message.subject, "Custom Test App");
+
+        fs::write(en.join("password_reset.txt"), "Only text").unwrap();
+        assert!(EmailTemplates::load(&dir, "en").is_err());
+
+        // The default locale must provide every template
+        fs::remove_file(en.join("password_reset.txt")).unwrap();
+        assert!(EmailTemplates::load(&dir, "de").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,22 +2,44 @@ use actix_web::{middleware::Logger, web, App, HttpServer};
 use dotenv::dotenv;
 use env_logger::Env;
 use sqlx::postgres::PgPoolOptions;
+use std::{path::Path, sync::Arc};
 
+mod commands;
 mod config;
 mod errors;
+mod geoip;
 mod handlers;
+mod mailer;
 mod middleware;
 mod models;
+mod rate_limit;
 mod routes;
 mod services;
+#[cfg(test)]
+mod test_support;
 
 use config::CONFIG;
-use routes::{configure_auth_routes, configure_public_routes};
-use services::AuthService;
+use geoip::GeoIp;
+use mailer::templates::EmailTemplates;
+use rate_limit::RateLimiter;
+use routes::{configure_admin_routes, configure_auth_routes, configure_public_routes};
+use services::{
+    AccountPurgeWorker, AdminService, AuditCheckpointWorker, AuditService, AuthService,
+    BulkUserService, DataExportService, DataExportWorker, NotificationService, OutboxService,
+    OutboxWorker, SessionService, WebhookService, WebhookWorker,
+};
 
 // Application state
 pub struct AppState {
     auth_service: AuthService,
+    admin_service: AdminService,
+    outbox_service: OutboxService,
+    data_export_service: DataExportService,
+    bulk_user_service: BulkUserService,
+    session_service: SessionService,
+    audit_service: AuditService,
+    webhook_service: WebhookService,
+    rate_limiter: Option<RateLimiter>,
 }
 
 #[actix_web::main]
@@ -48,6 +70,12 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Database connected successfully");
 
+    // One-off maintenance commands run instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, command_args)) = args.split_first() {
+        std::process::exit(commands::run(command, command_args, &db_pool).await);
+    }
+
     // Run migrations
     log::info!("Running database migrations...");
     sqlx::migrate!("./migrations")
@@ -57,11 +85,82 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Database migrations completed");
 
+    // Create the configured mailer
+    let mailer = mailer::mailer_from_config(&CONFIG).unwrap_or_else(|e| {
+        log::error!("Mailer configuration failed: {e}");
+        std::process::exit(1);
+    });
+
+    // Load email templates, overriding the built-in ones from the template directory
+    let templates = EmailTemplates::load(
+        Path::new(&CONFIG.email_template_dir),
+        &CONFIG.email_default_locale,
+    )
+    .unwrap_or_else(|e| {
+        log::error!("Email template loading failed: {e}");
+        std::process::exit(1);
+    });
+
+    // Open the IP geolocation database for the suspicious-login checks, if configured
+    let geoip = CONFIG.geoip_database.as_ref().map(|path| {
+        GeoIp::open(path).map(Arc::new).unwrap_or_else(|e| {
+            log::error!("GeoIP database loading failed: {e}");
+            std::process::exit(1);
+        })
+    });
+
     // Create services
-    let auth_service = AuthService::new(db_pool.clone());
+    let notifications = NotificationService::new(Arc::new(templates));
+    let auth_service = AuthService::new(db_pool.clone(), notifications.clone(), geoip);
+    let admin_service = AdminService::new(db_pool.clone(), notifications);
+    let outbox_service = OutboxService::new(db_pool.clone());
+    let data_export_service = DataExportService::new(db_pool.clone());
+    let bulk_user_service = BulkUserService::new(db_pool.clone());
+    let session_service = SessionService::new(db_pool.clone());
+    let audit_service = AuditService::new(db_pool.clone());
+    let webhook_service = WebhookService::new(db_pool.clone());
+    let rate_limiter = RateLimiter::from_config(&CONFIG, db_pool.clone());
+    log::info!("Rate limit backend: {}", CONFIG.rate_limit_backend);
+
+    // Deliver queued emails in the background
+    OutboxWorker::new(outbox_service.clone(), mailer).spawn();
+    log::info!("Outbox worker started");
+
+    // Send queued webhook deliveries
+    WebhookWorker::new(webhook_service.clone())
+        .unwrap_or_else(|e| {
+            log::error!("Webhook HTTP client creation failed: {e}");
+            std::process::exit(1);
+        })
+        .spawn();
+    log::info!("Webhook worker started");
+
+    // Erase self-deleted accounts once their grace period ends
+    AccountPurgeWorker::new(db_pool.clone()).spawn();
+    log::info!("Account purge worker started");
+
+    // Export signed checkpoints of the audit log's hash chain
+    if let Some(path) = &CONFIG.audit_checkpoint_file {
+        AuditCheckpointWorker::new(audit_service.clone(), path).spawn();
+        log::info!("Audit checkpoint worker started");
+    }
+
+    // Generate requested personal data exports
+    DataExportWorker::new(data_export_service.clone()).spawn();
+    log::info!("Data export worker started");
 
     // Create application state
-    let app_state = AppState { auth_service };
+    let app_state = AppState {
+        auth_service,
+        admin_service,
+        outbox_service,
+        data_export_service,
+        bulk_user_service,
+        session_service,
+        audit_service,
+        webhook_service,
+        rate_limiter,
+    };
 
     log::info!("Starting HTTP server on {}", CONFIG.server_address());
 
@@ -70,6 +169,19 @@ async fn main() -> std::io::Result<()> {
         App::new()
             // Add application state
             .app_data(web::Data::new(app_state.auth_service.clone()))
+            .app_data(web::Data::new(app_state.admin_service.clone()))
+            .app_data(web::Data::new(app_state.outbox_service.clone()))
+            .app_data(web::Data::new(app_state.data_export_service.clone()))
+            .app_data(web::Data::new(app_state.bulk_user_service.clone()))
+            .app_data(web::Data::new(app_state.session_service.clone()))
+            .app_data(web::Data::new(app_state.audit_service.clone()))
+            .app_data(web::Data::new(app_state.webhook_service.clone()))
+            .configure(|cfg| {
+                // Shared by all workers, so in-memory buckets are per process
+                if let Some(rate_limiter) = &app_state.rate_limiter {
+                    cfg.app_data(web::Data::new(rate_limiter.clone()));
+                }
+            })
             // Add middleware
             .wrap(Logger::default())
             .wrap(
@@ -87,13 +199,14 @@ async fn main() -> std::io::Result<()> {
                 actix_cors::Cors::default()
                     .allowed_origin("http://localhost:3000") // Adjust for your frontend
                     .allowed_origin("http://127.0.0.1:3000")
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
                     .allowed_headers(vec!["Authorization", "Content-Type", "X-Requested-With"])
                     .supports_credentials()
                     .max_age(3600),
             )
             // Configure routes
             .configure(configure_auth_routes)
+            .configure(configure_admin_routes)
             .configure(configure_public_routes)
             // Add a catch-all route for unmatched requests
             .default_service(web::route().to(|| async {
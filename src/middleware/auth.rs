@@ -1,6 +1,6 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpMessage,
+    web, Error, HttpMessage,
 };
 use futures_util::future::LocalBoxFuture;
 use jsonwebtoken::{decode, DecodingKey, Validation};
@@ -10,7 +10,12 @@ use std::{
 };
 use uuid::Uuid;
 
-use crate::{config::CONFIG, errors::ServiceError, models::auth_user::Claims};
+use crate::{
+    config::CONFIG,
+    errors::ServiceError,
+    models::auth_user::{AuthUser, Claims, PASSWORD_CHANGE_SCOPE},
+    services::SessionService,
+};
 
 // Middleware factory
 pub struct JwtAuth;
@@ -66,13 +71,36 @@ where
                 if let Some(token) = auth_header.strip_prefix("Bearer ") {
                     match verify_jwt_token(token) {
                         Ok(claims) => {
+                            // Scoped tokens only work on the endpoints their scope allows
+                            if let Some(scope) = &claims.scope {
+                                if !scope_allows_path(scope, req.path()) {
+                                    return Err(actix_web::error::ErrorForbidden(
+                                        ServiceError::Forbidden,
+                                    ));
+                                }
+                            }
+
+                            let user_id: Uuid =
+                                claims.sub.parse().map_err(|_| ServiceError::InvalidToken)?;
+
+                            // Tokens of revoked sessions are rejected; tokens without a session
+                            // are accepted until they expire
+                            if let (Some(session_id), Some(sessions)) =
+                                (claims.sid, req.app_data::<web::Data<SessionService>>())
+                            {
+                                if !sessions.is_active(user_id, session_id).await? {
+                                    return Err(actix_web::error::ErrorUnauthorized(
+                                        ServiceError::InvalidToken,
+                                    ));
+                                }
+                            }
+
                             // Add user info to request extensions
                             req.extensions_mut().insert(AuthenticatedUser {
-                                user_id: claims
-                                    .sub
-                                    .parse()
-                                    .map_err(|_| ServiceError::InvalidToken)?,
+                                user_id,
                                 email: claims.email,
+                                is_admin: claims.is_admin,
+                                session_id: claims.sid,
                             });
                         }
                         Err(e) => {
@@ -149,10 +177,12 @@ where
             if let Some(auth_header) = auth_header {
                 if let Some(token) = auth_header.strip_prefix("Bearer ") {
                     if let Ok(claims) = verify_jwt_token(token) {
-                        if let Ok(user_id) = claims.sub.parse() {
+                        if let (Ok(user_id), None) = (claims.sub.parse(), &claims.scope) {
                             req.extensions_mut().insert(AuthenticatedUser {
                                 user_id,
                                 email: claims.email,
+                                is_admin: claims.is_admin,
+                                session_id: claims.sid,
                             });
                         }
                     }
@@ -170,6 +200,19 @@ where
 pub struct AuthenticatedUser {
     pub user_id: Uuid,
     pub email: String,
+    pub is_admin: bool,
+    pub session_id: Option<Uuid>, // login session the token belongs to
+}
+
+/// Only route accepting tokens with the password change scope
+pub const PASSWORD_CHANGE_PATH: &str = "/api/v1/auth/user/change-password";
+
+// Check whether a scoped token may be used for the given request path
+fn scope_allows_path(scope: &str, path: &str) -> bool {
+    match scope {
+        PASSWORD_CHANGE_SCOPE => path == PASSWORD_CHANGE_PATH,
+        _ => false,
+    }
 }
 
 // JWT token verification function
@@ -183,11 +226,32 @@ pub fn verify_jwt_token(token: &str) -> Result<Claims, ServiceError> {
 }
 
 // JWT token generation function
-pub fn generate_jwt_token(user_id: Uuid, email: String) -> Result<String, ServiceError> {
-    let claims = Claims::new(user_id, email, CONFIG.jwt_expiration);
+pub fn generate_jwt_token(
+    user: &AuthUser,
+    session_id: Option<Uuid>,
+) -> Result<String, ServiceError> {
+    encode_jwt_token(&Claims::for_user(user, CONFIG.jwt_expiration).with_session(session_id))
+}
+
+// Restricted token that only allows the user to change their password
+pub fn generate_password_change_token(
+    user: &AuthUser,
+    session_id: Option<Uuid>,
+) -> Result<String, ServiceError> {
+    let claims = Claims::new(
+        user.id,
+        user.email.clone(),
+        CONFIG.password_change_token_expiration,
+    )
+    .with_scope(PASSWORD_CHANGE_SCOPE)
+    .with_session(session_id);
+    encode_jwt_token(&claims)
+}
+
+fn encode_jwt_token(claims: &Claims) -> Result<String, ServiceError> {
     let encoding_key = jsonwebtoken::EncodingKey::from_secret(CONFIG.jwt_secret_bytes());
 
-    jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &encoding_key)
+    jsonwebtoken::encode(&jsonwebtoken::Header::default(), claims, &encoding_key)
         .map_err(ServiceError::from)
 }
 
@@ -195,6 +259,15 @@ pub fn generate_jwt_token(user_id: Uuid, email: String) -> Result<String, Servic
 pub trait AuthenticatedUserExt {
     fn authenticated_user(&self) -> Option<AuthenticatedUser>;
     fn require_authenticated_user(&self) -> Result<AuthenticatedUser, ServiceError>;
+
+    fn require_admin_user(&self) -> Result<AuthenticatedUser, ServiceError> {
+        let user = self.require_authenticated_user()?;
+        if user.is_admin {
+            Ok(user)
+        } else {
+            Err(ServiceError::Forbidden)
+        }
+    }
 }
 
 impl AuthenticatedUserExt for ServiceRequest {
@@ -263,6 +336,50 @@ mod tests {
         assert!(resp.is_err());
     }
 
+    #[actix_web::test]
+    async fn test_password_change_token_is_restricted() {
+        let user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+        let token = generate_password_change_token(&user, None).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .service(
+                    web::scope("/api/v1/auth/user")
+                        .wrap(JwtAuth)
+                        .route("/info", web::get().to(protected_handler))
+                        .route("/change-password", web::post().to(protected_handler)),
+                )
+                .service(
+                    web::scope("/api/v1/admin")
+                        .wrap(JwtAuth)
+                        .route("/user/change-password", web::post().to(protected_handler)),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/auth/user/info")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::try_call_service(&app, req).await;
+        assert!(resp.is_err());
+
+        // Other routes ending in the same path are not allowed either
+        let req = test::TestRequest::post()
+            .uri("/api/v1/admin/user/change-password")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::try_call_service(&app, req).await;
+        assert!(resp.is_err());
+
+        let req = test::TestRequest::post()
+            .uri(PASSWORD_CHANGE_PATH)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
     #[actix_web::test]
     async fn test_optional_jwt_auth_middleware_without_token() {
         async fn optional_handler(req: actix_web::HttpRequest) -> HttpResponse {
@@ -1,3 +1,4 @@
 pub mod auth;
+pub mod rate_limit;
 
 // pub use auth::*; // Commented out due to unused import
@@ -0,0 +1,284 @@
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    web::{self, Bytes},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::{
+    models::{email::email_identity_key, request_context::client_ip, token::hash_token},
+    rate_limit::{RateLimitRule, RateLimiter},
+};
+
+/// Rate limits a route per client IP and per target account.
+///
+/// The account is the `email` (or `username`) field of the JSON body, so guessing passwords
+/// for one account from many addresses is limited too. `per_ip_and_account` limits each
+/// client IP's requests for an account instead, so a client exhausting it doesn't lock the
+/// account out for everyone else. Requests pass unchecked when no `RateLimiter` is
+/// registered, i.e. with `RATE_LIMIT_BACKEND=none`.
+#[derive(Clone)]
+pub struct RateLimit {
+    route: &'static str,
+    per_ip: Option<RateLimitRule>,
+    per_account: Option<RateLimitRule>,
+    per_ip_and_account: Option<RateLimitRule>,
+}
+
+impl RateLimit {
+    /// `route` names the buckets, so routes sharing a name share their limits
+    pub fn new(route: &'static str) -> Self {
+        Self {
+            route,
+            per_ip: None,
+            per_account: None,
+            per_ip_and_account: None,
+        }
+    }
+
+    pub fn per_ip(mut self, rule: RateLimitRule) -> Self {
+        self.per_ip = Some(rule);
+        self
+    }
+
+    pub fn per_account(mut self, rule: RateLimitRule) -> Self {
+        self.per_account = Some(rule);
+        self
+    }
+
+    pub fn per_ip_and_account(mut self, rule: RateLimitRule) -> Self {
+        self.per_ip_and_account = Some(rule);
+        self
+    }
+}
+
+// Middleware factory
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            policy: self.clone(),
+        }))
+    }
+}
+
+// Middleware service
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    policy: RateLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+                return service.call(req).await;
+            };
+
+            let ip = client_ip(req.request());
+            if let (Some(rule), Some(ip)) = (policy.per_ip, &ip) {
+                let key = format!("{}:ip:{}", policy.route, ip);
+                limiter.check(&key, rule).await?;
+            }
+
+            if policy.per_account.is_some() || policy.per_ip_and_account.is_some() {
+                // Read the body and put it back for the handler
+                let body = req.extract::<Bytes>().await?;
+                let account = account_key(&body).map(|account| hash_token(&account));
+                req.set_payload(Payload::from(body));
+
+                if let (Some(rule), Some(account)) = (policy.per_account, &account) {
+                    let key = format!("{}:account:{}", policy.route, account);
+                    limiter.check(&key, rule).await?;
+                }
+                if let (Some(rule), Some(account), Some(ip)) =
+                    (policy.per_ip_and_account, &account, &ip)
+                {
+                    let key = format!("{}:ip_account:{}:{}", policy.route, ip, account);
+                    limiter.check(&key, rule).await?;
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+// The account a JSON body targets; hashed before use so buckets don't hold email addresses
+fn account_key(body: &[u8]) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let field = |name: &str| {
+        body.get(name)
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(email) = field("email") {
+        Some(format!("email:{}", email_identity_key(email)))
+    } else {
+        field("username").map(|username| format!("username:{}", username.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::MemoryStore;
+    use actix_web::{http::StatusCode, test, App, HttpResponse};
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn test_account_key() {
+        assert_eq!(
+            account_key(br#"{"email": " Alice@Example.COM ", "password": "x"}"#),
+            Some("email:alice@example.com".to_string())
+        );
+        assert_eq!(
+            account_key(br#"{"username": "Alice_1"}"#),
+            Some("username:alice_1".to_string())
+        );
+        assert_eq!(account_key(br#"{"email": ""}"#), None);
+        assert_eq!(account_key(b"not json"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_per_account_and_ip() {
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(limiter)).service(
+                web::resource("/login")
+                    .wrap(
+                        RateLimit::new("login")
+                            .per_ip(RateLimitRule::new(3, 3600))
+                            .per_account(RateLimitRule::new(2, 3600)),
+                    )
+                    // The handler still sees the body the middleware read
+                    .route(
+                        web::post().to(|body: String| async move { HttpResponse::Ok().body(body) }),
+                    ),
+            ),
+        )
+        .await;
+
+        let login = |email: &str| {
+            test::TestRequest::post()
+                .uri("/login")
+                .peer_addr("203.0.113.7:5000".parse().unwrap())
+                .set_json(serde_json::json!({ "email": email }))
+                .to_request()
+        };
+
+        for _ in 0..2 {
+            let resp = test::call_service(&app, login("alice@example.com")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                test::read_body(resp).await,
+                Bytes::from_static(br#"{"email":"alice@example.com"}"#)
+            );
+        }
+
+        // The account is out of attempts
+        let err = test::try_call_service(&app, login("ALICE@example.com"))
+            .await
+            .unwrap_err();
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get("Retry-After").unwrap(), "1800");
+
+        // So is the IP, whatever account it targets
+        let err = test::try_call_service(&app, login("bob@example.com"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_response().status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_per_ip_and_account() {
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(limiter)).service(
+                web::resource("/login")
+                    .wrap(
+                        RateLimit::new("login")
+                            .per_ip(RateLimitRule::new(10, 3600))
+                            .per_ip_and_account(RateLimitRule::new(2, 3600)),
+                    )
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let login = |ip: &str, email: &str| {
+            test::TestRequest::post()
+                .uri("/login")
+                .peer_addr(format!("{ip}:5000").parse().unwrap())
+                .set_json(serde_json::json!({ "email": email }))
+                .to_request()
+        };
+
+        for _ in 0..2 {
+            let resp = test::call_service(&app, login("203.0.113.7", "alice@example.com")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let err = test::try_call_service(&app, login("203.0.113.7", "alice@example.com"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_response().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Another IP can still log in to the account, and the first IP to other accounts
+        let resp = test::call_service(&app, login("198.51.100.2", "alice@example.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, login("203.0.113.7", "bob@example.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_disabled_without_limiter() {
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/login")
+                    .wrap(RateLimit::new("login").per_ip(RateLimitRule::new(1, 3600)))
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri("/login")
+                .peer_addr("203.0.113.7:5000".parse().unwrap())
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::auth_user::AuthUser;
+
+/// Escape `LIKE` wildcards so user input only matches literally
+pub fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("{escaped}%")
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePasswordPolicyRequest {
+    pub must_change_password: Option<bool>,
+    pub password_expires: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UserListQuery {
+    pub verified: Option<bool>,
+    pub active: Option<bool>,
+    pub locked: Option<bool>,
+    pub created_after: Option<DateTime<Utc>>,  // inclusive
+    pub created_before: Option<DateTime<Utc>>, // exclusive
+
+    // Matched case-insensitively against the start of the email
+    #[validate(length(min = 1, max = 255, message = "Email prefix must be 1-255 characters"))]
+    pub email_prefix: Option<String>,
+
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200"))]
+    pub limit: Option<i64>,
+
+    // next_cursor of the previous page
+    pub cursor: Option<String>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct AdminUserInfo {
+    pub id: Uuid,
+    pub email: String,
+    pub pending_email: Option<String>,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub is_admin: bool,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub password_changed_at: DateTime<Utc>,
+    pub password_expires: bool,
+    pub must_change_password: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+}
+
+impl From<AuthUser> for AdminUserInfo {
+    fn from(user: AuthUser) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            pending_email: user.pending_email,
+            username: user.username,
+            display_name: user.display_name,
+            locale: user.locale,
+            is_active: user.is_active,
+            is_verified: user.is_verified,
+            is_admin: user.is_admin,
+            failed_login_attempts: user.failed_login_attempts,
+            locked_until: user.locked_until,
+            password_changed_at: user.password_changed_at,
+            password_expires: user.password_expires,
+            must_change_password: user.must_change_password,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            last_login: user.last_login,
+            deletion_scheduled_at: user.deletion_scheduled_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserListResponse {
+    pub users: Vec<AdminUserInfo>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_user_info_omits_secrets() {
+        let user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+        let info = serde_json::to_value(AdminUserInfo::from(user)).unwrap();
+
+        assert_eq!(info["email"], "test@example.com");
+        assert!(info.get("password_hash").is_none());
+        assert!(info.get("verification_token").is_none());
+        assert!(info.get("reset_token").is_none());
+    }
+
+    #[test]
+    fn test_like_prefix_escapes_wildcards() {
+        assert_eq!(like_prefix("alice"), "alice%");
+        assert_eq!(like_prefix("a_b%c"), "a\\_b\\%c%");
+        assert_eq!(like_prefix("a\\b"), "a\\\\b%");
+    }
+}
@@ -0,0 +1,484 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{
+    login_event::{LoginEvent, LoginFailure},
+    request_context::RequestContext,
+    token::{hmac_sha256_hex, hmac_sha256_hex_matches},
+};
+
+/// Metadata keys holding email addresses, removed when the account is erased
+pub const AUDIT_PERSONAL_METADATA: [&str; 2] = ["previous_email", "new_email"];
+
+// Domain separation, so the HMACs cannot be confused with each other
+const CHAIN_CONTEXT: &str = "audit-chain";
+const PERSONAL_CONTEXT: &str = "audit-personal";
+const CHECKPOINT_CONTEXT: &str = "audit-checkpoint";
+
+/// Security-relevant action recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    UserRegistered,
+    LoginSucceeded,
+    LoginFailed,
+    LoginChallenged, // suspicious login held back until the emailed code is entered
+    LoginLocked,     // failed logins started a backoff block
+    Logout,
+    PasswordChanged,
+    PasswordResetRequested,
+    PasswordReset,
+    EmailVerified,
+    EmailChangeRequested,
+    EmailChanged,
+    AccountDeletionRequested,
+    AccountErased, // by the account purge worker once the grace period is over
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::UserRegistered => "user.registered",
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::LoginChallenged => "login.challenged",
+            AuditAction::LoginLocked => "login.locked",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordResetRequested => "password.reset_requested",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::EmailVerified => "email.verified",
+            AuditAction::EmailChangeRequested => "email.change_requested",
+            AuditAction::EmailChanged => "email.changed",
+            AuditAction::AccountDeletionRequested => "account.deletion_requested",
+            AuditAction::AccountErased => "account.erased",
+        }
+    }
+}
+
+/// Entry of the append-only audit log
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub action: String,
+    pub actor_id: Option<Uuid>, // who did it; None for unauthenticated requests
+    pub target_id: Option<Uuid>, // account it was done to
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub metadata: Json<Value>,
+    pub created_at: DateTime<Utc>,
+    pub sequence: i64, // position in the hash chain, assigned when recorded
+    #[serde(skip)]
+    pub personal_hmac: Option<String>,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>, // None for entries from before the chain
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, ctx: &RequestContext) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            action: action.as_str().to_string(),
+            actor_id: None,
+            target_id: None,
+            ip_address: ctx.ip_address.clone(),
+            user_agent: ctx.user_agent.clone(),
+            request_id: ctx.request_id.clone(),
+            metadata: Json(json!({})),
+            // Postgres keeps microseconds; the hash must match what is read back
+            created_at: Utc::now().trunc_subsecs(6),
+            sequence: 0,
+            personal_hmac: None,
+            prev_hash: None,
+            hash: None,
+        }
+    }
+
+    /// Action a user took on their own account
+    pub fn by_user(action: AuditAction, user_id: Uuid, ctx: &RequestContext) -> Self {
+        Self::new(action, ctx).actor(user_id).target(user_id)
+    }
+
+    /// Audit entry for a login attempt recorded in the login history
+    pub fn login(event: &LoginEvent, ctx: &RequestContext) -> Self {
+        let challenged =
+            event.failure_reason.as_deref() == Some(LoginFailure::ChallengeRequired.as_str());
+        let audit = match (event.success, challenged) {
+            (true, _) => Self::by_user(AuditAction::LoginSucceeded, event.user_id, ctx),
+            (false, true) => Self::new(AuditAction::LoginChallenged, ctx).target(event.user_id),
+            (false, false) => Self::new(AuditAction::LoginFailed, ctx).target(event.user_id),
+        };
+
+        audit.metadata(json!({
+            "method": event.method,
+            "failure_reason": event.failure_reason,
+            "session_id": event.session_id,
+            "anomalies": event.anomalies,
+            "location": event.location,
+        }))
+    }
+
+    pub fn actor(mut self, actor_id: Uuid) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn target(mut self, target_id: Uuid) -> Self {
+        self.target_id = Some(target_id);
+        self
+    }
+
+    /// Attach details of the action; null fields are dropped
+    pub fn metadata(mut self, metadata: Value) -> Self {
+        self.metadata = Json(match metadata {
+            Value::Object(fields) => {
+                Value::Object(fields.into_iter().filter(|(_, v)| !v.is_null()).collect())
+            }
+            other => other,
+        });
+        self
+    }
+
+    /// Link the entry to the chain at `sequence`, after the entry hashed to `prev_hash`
+    /// (empty for the first entry)
+    pub fn chain(&mut self, sequence: i64, prev_hash: String, key: &[u8]) {
+        self.sequence = sequence;
+        self.personal_hmac = Some(hmac_sha256_hex(key, self.personal_input().as_bytes()));
+        self.prev_hash = Some(prev_hash);
+        self.hash = Some(hmac_sha256_hex(key, self.chain_input().as_bytes()));
+    }
+
+    /// Check the entry's place in the chain. Personal data is only compared for entries
+    /// whose accounts were not erased, as erasing strips it.
+    pub fn check_link(
+        &self,
+        sequence: i64,
+        prev_hash: &str,
+        key: &[u8],
+        erased: bool,
+    ) -> Result<(), ChainBreak> {
+        if self.sequence != sequence {
+            return Err(ChainBreak::SequenceGap);
+        }
+        let (Some(hash), Some(stored_prev_hash), Some(personal_hmac)) =
+            (&self.hash, &self.prev_hash, &self.personal_hmac)
+        else {
+            return Err(ChainBreak::UnchainedEntry);
+        };
+
+        if stored_prev_hash != prev_hash {
+            return Err(ChainBreak::PrevHashMismatch);
+        }
+        if !hmac_sha256_hex_matches(key, self.chain_input().as_bytes(), hash) {
+            return Err(ChainBreak::HashMismatch);
+        }
+        if !erased && !hmac_sha256_hex_matches(key, self.personal_input().as_bytes(), personal_hmac)
+        {
+            return Err(ChainBreak::PersonalDataMismatch);
+        }
+        Ok(())
+    }
+
+    // Covers everything but the personal data, which is covered through its own HMAC
+    fn chain_input(&self) -> String {
+        let (_, retained) = split_personal_metadata(&self.metadata.0);
+        mac_input(
+            CHAIN_CONTEXT,
+            &json!([
+                self.sequence,
+                self.id,
+                self.action,
+                self.actor_id,
+                self.target_id,
+                self.request_id,
+                retained,
+                self.created_at.timestamp_micros(),
+                self.personal_hmac,
+                self.prev_hash,
+            ]),
+        )
+    }
+
+    fn personal_input(&self) -> String {
+        let (personal, _) = split_personal_metadata(&self.metadata.0);
+        mac_input(
+            PERSONAL_CONTEXT,
+            &json!([self.ip_address, self.user_agent, personal]),
+        )
+    }
+}
+
+/// Column list matching the fields of `AuditEvent`, for use with `query_as`
+pub const AUDIT_EVENT_COLUMNS: &str = r#"
+    id, action, actor_id, target_id, ip_address, user_agent, request_id, metadata, created_at,
+    sequence, personal_hmac, prev_hash, hash
+"#;
+
+/// Latest entry of the hash chain
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AuditChainHead {
+    pub sequence: i64,
+    pub hash: String,
+}
+
+/// Signed record of the chain head, exported so that rewriting or truncating the chain can be
+/// detected even by someone holding the key and the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditCheckpoint {
+    pub sequence: i64,
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl AuditCheckpoint {
+    pub fn new(head: AuditChainHead, key: &[u8]) -> Self {
+        let mut checkpoint = Self {
+            sequence: head.sequence,
+            hash: head.hash,
+            created_at: Utc::now().trunc_subsecs(6),
+            signature: String::new(),
+        };
+        checkpoint.signature = hmac_sha256_hex(key, checkpoint.signed_input().as_bytes());
+        checkpoint
+    }
+
+    pub fn signature_valid(&self, key: &[u8]) -> bool {
+        hmac_sha256_hex_matches(key, self.signed_input().as_bytes(), &self.signature)
+    }
+
+    fn signed_input(&self) -> String {
+        mac_input(
+            CHECKPOINT_CONTEXT,
+            &json!([self.sequence, self.hash, self.created_at.timestamp_micros()]),
+        )
+    }
+}
+
+/// Why verifying the chain stopped at an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainBreak {
+    SequenceGap,          // entries missing or out of order
+    UnchainedEntry,       // entry without a hash after the chain started
+    PrevHashMismatch,     // entry doesn't follow the one before it
+    HashMismatch,         // entry was modified
+    PersonalDataMismatch, // IP, user agent or emails modified
+    CheckpointMismatch,   // chain differs from an exported checkpoint
+    CheckpointSignature,  // checkpoint wasn't signed with the key
+    MissingEntries,       // a checkpoint is past the end of the chain
+}
+
+// Message HMACs are computed over: the context and the canonical form of `fields`
+fn mac_input(context: &str, fields: &Value) -> String {
+    format!("{context}:{}", canonical_json(fields))
+}
+
+// JSON with object keys sorted, so the same value always hashes the same; JSONB reorders keys
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(name, value)| {
+                    format!("{}:{}", Value::from(name.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+// Split metadata into the fields erased with the account and the rest
+fn split_personal_metadata(metadata: &Value) -> (Value, Value) {
+    let Value::Object(fields) = metadata else {
+        return (Value::Null, metadata.clone());
+    };
+    let (personal, retained): (Map<String, Value>, Map<String, Value>) = fields
+        .clone()
+        .into_iter()
+        .partition(|(name, _)| AUDIT_PERSONAL_METADATA.contains(&name.as_str()));
+    (Value::Object(personal), Value::Object(retained))
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct AuditEventQuery {
+    // Events the user did or that were done to their account
+    pub user_id: Option<Uuid>,
+
+    #[validate(length(min = 1, max = 64, message = "Action must be 1-64 characters"))]
+    pub action: Option<String>,
+
+    pub from: Option<DateTime<Utc>>, // inclusive
+    pub to: Option<DateTime<Utc>>,   // exclusive
+
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200"))]
+    pub limit: Option<i64>,
+
+    // next_cursor of the previous page
+    pub cursor: Option<String>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct AuditEventListResponse {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrokenLink {
+    pub sequence: i64,
+    pub id: Option<Uuid>, // None if the entry is missing
+    pub reason: ChainBreak,
+}
+
+/// Outcome of walking the hash chain from the first entry
+#[derive(Debug, Default, Serialize)]
+pub struct AuditChainReport {
+    pub valid: bool,
+    pub entries: i64,       // chained entries verified
+    pub unchained: i64,     // entries from before the chain, not verifiable
+    pub checkpoints: usize, // exported checkpoints matched
+    pub head: Option<AuditChainHead>,
+    pub broken_link: Option<BrokenLink>, // the first one
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::login_event::LoginMethod;
+
+    #[test]
+    fn test_login_audit_events() {
+        let user_id = Uuid::new_v4();
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.7".to_string()),
+            request_id: Some("req-1".to_string()),
+            ..Default::default()
+        };
+
+        let success =
+            LoginEvent::succeeded(user_id, LoginMethod::EmailPassword, Uuid::new_v4(), &ctx);
+        let audit = AuditEvent::login(&success, &ctx);
+        assert_eq!(audit.action, "login.succeeded");
+        assert_eq!(audit.actor_id, Some(user_id));
+        assert_eq!(audit.request_id.as_deref(), Some("req-1"));
+        assert_eq!(audit.metadata.0["method"], "email_password");
+        assert!(audit.metadata.0.get("failure_reason").is_none());
+
+        let failure = LoginEvent::failed(
+            user_id,
+            LoginMethod::EmailPassword,
+            LoginFailure::InvalidCredentials,
+            &ctx,
+        );
+        let audit = AuditEvent::login(&failure, &ctx);
+        assert_eq!(audit.action, "login.failed");
+        assert_eq!(audit.actor_id, None);
+        assert_eq!(audit.target_id, Some(user_id));
+        assert_eq!(audit.metadata.0["failure_reason"], "invalid_credentials");
+
+        let challenged = LoginEvent::failed(
+            user_id,
+            LoginMethod::EmailPassword,
+            LoginFailure::ChallengeRequired,
+            &ctx,
+        );
+        assert_eq!(
+            AuditEvent::login(&challenged, &ctx).action,
+            "login.challenged"
+        );
+    }
+
+    #[test]
+    fn test_chain_detects_changes() {
+        let key = b"this_is_a_very_long_secret_key_for_audit_chain";
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.7".to_string()),
+            ..Default::default()
+        };
+        let mut first = AuditEvent::by_user(AuditAction::EmailChanged, Uuid::new_v4(), &ctx)
+            .metadata(
+                json!({ "previous_email": "old@example.com", "new_email": "new@example.com" }),
+            );
+        first.chain(1, String::new(), key);
+        let mut second = AuditEvent::new(AuditAction::LoginFailed, &ctx);
+        second.chain(2, first.hash.clone().unwrap(), key);
+
+        assert_eq!(first.check_link(1, "", key, false), Ok(()));
+        assert_eq!(
+            second.check_link(2, first.hash.as_deref().unwrap(), key, false),
+            Ok(())
+        );
+        assert_eq!(
+            second.check_link(2, "other", key, false),
+            Err(ChainBreak::PrevHashMismatch)
+        );
+        assert_eq!(
+            second.check_link(3, "", key, false),
+            Err(ChainBreak::SequenceGap)
+        );
+        assert_eq!(
+            first.check_link(1, "", b"another_key_that_is_long_enough_to_use", false),
+            Err(ChainBreak::HashMismatch)
+        );
+
+        let mut edited = first.clone();
+        edited.action = "logout".to_string();
+        assert_eq!(
+            edited.check_link(1, "", key, false),
+            Err(ChainBreak::HashMismatch)
+        );
+
+        // Erasing personal data keeps the chain intact, but only for erased accounts
+        let mut erased = first.clone();
+        erased.ip_address = None;
+        erased.metadata = Json(json!({}));
+        assert_eq!(erased.check_link(1, "", key, true), Ok(()));
+        assert_eq!(
+            erased.check_link(1, "", key, false),
+            Err(ChainBreak::PersonalDataMismatch)
+        );
+
+        // Key order doesn't matter, as JSONB doesn't keep it
+        let mut reordered = first.clone();
+        reordered.metadata = Json(
+            serde_json::from_str(
+                r#"{"new_email":"new@example.com","previous_email":"old@example.com"}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(reordered.check_link(1, "", key, false), Ok(()));
+    }
+
+    #[test]
+    fn test_checkpoint_signature() {
+        let key = b"this_is_a_very_long_secret_key_for_audit_chain";
+        let head = AuditChainHead {
+            sequence: 42,
+            hash: "abc".to_string(),
+        };
+        let checkpoint = AuditCheckpoint::new(head, key);
+        assert!(checkpoint.signature_valid(key));
+
+        let line = serde_json::to_string(&checkpoint).unwrap();
+        let mut parsed: AuditCheckpoint = serde_json::from_str(&line).unwrap();
+        assert!(parsed.signature_valid(key));
+
+        parsed.sequence = 41;
+        assert!(!parsed.signature_valid(key));
+    }
+}
@@ -4,6 +4,21 @@ use sqlx::FromRow;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::{
+    config::CONFIG,
+    models::{
+        email::{deserialize_email, deserialize_optional_email},
+        login_throttle::{retry_after, LoginBackoff},
+        password_strength::PasswordStrengthResponse,
+        profile::{
+            deserialize_patch, validate_avatar_url, validate_display_name, validate_locale,
+            validate_timezone,
+        },
+        token::{generate_token, hash_token, token_matches},
+        username::{deserialize_username, validate_username},
+    },
+};
+
 #[derive(Debug, Clone, FromRow, Serialize)]
 pub struct AuthUser {
     pub id: Uuid,
@@ -16,11 +31,42 @@ pub struct AuthUser {
     pub last_login: Option<DateTime<Utc>>,
     pub failed_login_attempts: i32,
     pub locked_until: Option<DateTime<Utc>>,
-    pub verification_token: Option<String>,
-    pub reset_token: Option<String>,
+    pub verification_token: Option<String>, // SHA-256 of the emailed token
+    pub verification_token_expires: Option<DateTime<Utc>>,
+    pub reset_token: Option<String>, // SHA-256 of the emailed token
     pub reset_token_expires: Option<DateTime<Utc>>,
+    pub password_changed_at: DateTime<Utc>,
+    pub password_expires: bool,
+    pub must_change_password: bool,
+    pub is_admin: bool,
+    pub locale: Option<String>, // preferred language for emails, e.g. "es"
+    pub pending_email: Option<String>, // requested new address, awaiting confirmation
+    pub email_change_token: Option<String>, // SHA-256 of the emailed token
+    pub email_change_token_expires: Option<DateTime<Utc>>,
+    pub username: Option<String>, // optional handle usable instead of the email to log in
+    pub display_name: Option<String>,
+    pub timezone: Option<String>, // IANA name, e.g. "Europe/Madrid"
+    pub avatar_url: Option<String>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>, // self-deleted accounts are erased after this
 }
 
+/// Column list matching the fields of `AuthUser`, for use with `query_as`
+pub const AUTH_USER_COLUMNS: &str = r#"
+    id, email, password_hash, is_active, is_verified,
+    created_at, updated_at, last_login, failed_login_attempts,
+    locked_until, verification_token, verification_token_expires,
+    reset_token, reset_token_expires,
+    password_changed_at, password_expires, must_change_password, is_admin, locale,
+    pending_email, email_change_token, email_change_token_expires, username,
+    display_name, timezone, avatar_url, deletion_scheduled_at
+"#;
+
+/// JWT scope for tokens that may only be used to change the password
+pub const PASSWORD_CHANGE_SCOPE: &str = "password_change";
+
+/// Longest password accepted, in characters; zxcvbn slows down quickly on longer input
+pub const PASSWORD_MAX_LENGTH: usize = 128;
+
 impl AuthUser {
     pub fn new(email: String, password_hash: String) -> Self {
         let now = Utc::now();
@@ -35,32 +81,55 @@ impl AuthUser {
             last_login: None,
             failed_login_attempts: 0,
             locked_until: None,
-            verification_token: Some(Uuid::new_v4().to_string()),
+            verification_token: None,
+            verification_token_expires: None,
             reset_token: None,
             reset_token_expires: None,
+            password_changed_at: now,
+            password_expires: false,
+            must_change_password: false,
+            is_admin: false,
+            locale: None,
+            pending_email: None,
+            email_change_token: None,
+            email_change_token_expires: None,
+            username: None,
+            display_name: None,
+            timezone: None,
+            avatar_url: None,
+            deletion_scheduled_at: None,
         }
     }
 
-    pub fn is_locked(&self) -> bool {
-        if let Some(locked_until) = self.locked_until {
-            Utc::now() < locked_until
-        } else {
-            false
-        }
+    /// Seconds until logins from unknown IPs are allowed again, while failed logins block them
+    pub fn login_retry_after(&self) -> Option<u64> {
+        retry_after(self.locked_until)
     }
 
-    pub fn can_login(&self) -> bool {
-        self.is_active && !self.is_locked()
+    /// Whether the user deleted the account and it is waiting to be erased
+    pub fn is_deletion_pending(&self) -> bool {
+        self.deletion_scheduled_at.is_some()
     }
 
-    pub fn increment_failed_attempts(&mut self) {
-        self.failed_login_attempts += 1;
+    /// Deactivate the account and schedule its erasure after the grace period
+    pub fn schedule_deletion(&mut self, grace_days: i64) {
+        self.is_active = false;
+        self.deletion_scheduled_at = Some(Utc::now() + chrono::Duration::days(grace_days));
+        self.updated_at = Utc::now();
+    }
 
-        // Lock account after 5 failed attempts for 15 minutes
-        if self.failed_login_attempts >= 5 {
-            self.locked_until = Some(Utc::now() + chrono::Duration::minutes(15));
-        }
+    pub fn cancel_deletion(&mut self) {
+        self.is_active = true;
+        self.deletion_scheduled_at = None;
+        self.updated_at = Utc::now();
+    }
 
+    /// Count a failed login, blocking logins from unknown IPs as `backoff` dictates
+    pub fn increment_failed_attempts(&mut self, backoff: &LoginBackoff) {
+        self.failed_login_attempts += 1;
+        self.locked_until = backoff
+            .delay(self.failed_login_attempts)
+            .map(|delay| Utc::now() + delay);
         self.updated_at = Utc::now();
     }
 
@@ -71,10 +140,13 @@ impl AuthUser {
         self.updated_at = Utc::now();
     }
 
-    pub fn generate_reset_token(&mut self) {
-        self.reset_token = Some(Uuid::new_v4().to_string());
+    /// Generate a new password reset token, storing only its hash; returns the raw token
+    pub fn generate_reset_token(&mut self) -> String {
+        let token = generate_token();
+        self.reset_token = Some(hash_token(&token));
         self.reset_token_expires = Some(Utc::now() + chrono::Duration::hours(1));
         self.updated_at = Utc::now();
+        token
     }
 
     pub fn clear_reset_token(&mut self) {
@@ -86,7 +158,27 @@ impl AuthUser {
     pub fn is_reset_token_valid(&self, token: &str) -> bool {
         if let (Some(stored_token), Some(expires)) = (&self.reset_token, &self.reset_token_expires)
         {
-            stored_token == token && Utc::now() < *expires
+            token_matches(token, stored_token) && Utc::now() < *expires
+        } else {
+            false
+        }
+    }
+
+    /// Generate a new email verification token, storing only its hash; returns the raw token
+    pub fn generate_verification_token(&mut self, expires_in_seconds: i64) -> String {
+        let token = generate_token();
+        self.verification_token = Some(hash_token(&token));
+        self.verification_token_expires =
+            Some(Utc::now() + chrono::Duration::seconds(expires_in_seconds));
+        self.updated_at = Utc::now();
+        token
+    }
+
+    pub fn is_verification_token_valid(&self, token: &str) -> bool {
+        if let (Some(stored_token), Some(expires)) =
+            (&self.verification_token, &self.verification_token_expires)
+        {
+            token_matches(token, stored_token) && Utc::now() < *expires
         } else {
             false
         }
@@ -95,38 +187,147 @@ impl AuthUser {
     pub fn verify_email(&mut self) {
         self.is_verified = true;
         self.verification_token = None;
+        self.verification_token_expires = None;
+        self.updated_at = Utc::now();
+    }
+
+    /// Start an email change to `new_email`, storing only the token hash; returns the raw token
+    pub fn generate_email_change_token(
+        &mut self,
+        new_email: String,
+        expires_in_seconds: i64,
+    ) -> String {
+        let token = generate_token();
+        self.pending_email = Some(new_email);
+        self.email_change_token = Some(hash_token(&token));
+        self.email_change_token_expires =
+            Some(Utc::now() + chrono::Duration::seconds(expires_in_seconds));
+        self.updated_at = Utc::now();
+        token
+    }
+
+    pub fn is_email_change_token_valid(&self, token: &str) -> bool {
+        if let (Some(_), Some(stored_token), Some(expires)) = (
+            &self.pending_email,
+            &self.email_change_token,
+            &self.email_change_token_expires,
+        ) {
+            token_matches(token, stored_token) && Utc::now() < *expires
+        } else {
+            false
+        }
+    }
+
+    /// Swap in the confirmed pending email; the new address is verified by the confirmation
+    pub fn confirm_email_change(&mut self) {
+        if let Some(new_email) = self.pending_email.take() {
+            self.email = new_email;
+            self.is_verified = true;
+            self.verification_token = None;
+            self.verification_token_expires = None;
+        }
+        self.email_change_token = None;
+        self.email_change_token_expires = None;
+        self.updated_at = Utc::now();
+    }
+
+    /// Apply the fields present in a profile update; `Some(None)` clears a field
+    pub fn update_profile(&mut self, update: UpdateProfileRequest) {
+        if let Some(display_name) = update.display_name {
+            self.display_name = display_name;
+        }
+        if let Some(locale) = update.locale {
+            self.locale = locale;
+        }
+        if let Some(timezone) = update.timezone {
+            self.timezone = timezone;
+        }
+        if let Some(avatar_url) = update.avatar_url {
+            self.avatar_url = avatar_url;
+        }
         self.updated_at = Utc::now();
     }
 
     pub fn update_password(&mut self, new_password_hash: String) {
         self.password_hash = new_password_hash;
+        self.password_changed_at = Utc::now();
+        self.must_change_password = false;
         self.updated_at = Utc::now();
         self.clear_reset_token();
     }
+
+    pub fn is_password_expired(&self, max_age_days: i64) -> bool {
+        self.password_expires
+            && max_age_days > 0
+            && Utc::now() >= self.password_changed_at + chrono::Duration::days(max_age_days)
+    }
+
+    /// Whether the user must set a new password before getting a full access token
+    pub fn password_change_required(&self, max_age_days: i64) -> bool {
+        self.must_change_password || self.is_password_expired(max_age_days)
+    }
 }
 
 // Request models
 #[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
+    #[serde(deserialize_with = "deserialize_email")]
     #[validate(email(message = "Invalid email format"))]
     pub email: String,
 
     #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
     #[validate(custom = "validate_password_complexity")]
+    #[validate(custom = "validate_password_strength")]
     pub password: String,
+
+    #[serde(default, deserialize_with = "deserialize_username")]
+    #[validate(custom = "validate_username")]
+    pub username: Option<String>,
 }
 
+/// Log in with either an email or a username
 #[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "validate_login_identifier"))]
 pub struct LoginRequest {
+    #[serde(default, deserialize_with = "deserialize_optional_email")]
     #[validate(email(message = "Invalid email format"))]
-    pub email: String,
+    pub email: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_username")]
+    pub username: Option<String>,
 
     #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
+/// Identifier of the account a login request refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginIdentifier<'a> {
+    Email(&'a str),
+    Username(&'a str),
+}
+
+impl LoginRequest {
+    pub fn identifier(&self) -> Option<LoginIdentifier<'_>> {
+        match (&self.email, &self.username) {
+            (Some(email), None) => Some(LoginIdentifier::Email(email)),
+            (None, Some(username)) => Some(LoginIdentifier::Username(username)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SetUsernameRequest {
+    /// New username, or `null` to remove it
+    #[serde(default, deserialize_with = "deserialize_username")]
+    #[validate(custom = "validate_username")]
+    pub username: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct ResetPasswordRequest {
+    #[serde(deserialize_with = "deserialize_email")]
     #[validate(email(message = "Invalid email format"))]
     pub email: String,
 }
@@ -137,6 +338,7 @@ pub struct ConfirmResetPasswordRequest {
 
     #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
     #[validate(custom = "validate_password_complexity")]
+    #[validate(custom = "validate_password_strength")]
     pub new_password: String,
 }
 
@@ -145,6 +347,13 @@ pub struct VerifyEmailRequest {
     pub token: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[serde(deserialize_with = "deserialize_email")]
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct ChangePasswordRequest {
     #[validate(length(min = 1, message = "Current password is required"))]
@@ -152,15 +361,59 @@ pub struct ChangePasswordRequest {
 
     #[validate(length(min = 8, message = "New password must be at least 8 characters long"))]
     #[validate(custom = "validate_password_complexity")]
+    #[validate(custom = "validate_password_strength")]
     pub new_password: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct RequestEmailChangeRequest {
+    #[serde(deserialize_with = "deserialize_email")]
+    #[validate(email(message = "Invalid email format"))]
+    pub new_email: String,
+
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeleteAccountRequest {
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ConfirmEmailChangeRequest {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+}
+
+/// Partial profile update: omitted fields are kept, `null` or `""` clears a field
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[validate(custom = "validate_display_name")]
+    pub display_name: Option<Option<String>>,
+
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[validate(custom = "validate_locale")]
+    pub locale: Option<Option<String>>,
+
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[validate(custom = "validate_timezone")]
+    pub timezone: Option<Option<String>>,
+
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[validate(custom = "validate_avatar_url")]
+    pub avatar_url: Option<Option<String>>,
+}
+
 // Response models
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub access_token: String,
     pub token_type: String,
     pub expires_in: i64,
+    pub password_change_required: bool,
     pub user: UserInfo,
 }
 
@@ -168,6 +421,11 @@ pub struct AuthResponse {
 pub struct UserInfo {
     pub id: Uuid,
     pub email: String,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub avatar_url: Option<String>,
     pub is_verified: bool,
     pub created_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
@@ -178,6 +436,11 @@ impl From<AuthUser> for UserInfo {
         Self {
             id: user.id,
             email: user.email,
+            username: user.username,
+            display_name: user.display_name,
+            locale: user.locale,
+            timezone: user.timezone,
+            avatar_url: user.avatar_url,
             is_verified: user.is_verified,
             created_at: user.created_at,
             last_login: user.last_login,
@@ -185,6 +448,12 @@ impl From<AuthUser> for UserInfo {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct AccountDeletionResponse {
+    pub message: String,
+    pub deletion_scheduled_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct MessageResponse {
     pub message: String,
@@ -205,6 +474,12 @@ pub struct Claims {
     pub email: String,
     pub exp: usize, // expiration time
     pub iat: usize, // issued at
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // restricts the token to specific endpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>, // login session, checked so revoked sessions lose access
 }
 
 impl Claims {
@@ -218,8 +493,28 @@ impl Claims {
             email,
             exp,
             iat,
+            is_admin: false,
+            scope: None,
+            sid: None,
+        }
+    }
+
+    pub fn for_user(user: &AuthUser, expires_in_seconds: i64) -> Self {
+        Self {
+            is_admin: user.is_admin,
+            ..Self::new(user.id, user.email.clone(), expires_in_seconds)
         }
     }
+
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(scope.to_string());
+        self
+    }
+
+    pub fn with_session(mut self, session_id: Option<Uuid>) -> Self {
+        self.sid = session_id;
+        self
+    }
 }
 
 // Password validation regex
@@ -229,12 +524,18 @@ impl Claims {
 // });
 
 use validator::ValidationError;
+
 fn validate_password_complexity(pw: &str) -> Result<(), ValidationError> {
     if pw.len() < 8 {
         return Err(ValidationError::new(
             "Password must be at least 8 characters",
         ));
     }
+    if pw.chars().count() > PASSWORD_MAX_LENGTH {
+        return Err(ValidationError::new(
+            "Password must be at most 128 characters",
+        ));
+    }
     if !pw.chars().any(|c| c.is_ascii_lowercase()) {
         return Err(ValidationError::new(
             "Password must contain a lowercase letter",
@@ -256,6 +557,36 @@ fn validate_password_complexity(pw: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_login_identifier(request: &LoginRequest) -> Result<(), ValidationError> {
+    if request.identifier().is_some() {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("login_identifier");
+    error.message = Some("Provide either an email or a username".into());
+    Err(error)
+}
+
+fn validate_password_strength(pw: &str) -> Result<(), ValidationError> {
+    // Overlong passwords are rejected by validate_password_complexity without estimating them
+    if pw.chars().count() > PASSWORD_MAX_LENGTH {
+        return Ok(());
+    }
+    let strength = PasswordStrengthResponse::evaluate(pw, &[], CONFIG.password_min_score);
+    if strength.acceptable {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("password_strength");
+    error.message = Some(
+        strength
+            .warning
+            .unwrap_or_else(|| "Password is too easy to guess".to_string())
+            .into(),
+    );
+    Err(error)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,36 +602,54 @@ mod tests {
         assert!(user.is_active);
         assert!(!user.is_verified);
         assert_eq!(user.failed_login_attempts, 0);
-        assert!(user.verification_token.is_some());
+        assert!(user.verification_token.is_none());
     }
 
     #[test]
     fn test_user_locking() {
         let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
 
-        assert!(!user.is_locked());
-        assert!(user.can_login());
+        let backoff = LoginBackoff {
+            threshold: 3,
+            base: 60,
+            max: 3600,
+        };
+
+        assert!(user.login_retry_after().is_none());
 
-        // Increment failed attempts
-        for _ in 0..5 {
-            user.increment_failed_attempts();
+        // Failures below the threshold don't block logins
+        for _ in 0..2 {
+            user.increment_failed_attempts(&backoff);
         }
+        assert!(user.login_retry_after().is_none());
 
-        assert!(user.is_locked());
-        assert!(!user.can_login());
+        user.increment_failed_attempts(&backoff);
+        assert!(user.login_retry_after().is_some());
+        let first_block = user.locked_until.unwrap();
+
+        // Each further failure doubles the block
+        user.increment_failed_attempts(&backoff);
+        assert!(user.locked_until.unwrap() >= first_block + chrono::Duration::seconds(59));
     }
 
     #[test]
     fn test_reset_token_generation() {
         let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
 
-        user.generate_reset_token();
+        let token = user.generate_reset_token();
         assert!(user.reset_token.is_some());
         assert!(user.reset_token_expires.is_some());
 
-        let token = user.reset_token.as_ref().unwrap().clone();
+        // Only the hash is stored
+        assert_ne!(user.reset_token.as_deref(), Some(token.as_str()));
         assert!(user.is_reset_token_valid(&token));
         assert!(!user.is_reset_token_valid("invalid_token"));
+
+        let stored_hash = user.reset_token.clone().unwrap();
+        assert!(!user.is_reset_token_valid(&stored_hash));
+
+        user.reset_token_expires = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(!user.is_reset_token_valid(&token));
     }
 
     #[test]
@@ -308,11 +657,118 @@ mod tests {
         let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
 
         assert!(!user.is_verified);
+        let token = user.generate_verification_token(3600);
         assert!(user.verification_token.is_some());
+        assert_ne!(user.verification_token.as_deref(), Some(token.as_str()));
+        assert!(user.is_verification_token_valid(&token));
+        assert!(!user.is_verification_token_valid("invalid_token"));
 
         user.verify_email();
         assert!(user.is_verified);
         assert!(user.verification_token.is_none());
+        assert!(!user.is_verification_token_valid(&token));
+    }
+
+    #[test]
+    fn test_verification_token_expiry() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+
+        let token = user.generate_verification_token(-1);
+        assert!(!user.is_verification_token_valid(&token));
+
+        // Issuing a new token invalidates the previous one
+        let fresh = user.generate_verification_token(3600);
+        assert!(user.is_verification_token_valid(&fresh));
+        assert!(!user.is_verification_token_valid(&token));
+    }
+
+    #[test]
+    fn test_email_change() {
+        let mut user = AuthUser::new("old@example.com".to_string(), "hash".to_string());
+
+        let expired = user.generate_email_change_token("new@example.com".to_string(), -1);
+        assert!(!user.is_email_change_token_valid(&expired));
+
+        let token = user.generate_email_change_token("new@example.com".to_string(), 3600);
+        assert_ne!(user.email_change_token.as_deref(), Some(token.as_str()));
+        assert!(user.is_email_change_token_valid(&token));
+        assert!(!user.is_email_change_token_valid(&expired));
+        assert_eq!(user.email, "old@example.com");
+
+        user.confirm_email_change();
+        assert_eq!(user.email, "new@example.com");
+        assert!(user.is_verified);
+        assert!(user.pending_email.is_none());
+        assert!(!user.is_email_change_token_valid(&token));
+    }
+
+    #[test]
+    fn test_profile_update_is_partial() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+        user.locale = Some("en".to_string());
+        user.timezone = Some("Europe/Madrid".to_string());
+
+        let update: UpdateProfileRequest = serde_json::from_value(serde_json::json!({
+            "display_name": "  Test User ",
+            "timezone": null
+        }))
+        .unwrap();
+        assert!(update.validate().is_ok());
+        user.update_profile(update);
+
+        assert_eq!(user.display_name.as_deref(), Some("Test User"));
+        assert_eq!(user.locale.as_deref(), Some("en"));
+        assert!(user.timezone.is_none());
+
+        let invalid: UpdateProfileRequest = serde_json::from_value(serde_json::json!({
+            "timezone": "Nowhere/Special",
+            "avatar_url": "http://example.com/a.png"
+        }))
+        .unwrap();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_account_deletion_schedule_and_cancel() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+
+        user.schedule_deletion(30);
+        assert!(!user.is_active);
+        assert!(user.is_deletion_pending());
+        assert!(user.deletion_scheduled_at.unwrap() > Utc::now() + chrono::Duration::days(29));
+
+        user.cancel_deletion();
+        assert!(user.is_active);
+        assert!(!user.is_deletion_pending());
+    }
+
+    #[test]
+    fn test_password_expiry() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+        user.password_changed_at = Utc::now() - chrono::Duration::days(100);
+
+        // Expiry only applies to flagged accounts
+        assert!(!user.password_change_required(90));
+
+        user.password_expires = true;
+        assert!(user.is_password_expired(90));
+        assert!(!user.is_password_expired(120));
+        assert!(!user.is_password_expired(0));
+
+        user.update_password("new_hash".to_string());
+        assert!(!user.password_change_required(90));
+    }
+
+    #[test]
+    fn test_must_change_password() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "hash".to_string());
+        assert!(!user.password_change_required(90));
+
+        user.must_change_password = true;
+        assert!(user.password_change_required(90));
+
+        user.update_password("new_hash".to_string());
+        assert!(!user.must_change_password);
     }
 
     #[test]
@@ -326,5 +782,43 @@ mod tests {
         assert_eq!(claims.sub, user_id.to_string());
         assert_eq!(claims.email, email);
         assert!(claims.exp > claims.iat);
+        assert!(!claims.is_admin);
+        assert!(claims.scope.is_none());
+
+        let mut user = AuthUser::new(email, "hash".to_string());
+        user.is_admin = true;
+        let claims = Claims::for_user(&user, expires_in).with_scope(PASSWORD_CHANGE_SCOPE);
+        assert!(claims.is_admin);
+        assert_eq!(claims.scope.as_deref(), Some(PASSWORD_CHANGE_SCOPE));
+    }
+
+    #[test]
+    fn test_guessable_password_fails_strength_check() {
+        // Satisfies every character-class rule but is trivially guessable
+        let request = RegisterRequest {
+            email: "test@example.com".to_string(),
+            password: "Password1!".to_string(),
+            username: None,
+        };
+        let errors = request.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("password"));
+
+        let request = RegisterRequest {
+            email: "test@example.com".to_string(),
+            password: "Quartz!Lantern7&Meadow".to_string(),
+            username: None,
+        };
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn test_overlong_password_is_rejected() {
+        let request = RegisterRequest {
+            email: "test@example.com".to_string(),
+            password: "Quartz!Lantern7&".repeat(PASSWORD_MAX_LENGTH / 16 + 1),
+            username: None,
+        };
+        let errors = request.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("password"));
     }
 }
@@ -0,0 +1,279 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::auth_user::AuthUser;
+
+/// Longest record accepted in an import; anything longer is not a user row
+pub const MAX_IMPORT_RECORD_LENGTH: usize = 64 * 1024;
+
+/// Row errors listed in an import report; further failures are only counted
+pub const MAX_REPORTED_IMPORT_ERRORS: usize = 1000;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// File format of bulk imports and exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkFormat {
+    /// Comma-separated values with a header row naming the columns
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+}
+
+impl BulkFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            BulkFormat::Csv => "text/csv; charset=utf-8",
+            BulkFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            BulkFormat::Csv => "csv",
+            BulkFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+// Query models
+#[derive(Debug, Deserialize, Validate)]
+pub struct ImportQuery {
+    pub format: BulkFormat,
+    pub dry_run: Option<bool>, // validate and check for conflicts, then roll back
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ExportQuery {
+    pub format: BulkFormat,
+}
+
+/// One user in an import file. Columns not listed here (such as those of an export) are
+/// ignored, so exports can be imported again.
+#[derive(Debug, Deserialize)]
+pub struct ImportRow {
+    pub email: String,
+    // bcrypt or argon2 hash from the previous system; without one the user must reset
+    // their password before logging in
+    pub password_hash: Option<String>,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub is_verified: Option<bool>,
+    pub created_at: Option<DateTime<Utc>>, // keeps the original sign-up date
+}
+
+/// Splits a byte stream into records as chunks arrive, so imports never hold the whole file.
+///
+/// Records end at a newline; in CSV a newline inside a quoted field does not end the record.
+/// Since quotes inside fields are doubled, a record is complete when it has seen an even
+/// number of quotes. A UTF-8 byte order mark at the start (as written by spreadsheets) is
+/// dropped.
+#[derive(Debug)]
+pub struct RecordSplitter {
+    quoted_fields: bool,
+    buffer: Vec<u8>,
+    in_quotes: bool,
+    line: u64,         // line the next record starts on
+    buffer_lines: u64, // newlines inside the buffered record
+    first_record: bool,
+}
+
+impl RecordSplitter {
+    pub fn new(format: BulkFormat) -> Self {
+        Self {
+            quoted_fields: format == BulkFormat::Csv,
+            buffer: Vec::new(),
+            in_quotes: false,
+            line: 1,
+            buffer_lines: 0,
+            first_record: true,
+        }
+    }
+
+    /// Feed a chunk, returning the complete records in it with the line each starts on.
+    ///
+    /// Fails when a record grows past `MAX_IMPORT_RECORD_LENGTH`.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, u64> {
+        let mut records = Vec::new();
+
+        for &byte in chunk {
+            match byte {
+                b'"' if self.quoted_fields => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => {
+                    let record = self.take_record();
+                    records.push((self.line, record));
+                    self.line += self.buffer_lines + 1;
+                    self.buffer_lines = 0;
+                    continue;
+                }
+                b'\n' => self.buffer_lines += 1,
+                _ => {}
+            }
+
+            if self.buffer.len() >= MAX_IMPORT_RECORD_LENGTH {
+                return Err(self.line);
+            }
+            self.buffer.push(byte);
+        }
+
+        Ok(records
+            .into_iter()
+            .map(|(line, record)| (line, trim_record(record)))
+            .filter(|(_, record)| !record.is_empty())
+            .collect())
+    }
+
+    /// The last record, when the input does not end with a newline
+    pub fn finish(mut self) -> Option<(u64, Vec<u8>)> {
+        let record = trim_record(self.take_record());
+        (!record.is_empty()).then_some((self.line, record))
+    }
+
+    fn take_record(&mut self) -> Vec<u8> {
+        let mut record = std::mem::take(&mut self.buffer);
+        if std::mem::take(&mut self.first_record) && record.starts_with(UTF8_BOM) {
+            record.drain(..UTF8_BOM.len());
+        }
+        record
+    }
+}
+
+// Drop the "\r" of CRLF line endings and skip blank lines
+fn trim_record(mut record: Vec<u8>) -> Vec<u8> {
+    if record.last() == Some(&b'\r') {
+        record.pop();
+    }
+    if record.iter().all(u8::is_ascii_whitespace) {
+        record.clear();
+    }
+    record
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct ImportRowError {
+    pub line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub rows: u64,
+    pub imported: u64,
+    pub failed: u64,
+    pub errors: Vec<ImportRowError>, // at most MAX_REPORTED_IMPORT_ERRORS
+    pub errors_truncated: bool,
+}
+
+impl ImportReport {
+    pub fn record_error(&mut self, line: u64, email: Option<String>, error: impl Into<String>) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REPORTED_IMPORT_ERRORS {
+            self.errors.push(ImportRowError {
+                line,
+                email,
+                error: error.into(),
+            });
+        } else {
+            self.errors_truncated = true;
+        }
+    }
+}
+
+/// One user in an export; the columns are a superset of `ImportRow` without secrets
+#[derive(Debug, Serialize)]
+pub struct ExportedUser {
+    pub id: Uuid,
+    pub email: String,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub is_verified: bool,
+    pub is_active: bool,
+    pub is_admin: bool,
+    pub must_change_password: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
+}
+
+impl From<AuthUser> for ExportedUser {
+    fn from(user: AuthUser) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            username: user.username,
+            display_name: user.display_name,
+            locale: user.locale,
+            timezone: user.timezone,
+            is_verified: user.is_verified,
+            is_active: user.is_active,
+            is_admin: user.is_admin,
+            must_change_password: user.must_change_password,
+            created_at: user.created_at,
+            last_login: user.last_login,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_splitter_across_chunks() {
+        let mut splitter = RecordSplitter::new(BulkFormat::Jsonl);
+        let first = splitter
+            .push(b"\xEF\xBB\xBF{\"email\":\"a@example.com\"}\r\n\n{\"em")
+            .unwrap();
+        assert_eq!(first, vec![(1, b"{\"email\":\"a@example.com\"}".to_vec())]);
+
+        let second = splitter.push(b"ail\":\"b@example.com\"}").unwrap();
+        assert!(second.is_empty());
+        assert_eq!(
+            splitter.finish(),
+            Some((3, b"{\"email\":\"b@example.com\"}".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_record_splitter_quoted_csv_newlines() {
+        let mut splitter = RecordSplitter::new(BulkFormat::Csv);
+        let records = splitter
+            .push(b"email,display_name\na@example.com,\"Line\nbreak \"\"quoted\"\"\"\nb@example.com,B\n")
+            .unwrap();
+        let lines: Vec<u64> = records.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![1, 2, 4]);
+        assert_eq!(
+            records[1].1,
+            b"a@example.com,\"Line\nbreak \"\"quoted\"\"\"".to_vec()
+        );
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn test_record_splitter_rejects_long_records() {
+        let mut splitter = RecordSplitter::new(BulkFormat::Jsonl);
+        let long = vec![b'x'; MAX_IMPORT_RECORD_LENGTH + 1];
+        assert_eq!(splitter.push(&long), Err(1));
+    }
+
+    #[test]
+    fn test_report_truncates_errors() {
+        let mut report = ImportReport::default();
+        for line in 0..MAX_REPORTED_IMPORT_ERRORS as u64 + 5 {
+            report.record_error(line, None, "bad");
+        }
+        assert_eq!(report.failed, MAX_REPORTED_IMPORT_ERRORS as u64 + 5);
+        assert_eq!(report.errors.len(), MAX_REPORTED_IMPORT_ERRORS);
+        assert!(report.errors_truncated);
+    }
+}
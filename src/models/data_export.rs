@@ -0,0 +1,298 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    models::{
+        auth_user::AuthUser,
+        login_event::LoginEvent,
+        token::{hmac_sha256_hex, hmac_sha256_hex_matches},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+impl DataExportStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DataExportStatus::Pending => "pending",
+            DataExportStatus::Ready => "ready",
+            DataExportStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Column list matching the fields of `DataExport`, for use with `query_as`; the archive
+/// itself is only loaded for downloads
+pub const DATA_EXPORT_COLUMNS: &str = r#"
+    id, user_id, status, error, created_at, completed_at, expires_at
+"#;
+
+// Domain separation, so the signature cannot be confused with other uses of the key
+const DOWNLOAD_SIGNATURE_CONTEXT: &str = "data-export-download";
+
+/// HMAC-SHA256 (keyed with `DATA_EXPORT_SIGNING_KEY`) over the export id and link expiry, as hex
+pub fn download_signature(export_id: Uuid, expires: i64) -> String {
+    hmac_sha256_hex(
+        CONFIG.data_export_signing_key.as_bytes(),
+        download_input(export_id, expires).as_bytes(),
+    )
+}
+
+/// Check a download link's signature in constant time and that it has not expired
+pub fn verify_download_signature(export_id: Uuid, expires: i64, signature: &str) -> bool {
+    hmac_sha256_hex_matches(
+        CONFIG.data_export_signing_key.as_bytes(),
+        download_input(export_id, expires).as_bytes(),
+        signature,
+    ) && Utc::now().timestamp() < expires
+}
+
+fn download_input(export_id: Uuid, expires: i64) -> String {
+    format!("{DOWNLOAD_SIGNATURE_CONTEXT}:{export_id}:{expires}")
+}
+
+/// Absolute, signed link to download an export until `expires_at`
+pub fn download_url(export_id: Uuid, expires_at: DateTime<Utc>) -> String {
+    let expires = expires_at.timestamp();
+    format!(
+        "{}/api/v1/data-exports/{export_id}/download?expires={expires}&signature={}",
+        CONFIG.api_base_url,
+        download_signature(export_id, expires)
+    )
+}
+
+// Query models
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct DataExportInfo {
+    pub id: Uuid,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>, // only once the archive is ready
+}
+
+impl From<DataExport> for DataExportInfo {
+    fn from(export: DataExport) -> Self {
+        let download_url = (export.status == DataExportStatus::Ready.as_str())
+            .then(|| download_url(export.id, export.expires_at));
+
+        Self {
+            id: export.id,
+            status: export.status,
+            error: export.error,
+            created_at: export.created_at,
+            completed_at: export.completed_at,
+            expires_at: export.expires_at,
+            download_url,
+        }
+    }
+}
+
+/// Personal data archive handed to the user; secrets are never included
+#[derive(Debug, Serialize)]
+pub struct PersonalDataArchive {
+    pub generated_at: DateTime<Utc>,
+    pub account: AccountData,
+    pub password_changes: Vec<DateTime<Utc>>,
+    pub emails: Vec<EmailRecord>,
+    pub login_history: Vec<LoginEvent>,
+    pub sessions: Vec<SessionRecord>,
+}
+
+/// Account record without password hashes or tokens
+#[derive(Debug, Serialize)]
+pub struct AccountData {
+    pub id: Uuid,
+    pub email: String,
+    pub pending_email: Option<String>,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub avatar_url: Option<String>,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub is_admin: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub password_changed_at: DateTime<Utc>,
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+}
+
+impl From<AuthUser> for AccountData {
+    fn from(user: AuthUser) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            pending_email: user.pending_email,
+            username: user.username,
+            display_name: user.display_name,
+            locale: user.locale,
+            timezone: user.timezone,
+            avatar_url: user.avatar_url,
+            is_active: user.is_active,
+            is_verified: user.is_verified,
+            is_admin: user.is_admin,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            last_login: user.last_login,
+            failed_login_attempts: user.failed_login_attempts,
+            locked_until: user.locked_until,
+            password_changed_at: user.password_changed_at,
+            deletion_scheduled_at: user.deletion_scheduled_at,
+        }
+    }
+}
+
+/// Email sent to the user, without its contents (which contain tokens)
+#[derive(Debug, Serialize, FromRow)]
+pub struct EmailRecord {
+    pub kind: String,
+    pub recipient: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Login session of the user, with the device it was started from
+#[derive(Debug, Serialize, FromRow)]
+pub struct SessionRecord {
+    pub id: Uuid,
+    pub device: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_signature() {
+        let id = Uuid::new_v4();
+        let expires = (Utc::now() + chrono::Duration::hours(1)).timestamp();
+        let signature = download_signature(id, expires);
+
+        assert!(verify_download_signature(id, expires, &signature));
+        assert!(!verify_download_signature(id, expires + 1, &signature));
+        assert!(!verify_download_signature(
+            Uuid::new_v4(),
+            expires,
+            &signature
+        ));
+        assert!(!verify_download_signature(id, expires, "deadbeef"));
+
+        let expired = Utc::now().timestamp() - 1;
+        let signature = download_signature(id, expired);
+        assert!(!verify_download_signature(id, expired, &signature));
+    }
+
+    // Export id, expiry and signature carried by a download link
+    fn link_parts(url: &str) -> (Uuid, i64, String) {
+        let (path, query) = url.split_once('?').unwrap();
+        let id = path
+            .trim_end_matches("/download")
+            .rsplit('/')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let param = |name: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(&format!("{name}=")))
+                .unwrap()
+                .to_string()
+        };
+        (id, param("expires").parse().unwrap(), param("signature"))
+    }
+
+    fn export(status: DataExportStatus, expires_at: DateTime<Utc>) -> DataExport {
+        DataExport {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            status: status.as_str().to_string(),
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_download_link_is_signed_until_expiry() {
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let ready = export(DataExportStatus::Ready, expires_at);
+        let ready_id = ready.id;
+        let url = DataExportInfo::from(ready).download_url.unwrap();
+        assert!(url.starts_with(&CONFIG.api_base_url));
+
+        let (id, expires, signature) = link_parts(&url);
+        assert_eq!(id, ready_id);
+        assert_eq!(expires, expires_at.timestamp());
+        assert!(verify_download_signature(id, expires, &signature));
+
+        // The link expires with the export
+        let expired = export(
+            DataExportStatus::Ready,
+            Utc::now() - chrono::Duration::seconds(1),
+        );
+        let (id, expires, signature) =
+            link_parts(&DataExportInfo::from(expired).download_url.unwrap());
+        assert!(!verify_download_signature(id, expires, &signature));
+
+        for status in [DataExportStatus::Pending, DataExportStatus::Failed] {
+            assert!(DataExportInfo::from(export(status, expires_at))
+                .download_url
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_account_data_has_no_secrets() {
+        let mut user = AuthUser::new("test@example.com".to_string(), "$2b$hash".to_string());
+        user.generate_reset_token();
+        let json = serde_json::to_string(&AccountData::from(user)).unwrap();
+
+        assert!(!json.contains("password_hash"));
+        assert!(!json.contains("$2b$hash"));
+        assert!(!json.contains("token"));
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Deserializer};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::CONFIG;
+
+/// Normalize an email address for storage and lookup using the configured IDN handling
+pub fn normalize_email(email: &str) -> String {
+    normalize_email_with(email, CONFIG.email_idn_punycode)
+}
+
+/// Trim, apply Unicode NFC and lowercase the domain, optionally converting it to punycode.
+///
+/// The local part keeps its case; accounts are matched case-insensitively (see
+/// `email_identity_key`), so `Alice@Example.com` and `alice@example.com` are the same user.
+pub fn normalize_email_with(email: &str, punycode: bool) -> String {
+    let email: String = email.trim().nfc().collect();
+
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return email;
+    };
+
+    let domain = domain.to_lowercase();
+    let domain = if punycode {
+        // Invalid domains are left as-is for validation to reject
+        idna::domain_to_ascii(&domain).unwrap_or(domain)
+    } else {
+        domain
+    };
+
+    format!("{local}@{domain}")
+}
+
+/// Key under which two addresses belong to the same account; matches `LOWER(email)` in SQL
+pub fn email_identity_key(email: &str) -> String {
+    normalize_email(email).to_lowercase()
+}
+
+/// Serde helper normalizing email fields of incoming requests
+pub fn deserialize_email<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(|email| normalize_email(&email))
+}
+
+/// Serde helper normalizing optional email fields of incoming requests
+pub fn deserialize_optional_email<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer).map(|email| email.map(|e| normalize_email(&e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_trims_and_lowercases_domain() {
+        assert_eq!(
+            normalize_email_with("  Alice@Example.COM \n", false),
+            "Alice@example.com"
+        );
+        assert_eq!(normalize_email_with("no-at-sign", false), "no-at-sign");
+    }
+
+    #[test]
+    fn test_normalize_applies_nfc() {
+        // "u" followed by a combining diaeresis becomes a single "ü"
+        let decomposed = "user@bu\u{0308}cher.de";
+        assert_eq!(
+            normalize_email_with(decomposed, false),
+            "user@b\u{00fc}cher.de"
+        );
+    }
+
+    #[test]
+    fn test_normalize_punycode() {
+        assert_eq!(
+            normalize_email_with("user@B\u{00dc}CHER.de", true),
+            "user@xn--bcher-kva.de"
+        );
+        assert_eq!(
+            normalize_email_with("user@B\u{00dc}CHER.de", false),
+            "user@b\u{00fc}cher.de"
+        );
+    }
+
+    #[test]
+    fn test_identity_key_ignores_case() {
+        assert_eq!(
+            email_identity_key("Alice@Example.com"),
+            email_identity_key("alice@example.COM")
+        );
+    }
+
+    #[test]
+    fn test_deserialize_email() {
+        #[derive(Deserialize)]
+        struct Request {
+            #[serde(deserialize_with = "deserialize_email")]
+            email: String,
+        }
+
+        let request: Request =
+            serde_json::from_value(serde_json::json!({ "email": " Bob@EXAMPLE.com" })).unwrap();
+        assert_eq!(request.email, "Bob@example.com");
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+use woothee::parser::Parser;
+
+use crate::models::{auth_user::LoginIdentifier, request_context::RequestContext};
+
+const UNKNOWN: &str = "UNKNOWN"; // what woothee reports for fields it can't tell
+
+/// How the user identified themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginMethod {
+    EmailPassword,
+    UsernamePassword,
+}
+
+impl LoginMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LoginMethod::EmailPassword => "email_password",
+            LoginMethod::UsernamePassword => "username_password",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "email_password" => Some(LoginMethod::EmailPassword),
+            "username_password" => Some(LoginMethod::UsernamePassword),
+            _ => None,
+        }
+    }
+}
+
+impl From<&LoginIdentifier<'_>> for LoginMethod {
+    fn from(identifier: &LoginIdentifier<'_>) -> Self {
+        match identifier {
+            LoginIdentifier::Email(_) => LoginMethod::EmailPassword,
+            LoginIdentifier::Username(_) => LoginMethod::UsernamePassword,
+        }
+    }
+}
+
+/// Why a login attempt was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginFailure {
+    InvalidCredentials,
+    Throttled, // refused by the login backoff without checking the password
+    Inactive,
+    ChallengeRequired, // correct password, but the login looked suspicious and needs a code
+    InvalidCode,       // wrong code for a login challenge
+}
+
+impl LoginFailure {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LoginFailure::InvalidCredentials => "invalid_credentials",
+            LoginFailure::Throttled => "throttled",
+            LoginFailure::Inactive => "inactive",
+            LoginFailure::ChallengeRequired => "challenge_required",
+            LoginFailure::InvalidCode => "invalid_code",
+        }
+    }
+}
+
+/// A login attempt against an existing account, shown to its owner in the login history
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct LoginEvent {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub success: bool,
+    pub failure_reason: Option<String>,
+    pub method: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub device: String, // e.g. "Firefox 128.0 on Linux"
+    pub location: Option<String>,
+    pub anomalies: Vec<String>, // why the login looked suspicious, e.g. "new_device"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<Uuid>, // session started by a successful login
+    pub created_at: DateTime<Utc>,
+}
+
+impl LoginEvent {
+    pub fn succeeded(
+        user_id: Uuid,
+        method: LoginMethod,
+        session_id: Uuid,
+        ctx: &RequestContext,
+    ) -> Self {
+        Self {
+            session_id: Some(session_id),
+            success: true,
+            ..Self::new(user_id, method, ctx)
+        }
+    }
+
+    pub fn failed(
+        user_id: Uuid,
+        method: LoginMethod,
+        reason: LoginFailure,
+        ctx: &RequestContext,
+    ) -> Self {
+        Self {
+            failure_reason: Some(reason.as_str().to_string()),
+            ..Self::new(user_id, method, ctx)
+        }
+    }
+
+    /// Attach the outcome of the suspicious-login checks
+    pub fn with_risk(mut self, anomalies: Vec<String>, location: Option<String>) -> Self {
+        self.anomalies = anomalies;
+        self.location = location;
+        self
+    }
+
+    fn new(user_id: Uuid, method: LoginMethod, ctx: &RequestContext) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            success: false,
+            failure_reason: None,
+            method: method.as_str().to_string(),
+            ip_address: ctx.ip_address.clone(),
+            user_agent: ctx.user_agent.clone(),
+            device: describe_device(ctx.user_agent.as_deref()),
+            location: None,
+            anomalies: Vec::new(),
+            session_id: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Column list matching the fields of `LoginEvent`, for use with `query_as`
+pub const LOGIN_EVENT_COLUMNS: &str = r#"
+    id, user_id, success, failure_reason, method, ip_address, user_agent, device, location,
+    anomalies, session_id, created_at
+"#;
+
+/// Human-readable browser and OS of a user agent, e.g. "Chrome 126.0.0.0 on Windows 10"
+pub fn describe_device(user_agent: Option<&str>) -> String {
+    let parsed = user_agent.and_then(|agent| Parser::new().parse(agent));
+    let Some(parsed) = parsed.filter(|parsed| parsed.name != UNKNOWN) else {
+        return "Unknown device".to_string();
+    };
+
+    let browser = if parsed.version == UNKNOWN {
+        parsed.name.to_string()
+    } else {
+        format!("{} {}", parsed.name, parsed.version)
+    };
+
+    if parsed.os == UNKNOWN {
+        browser
+    } else {
+        format!("{browser} on {}", parsed.os)
+    }
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct LoginHistoryQuery {
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200"))]
+    pub limit: Option<i64>,
+
+    // next_cursor of the previous page
+    pub cursor: Option<String>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct LoginHistoryResponse {
+    pub events: Vec<LoginEvent>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_device() {
+        assert_eq!(
+            describe_device(Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"
+            )),
+            "Firefox 128.0 on Linux"
+        );
+        assert_eq!(describe_device(Some("")), "Unknown device");
+        assert_eq!(describe_device(None), "Unknown device");
+    }
+
+    #[test]
+    fn test_login_event_outcomes() {
+        let user_id = Uuid::new_v4();
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.7".to_string()),
+            ..Default::default()
+        };
+
+        let failed = LoginEvent::failed(
+            user_id,
+            LoginMethod::UsernamePassword,
+            LoginFailure::Throttled,
+            &ctx,
+        );
+        assert!(!failed.success);
+        assert_eq!(failed.failure_reason.as_deref(), Some("throttled"));
+        assert_eq!(failed.method, "username_password");
+        assert_eq!(failed.ip_address.as_deref(), Some("203.0.113.7"));
+
+        let session_id = Uuid::new_v4();
+        let succeeded =
+            LoginEvent::succeeded(user_id, LoginMethod::EmailPassword, session_id, &ctx);
+        assert!(succeeded.success);
+        assert!(succeeded.failure_reason.is_none());
+        assert_eq!(succeeded.session_id, Some(session_id));
+
+        // The user id stays internal
+        let json = serde_json::to_value(&succeeded).unwrap();
+        assert!(json.get("user_id").is_none());
+    }
+}
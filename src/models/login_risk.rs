@@ -0,0 +1,287 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    geoip::Location,
+    models::{
+        auth_user::AuthResponse,
+        login_event::LoginMethod,
+        token::{hash_token, token_matches},
+    },
+};
+
+/// Wrong codes accepted per login challenge before it is void
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Why a login with the correct password looks unusual
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginAnomaly {
+    NewDevice,       // device never used for a successful login
+    UnusualLocation, // far from every previous successful login
+}
+
+impl LoginAnomaly {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LoginAnomaly::NewDevice => "new_device",
+            LoginAnomaly::UnusualLocation => "unusual_location",
+        }
+    }
+}
+
+/// Earlier successful logins of an account, to compare a new one against
+#[derive(Debug, Default, FromRow)]
+pub struct LoginBaseline {
+    pub has_logged_in: bool,
+    pub known_device: bool,
+    pub ip_addresses: Vec<String>, // of recent successful logins
+}
+
+/// Outcome of comparing a login against the account's baseline
+#[derive(Debug, Clone, Default)]
+pub struct LoginAssessment {
+    pub anomalies: Vec<LoginAnomaly>,
+    pub location: Option<Location>,
+}
+
+impl LoginAssessment {
+    /// Compare a login from `ip_address` with the baseline, geolocating addresses with
+    /// `locate`. The very first login of an account has nothing to compare with and is never
+    /// flagged; addresses that can't be located are left out of the comparison.
+    pub fn evaluate(
+        baseline: &LoginBaseline,
+        ip_address: Option<&str>,
+        locate: impl Fn(&str) -> Option<Location>,
+        max_distance_km: f64,
+    ) -> Self {
+        let location = ip_address.and_then(&locate);
+        let mut anomalies = Vec::new();
+
+        if baseline.has_logged_in {
+            if !baseline.known_device {
+                anomalies.push(LoginAnomaly::NewDevice);
+            }
+
+            let far_from_all = location.as_ref().is_some_and(|location| {
+                let previous: Vec<Location> = baseline
+                    .ip_addresses
+                    .iter()
+                    .filter_map(|ip| locate(ip))
+                    .collect();
+                !previous.is_empty()
+                    && previous
+                        .iter()
+                        .all(|previous| previous.distance_km(location) > max_distance_km)
+            });
+            if far_from_all {
+                anomalies.push(LoginAnomaly::UnusualLocation);
+            }
+        }
+
+        Self {
+            anomalies,
+            location,
+        }
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        !self.anomalies.is_empty()
+    }
+
+    pub fn anomaly_names(&self) -> Vec<String> {
+        self.anomalies
+            .iter()
+            .map(|anomaly| anomaly.as_str().to_string())
+            .collect()
+    }
+
+    pub fn location_name(&self) -> Option<String> {
+        self.location.as_ref().map(Location::describe)
+    }
+}
+
+/// Column list matching the fields of `LoginChallenge`, for use with `query_as`
+pub const LOGIN_CHALLENGE_COLUMNS: &str = r#"
+    id, user_id, code_hash, method, device_key, anomalies, location, attempts, expires_at,
+    completed_at, created_at
+"#;
+
+/// A suspicious login waiting for the code emailed to the account owner
+#[derive(Debug, Clone, FromRow)]
+pub struct LoginChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub method: String,
+    pub device_key: String, // the code only completes the login on the same device
+    pub anomalies: Vec<String>,
+    pub location: Option<String>,
+    pub attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LoginChallenge {
+    /// Start a challenge; returns it with the raw code to email, of which only a hash is kept
+    pub fn new(
+        user_id: Uuid,
+        method: LoginMethod,
+        device_key: String,
+        assessment: &LoginAssessment,
+        expires_in_seconds: i64,
+    ) -> (Self, String) {
+        let id = Uuid::new_v4();
+        let code = format!("{:06}", Uuid::new_v4().as_u128() % 1_000_000);
+        let now = Utc::now();
+
+        let challenge = Self {
+            id,
+            user_id,
+            code_hash: challenge_code_hash(id, &code),
+            method: method.as_str().to_string(),
+            device_key,
+            anomalies: assessment.anomaly_names(),
+            location: assessment.location_name(),
+            attempts: 0,
+            expires_at: now + Duration::seconds(expires_in_seconds),
+            completed_at: None,
+            created_at: now,
+        };
+        (challenge, code)
+    }
+
+    /// Whether the challenge can still be answered
+    pub fn is_open(&self) -> bool {
+        self.completed_at.is_none()
+            && self.attempts < MAX_CHALLENGE_ATTEMPTS
+            && self.expires_at > Utc::now()
+    }
+
+    pub fn code_matches(&self, code: &str) -> bool {
+        token_matches(&format!("{}:{}", self.id, code.trim()), &self.code_hash)
+    }
+}
+
+// Salted with the challenge id, so equal codes don't share a hash
+fn challenge_code_hash(id: Uuid, code: &str) -> String {
+    hash_token(&format!("{id}:{code}"))
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct VerifyLoginRequest {
+    pub challenge_id: Uuid,
+
+    #[validate(length(min = 1, max = 20, message = "Code is required"))]
+    pub code: String,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct LoginChallengeResponse {
+    pub challenge_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Answer to a login with the correct password
+#[derive(Debug)]
+pub enum LoginOutcome {
+    Authenticated(AuthResponse),
+    ChallengeRequired(LoginChallengeResponse), // suspicious login, see SUSPICIOUS_LOGIN_OTP
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test addresses standing in for a few cities
+    fn locate(ip: &str) -> Option<Location> {
+        let (latitude, longitude) = match ip {
+            "192.0.2.1" => (52.52, 13.405),  // Berlin
+            "192.0.2.2" => (52.39, 13.065),  // Potsdam, about 25 km away
+            "198.51.100.1" => (48.86, 2.35), // Paris, about 880 km away
+            _ => return None,
+        };
+        Some(Location {
+            city: None,
+            country: None,
+            latitude,
+            longitude,
+        })
+    }
+
+    fn baseline(known_device: bool) -> LoginBaseline {
+        LoginBaseline {
+            has_logged_in: true,
+            known_device,
+            ip_addresses: vec!["192.0.2.1".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_first_login_is_not_flagged() {
+        let assessment = LoginAssessment::evaluate(
+            &LoginBaseline::default(),
+            Some("198.51.100.1"),
+            locate,
+            500.0,
+        );
+        assert!(!assessment.is_suspicious());
+        assert!(assessment.location.is_some());
+    }
+
+    #[test]
+    fn test_new_device_and_unusual_location() {
+        let assessment =
+            LoginAssessment::evaluate(&baseline(false), Some("198.51.100.1"), locate, 500.0);
+        assert_eq!(
+            assessment.anomalies,
+            vec![LoginAnomaly::NewDevice, LoginAnomaly::UnusualLocation]
+        );
+        assert_eq!(
+            assessment.anomaly_names(),
+            vec!["new_device", "unusual_location"]
+        );
+
+        let known = baseline(true);
+        assert!(
+            !LoginAssessment::evaluate(&known, Some("192.0.2.2"), locate, 500.0).is_suspicious()
+        );
+        assert!(
+            !LoginAssessment::evaluate(&known, Some("198.51.100.1"), locate, 1000.0)
+                .is_suspicious()
+        );
+
+        // Addresses that can't be located are not compared
+        assert!(
+            !LoginAssessment::evaluate(&known, Some("203.0.113.1"), locate, 500.0).is_suspicious()
+        );
+        assert!(!LoginAssessment::evaluate(&known, None, locate, 500.0).is_suspicious());
+    }
+
+    #[test]
+    fn test_challenge_code() {
+        let (mut challenge, code) = LoginChallenge::new(
+            Uuid::new_v4(),
+            LoginMethod::EmailPassword,
+            "device".to_string(),
+            &LoginAssessment::default(),
+            600,
+        );
+
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+        assert!(challenge.code_matches(&code));
+        assert!(challenge.code_matches(&format!(" {code} ")));
+        assert!(!challenge.code_matches("not the code"));
+        assert!(challenge.is_open());
+
+        challenge.attempts = MAX_CHALLENGE_ATTEMPTS;
+        assert!(!challenge.is_open());
+    }
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    models::{auth_user::LoginIdentifier, token::hash_token},
+};
+
+/// Exponential backoff after failed logins: from the `threshold`th consecutive failure on,
+/// each failure blocks further attempts for `base` seconds, doubling up to `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginBackoff {
+    pub threshold: i32,
+    pub base: i64, // in seconds
+    pub max: i64,  // in seconds
+}
+
+impl LoginBackoff {
+    /// Backoff for failures against an account from any IP
+    pub fn for_account() -> Self {
+        Self {
+            threshold: CONFIG.login_backoff_account_threshold,
+            base: CONFIG.login_backoff_base,
+            max: CONFIG.login_backoff_account_max,
+        }
+    }
+
+    /// Backoff for failures against an account from a single IP
+    pub fn for_ip() -> Self {
+        Self {
+            threshold: CONFIG.login_backoff_ip_threshold,
+            base: CONFIG.login_backoff_base,
+            max: CONFIG.login_backoff_ip_max,
+        }
+    }
+
+    /// How long to block logins after `failed_attempts` consecutive failures
+    pub fn delay(&self, failed_attempts: i32) -> Option<Duration> {
+        if failed_attempts < self.threshold {
+            return None;
+        }
+
+        let doublings = (failed_attempts - self.threshold).min(32) as u32;
+        let seconds = self.base.saturating_mul(1 << doublings).min(self.max);
+        Some(Duration::seconds(seconds))
+    }
+}
+
+/// Whole seconds until `blocked_until`, if it is still in the future
+pub fn retry_after(blocked_until: Option<DateTime<Utc>>) -> Option<u64> {
+    let remaining = (blocked_until? - Utc::now()).num_milliseconds();
+    (remaining > 0).then(|| (remaining as u64).div_ceil(1000))
+}
+
+/// Failed logins against one account from one IP address
+#[derive(Debug, Clone, FromRow)]
+pub struct LoginThrottle {
+    pub user_id: Uuid,
+    pub ip_address: String,
+    pub failed_attempts: i32,
+    pub blocked_until: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>, // set once the account logged in from here
+    pub updated_at: DateTime<Utc>,
+}
+
+impl LoginThrottle {
+    pub fn new(user_id: Uuid, ip_address: String) -> Self {
+        Self {
+            user_id,
+            ip_address,
+            failed_attempts: 0,
+            blocked_until: None,
+            last_success_at: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Whether the account has logged in from this IP before. Known IPs are exempt from
+    /// the account-wide backoff, so attacks from elsewhere don't lock the owner out.
+    pub fn is_known(&self) -> bool {
+        self.last_success_at.is_some()
+    }
+
+    pub fn record_failure(&mut self, backoff: &LoginBackoff) {
+        self.failed_attempts += 1;
+        self.blocked_until = backoff
+            .delay(self.failed_attempts)
+            .map(|delay| Utc::now() + delay);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn record_success(&mut self) {
+        self.failed_attempts = 0;
+        self.blocked_until = None;
+        self.last_success_at = Some(Utc::now());
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Failed logins to an email or username without an account, from one IP or, with an empty
+/// `ip_address`, from any. They back off like logins to existing accounts, so that the
+/// responses don't tell which accounts exist.
+#[derive(Debug, Clone, FromRow)]
+pub struct UnknownLoginThrottle {
+    pub identifier_hash: String,
+    pub ip_address: String,
+    pub failed_attempts: i32,
+    pub blocked_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UnknownLoginThrottle {
+    pub fn new(identifier_hash: String, ip_address: String) -> Self {
+        Self {
+            identifier_hash,
+            ip_address,
+            failed_attempts: 0,
+            blocked_until: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn record_failure(&mut self, backoff: &LoginBackoff) {
+        self.failed_attempts += 1;
+        self.blocked_until = backoff
+            .delay(self.failed_attempts)
+            .map(|delay| Utc::now() + delay);
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Key of the backoff state of an identifier without an account; case-insensitive like the
+/// account lookup, and hashed so the identifiers tried are not stored
+pub fn unknown_identifier_hash(identifier: &LoginIdentifier) -> String {
+    match identifier {
+        LoginIdentifier::Email(email) => hash_token(&format!("email:{}", email.to_lowercase())),
+        LoginIdentifier::Username(username) => {
+            hash_token(&format!("username:{}", username.to_lowercase()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let backoff = LoginBackoff {
+            threshold: 3,
+            base: 2,
+            max: 60,
+        };
+
+        assert_eq!(backoff.delay(2), None);
+        assert_eq!(backoff.delay(3), Some(Duration::seconds(2)));
+        assert_eq!(backoff.delay(4), Some(Duration::seconds(4)));
+        assert_eq!(backoff.delay(7), Some(Duration::seconds(32)));
+        assert_eq!(backoff.delay(8), Some(Duration::seconds(60)));
+        assert_eq!(backoff.delay(i32::MAX), Some(Duration::seconds(60)));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(retry_after(None), None);
+        assert_eq!(retry_after(Some(Utc::now() - Duration::seconds(1))), None);
+        assert_eq!(
+            retry_after(Some(Utc::now() + Duration::milliseconds(1500))),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_login_throttle() {
+        let backoff = LoginBackoff {
+            threshold: 2,
+            base: 1,
+            max: 60,
+        };
+        let mut throttle = LoginThrottle::new(Uuid::new_v4(), "203.0.113.7".to_string());
+
+        throttle.record_failure(&backoff);
+        assert!(throttle.blocked_until.is_none());
+        throttle.record_failure(&backoff);
+        assert!(retry_after(throttle.blocked_until).is_some());
+        assert!(!throttle.is_known());
+
+        throttle.record_success();
+        assert_eq!(throttle.failed_attempts, 0);
+        assert!(throttle.blocked_until.is_none());
+        assert!(throttle.is_known());
+    }
+
+    #[test]
+    fn test_unknown_identifier_hash() {
+        let hash = unknown_identifier_hash(&LoginIdentifier::Email("Ghost@Example.com"));
+
+        assert_eq!(
+            hash,
+            unknown_identifier_hash(&LoginIdentifier::Email("ghost@example.com"))
+        );
+        assert_ne!(
+            hash,
+            unknown_identifier_hash(&LoginIdentifier::Username("ghost@example.com"))
+        );
+        assert!(!hash.contains("ghost"));
+    }
+}
@@ -1 +1,18 @@
+pub mod admin;
+pub mod audit;
 pub mod auth_user;
+pub mod bulk_user;
+pub mod data_export;
+pub mod email;
+pub mod login_event;
+pub mod login_risk;
+pub mod login_throttle;
+pub mod outbox;
+pub mod pagination;
+pub mod password_strength;
+pub mod profile;
+pub mod request_context;
+pub mod session;
+pub mod token;
+pub mod username;
+pub mod webhook;
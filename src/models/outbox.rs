@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::mailer::EmailMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    Pending,
+    Sent,
+    Dead,
+}
+
+impl OutboxStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Sent => "sent",
+            OutboxStatus::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub kind: String,
+    pub recipient: String,
+    pub payload: Option<Json<EmailMessage>>, // cleared once delivered
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Column list matching the fields of `OutboxMessage`, for use with `query_as`
+pub const OUTBOX_COLUMNS: &str = r#"
+    id, kind, recipient, payload, status, attempts, next_attempt_at,
+    last_error, created_at, updated_at, sent_at
+"#;
+
+/// Delay before the next delivery attempt: `base * 2^(attempts - 1)`, capped at `max`
+pub fn retry_delay(attempts: i32, base: i64, max: i64) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 32) as u32;
+    base.saturating_mul(2_i64.saturating_pow(exponent)).min(max)
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct OutboxListQuery {
+    // Defaults to dead-lettered messages
+    pub status: Option<OutboxStatus>,
+
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200"))]
+    pub limit: Option<i64>,
+
+    #[validate(range(min = 0, message = "Offset cannot be negative"))]
+    pub offset: Option<i64>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct OutboxMessageInfo {
+    pub id: Uuid,
+    pub kind: String,
+    pub recipient: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl From<OutboxMessage> for OutboxMessageInfo {
+    fn from(message: OutboxMessage) -> Self {
+        Self {
+            id: message.id,
+            kind: message.kind,
+            recipient: message.recipient,
+            status: message.status,
+            attempts: message.attempts,
+            next_attempt_at: message.next_attempt_at,
+            last_error: message.last_error,
+            created_at: message.created_at,
+            updated_at: message.updated_at,
+            sent_at: message.sent_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_backs_off_exponentially() {
+        assert_eq!(retry_delay(1, 30, 3600), 30);
+        assert_eq!(retry_delay(2, 30, 3600), 60);
+        assert_eq!(retry_delay(4, 30, 3600), 240);
+        assert_eq!(retry_delay(8, 30, 3600), 3600);
+        assert_eq!(retry_delay(1000, 30, 3600), 3600);
+        assert_eq!(retry_delay(0, 30, 3600), 30);
+    }
+
+    #[test]
+    fn test_outbox_message_info_omits_payload() {
+        let now = Utc::now();
+        let message = OutboxMessage {
+            id: Uuid::new_v4(),
+            kind: "password_reset".to_string(),
+            recipient: "user@example.com".to_string(),
+            payload: Some(Json(EmailMessage {
+                template: "password_reset".to_string(),
+                to: "user@example.com".to_string(),
+                subject: "Reset".to_string(),
+                text_body: "token".to_string(),
+                html_body: None,
+            })),
+            status: OutboxStatus::Dead.as_str().to_string(),
+            attempts: 8,
+            next_attempt_at: now,
+            last_error: Some("connection refused".to_string()),
+            created_at: now,
+            updated_at: now,
+            sent_at: None,
+        };
+
+        let info = serde_json::to_value(OutboxMessageInfo::from(message)).unwrap();
+        assert_eq!(info["status"], "dead");
+        assert!(info.get("payload").is_none());
+    }
+}
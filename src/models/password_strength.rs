@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use zxcvbn::{time_estimates::CrackTimeSeconds, zxcvbn};
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordStrengthRequest {
+    #[validate(length(min = 1, message = "Password is required"))]
+    #[validate(length(max = 128, message = "Password must be at most 128 characters"))]
+    pub password: String,
+
+    // Optional email so the estimator can penalise passwords built from it
+    #[serde(default)]
+    #[validate(length(max = 254, message = "Email must be at most 254 characters"))]
+    pub email: Option<String>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct PasswordStrengthResponse {
+    pub score: u8,
+    pub min_score: u8,
+    pub acceptable: bool,
+    pub crack_time_seconds: f64,
+    pub crack_time_display: String,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl PasswordStrengthResponse {
+    /// Estimate password strength with zxcvbn.
+    ///
+    /// Crack times assume an offline attack against a slow hash (10^4 guesses/second),
+    /// which is the relevant scenario for bcrypt-hashed passwords.
+    pub fn evaluate(password: &str, user_inputs: &[&str], min_score: u8) -> Self {
+        let entropy = zxcvbn(password, user_inputs);
+        let score = u8::from(entropy.score());
+        let crack_time = entropy.crack_times().offline_slow_hashing_1e4_per_second();
+
+        let (warning, suggestions) = match entropy.feedback() {
+            Some(feedback) => (
+                feedback.warning().map(|w| w.to_string()),
+                feedback
+                    .suggestions()
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+
+        Self {
+            score,
+            min_score,
+            acceptable: score >= min_score,
+            crack_time_seconds: match crack_time {
+                CrackTimeSeconds::Integer(seconds) => seconds as f64,
+                CrackTimeSeconds::Float(seconds) => seconds,
+            },
+            crack_time_display: crack_time.to_string(),
+            warning,
+            suggestions,
+        }
+    }
+}
+
+/// Split an email address into the tokens zxcvbn should treat as user-specific.
+pub fn email_user_inputs(email: &str) -> Vec<String> {
+    let email = email.trim().to_lowercase();
+    let mut inputs = vec![email.clone()];
+
+    if let Some((local, domain)) = email.split_once('@') {
+        inputs.extend(
+            local
+                .split(|c: char| !c.is_alphanumeric())
+                .chain(domain.split('.'))
+                .filter(|part| part.len() >= 3)
+                .map(str::to_string),
+        );
+    }
+
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::auth_user::PASSWORD_MAX_LENGTH;
+
+    #[test]
+    fn test_common_password_is_rejected() {
+        let result = PasswordStrengthResponse::evaluate("Password1!", &[], 3);
+
+        assert!(result.score < 3);
+        assert!(!result.acceptable);
+        assert!(!result.suggestions.is_empty() || result.warning.is_some());
+    }
+
+    #[test]
+    fn test_strong_passphrase_is_accepted() {
+        let result = PasswordStrengthResponse::evaluate("correct-Horse7-battery-staple!", &[], 3);
+
+        assert!(result.acceptable);
+        assert_eq!(result.min_score, 3);
+        assert!(result.crack_time_seconds > 0.0);
+    }
+
+    #[test]
+    fn test_user_inputs_lower_score() {
+        let password = "JohnathanSmithers1987!";
+        let inputs = email_user_inputs("johnathan.smithers1987@example.com");
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+
+        let without = PasswordStrengthResponse::evaluate(password, &[], 3);
+        let with = PasswordStrengthResponse::evaluate(password, &inputs, 3);
+
+        assert!(with.crack_time_seconds < without.crack_time_seconds);
+    }
+
+    #[test]
+    fn test_email_user_inputs() {
+        let inputs = email_user_inputs("Jane.Doe@Example.com");
+
+        assert!(inputs.contains(&"jane.doe@example.com".to_string()));
+        assert!(inputs.contains(&"jane".to_string()));
+        assert!(inputs.contains(&"doe".to_string()));
+        assert!(inputs.contains(&"example".to_string()));
+    }
+
+    #[test]
+    fn test_request_length_limits() {
+        let request =
+            |password: String, email: Option<String>| PasswordStrengthRequest { password, email };
+
+        assert!(request("a".repeat(PASSWORD_MAX_LENGTH), None)
+            .validate()
+            .is_ok());
+        assert!(request("a".repeat(PASSWORD_MAX_LENGTH + 1), None)
+            .validate()
+            .is_err());
+        assert!(request(
+            "Password1!".to_string(),
+            Some(format!("{}@example.com", "a".repeat(250)))
+        )
+        .validate()
+        .is_err());
+    }
+}
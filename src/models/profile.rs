@@ -0,0 +1,118 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use validator::ValidationError;
+
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 100;
+pub const AVATAR_URL_MAX_LENGTH: usize = 2048;
+
+// BCP 47 language tag such as "en", "pt-BR" or "zh-Hant-TW"
+static LOCALE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z]{2,3}(-[A-Za-z0-9]{2,8})*$").unwrap());
+
+fn validation_error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(message.into());
+    error
+}
+
+/// Display names are free text without control characters
+pub fn validate_display_name(name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() || name.chars().count() > DISPLAY_NAME_MAX_LENGTH {
+        return Err(validation_error(
+            "display_name_length",
+            "Display name must be between 1 and 100 characters long",
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(validation_error(
+            "display_name_characters",
+            "Display name must not contain control characters",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    if locale.len() > 35 || !LOCALE_REGEX.is_match(locale) {
+        return Err(validation_error(
+            "locale",
+            "Locale must be a language tag such as 'en' or 'pt-BR'",
+        ));
+    }
+    Ok(())
+}
+
+/// Timezones must be IANA names such as `Europe/Madrid`
+pub fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    timezone.parse::<chrono_tz::Tz>().map(|_| ()).map_err(|_| {
+        validation_error(
+            "timezone",
+            "Timezone must be an IANA name such as 'Europe/Madrid'",
+        )
+    })
+}
+
+/// Avatars are linked, not uploaded, and must be served over HTTPS
+pub fn validate_avatar_url(url: &str) -> Result<(), ValidationError> {
+    if url.len() > AVATAR_URL_MAX_LENGTH
+        || !url.starts_with("https://")
+        || !validator::validate_url(url)
+    {
+        return Err(validation_error(
+            "avatar_url",
+            "Avatar URL must be an https:// URL of at most 2048 characters",
+        ));
+    }
+    Ok(())
+}
+
+/// Serde helper for PATCH fields: a missing field stays `None`, `null` becomes `Some(None)`.
+/// Strings are trimmed and an empty string clears the field like `null`.
+pub fn deserialize_patch<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_display_name() {
+        assert!(validate_display_name("Zoë O'Brien").is_ok());
+        assert!(validate_display_name("").is_err());
+        assert!(validate_display_name(&"x".repeat(101)).is_err());
+        assert!(validate_display_name("line\nbreak").is_err());
+    }
+
+    #[test]
+    fn test_validate_locale() {
+        for locale in ["en", "pt-BR", "zh-Hant-TW", "es-419"] {
+            assert!(validate_locale(locale).is_ok(), "{locale}");
+        }
+        for locale in ["e", "english!", "en_US", "-en"] {
+            assert!(validate_locale(locale).is_err(), "{locale}");
+        }
+    }
+
+    #[test]
+    fn test_validate_timezone() {
+        assert!(validate_timezone("Europe/Madrid").is_ok());
+        assert!(validate_timezone("UTC").is_ok());
+        assert!(validate_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_validate_avatar_url() {
+        assert!(validate_avatar_url("https://cdn.example.com/a/1.png").is_ok());
+        assert!(validate_avatar_url("http://cdn.example.com/a/1.png").is_err());
+        assert!(validate_avatar_url("javascript:alert(1)").is_err());
+        assert!(validate_avatar_url("https://").is_err());
+    }
+}
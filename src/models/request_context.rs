@@ -0,0 +1,202 @@
+use actix_web::{dev::Payload, http::header, FromRequest, HttpRequest};
+use ipnet::IpNet;
+use std::{
+    future::{ready, Ready},
+    net::IpAddr,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    models::session::{is_valid_device_id, DEVICE_COOKIE},
+};
+
+/// Details about the incoming request that services need for emails and logging
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accept_language: Option<String>,
+    pub device_id: Option<String>, // from the device cookie, see `models::session`
+    pub request_id: Option<String>, // from `X-Request-Id`, generated if missing
+}
+
+impl RequestContext {
+    pub fn from_http_request(req: &HttpRequest) -> Self {
+        let header_value = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            ip_address: client_ip(req).map(|ip| ip.to_string()),
+            user_agent: header_value(header::USER_AGENT),
+            accept_language: header_value(header::ACCEPT_LANGUAGE),
+            device_id: req
+                .cookie(DEVICE_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+                .filter(|id| is_valid_device_id(id)),
+            request_id: Some(
+                header_value(header::HeaderName::from_static("x-request-id"))
+                    .filter(|id| is_valid_request_id(id))
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            ),
+        }
+    }
+
+    /// Language tags from `Accept-Language`, most preferred first
+    pub fn preferred_locales(&self) -> Vec<String> {
+        self.accept_language
+            .as_deref()
+            .map(parse_accept_language)
+            .unwrap_or_default()
+    }
+}
+
+impl FromRequest for RequestContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self::from_http_request(req)))
+    }
+}
+
+/// Address of the client, taken from `X-Forwarded-For` when the request came through one of
+/// the `TRUSTED_PROXIES`
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|value| value.to_str().ok());
+
+    Some(resolve_client_ip(
+        peer,
+        forwarded_for,
+        &CONFIG.trusted_proxies,
+    ))
+}
+
+// Walk X-Forwarded-For from the right, where our own proxies append, and stop at the first
+// address that is not a trusted proxy; anything further left could be forged by the client
+fn resolve_client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        if !is_trusted(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+
+    client
+}
+
+// Ids set by a proxy or the client are kept for correlating logs, if they look sane
+fn is_valid_request_id(id: &str) -> bool {
+    (1..=128).contains(&id.len()) && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+// Parse e.g. "es-MX,es;q=0.9,en;q=0.5" into tags ordered by quality, skipping "*" and q=0
+fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            (quality > 0.0).then(|| (tag.to_string(), quality))
+        })
+        .collect();
+
+    // Stable sort keeps header order for equal qualities
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(
+            parse_accept_language("en;q=0.5, es-MX, es;q=0.9, *;q=0.1, fr;q=0"),
+            vec!["es-MX", "es", "en"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let ip = |value: &str| value.parse::<IpAddr>().unwrap();
+
+        // Untrusted peers cannot claim another address
+        assert_eq!(
+            resolve_client_ip(ip("203.0.113.7"), Some("198.51.100.1"), &trusted),
+            ip("203.0.113.7")
+        );
+        // Trusted proxies are skipped, forged entries left of the client are ignored
+        assert_eq!(
+            resolve_client_ip(
+                ip("10.0.0.1"),
+                Some("1.2.3.4, 203.0.113.7, 10.0.0.2"),
+                &trusted
+            ),
+            ip("203.0.113.7")
+        );
+        // Garbage stops the walk at the last address we could trust
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.1"), Some("203.0.113.7, unknown"), &trusted),
+            ip("10.0.0.1")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.1"), None, &trusted),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_from_http_request() {
+        let req = TestRequest::default()
+            .insert_header((header::USER_AGENT, "curl/8.0"))
+            .insert_header((header::ACCEPT_LANGUAGE, "es"))
+            .peer_addr("203.0.113.7:5000".parse().unwrap())
+            .to_http_request();
+
+        let ctx = RequestContext::from_http_request(&req);
+        assert_eq!(ctx.ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(ctx.user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(ctx.preferred_locales(), vec!["es"]);
+        assert!(ctx.request_id.is_some());
+
+        let req = TestRequest::default()
+            .insert_header(("X-Request-Id", "req-42"))
+            .to_http_request();
+        let ctx = RequestContext::from_http_request(&req);
+        assert_eq!(ctx.request_id.as_deref(), Some("req-42"));
+
+        let req = TestRequest::default()
+            .insert_header(("X-Request-Id", "two words"))
+            .to_http_request();
+        let ctx = RequestContext::from_http_request(&req);
+        assert_ne!(ctx.request_id.as_deref(), Some("two words"));
+    }
+}
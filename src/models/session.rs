@@ -0,0 +1,135 @@
+use actix_web::cookie::{time, Cookie, SameSite};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    models::{login_event::describe_device, request_context::RequestContext, token::hash_token},
+};
+
+/// Cookie holding a random id that tells the user's browsers apart
+pub const DEVICE_COOKIE: &str = "device_id";
+
+// Browsers cap cookie lifetimes at 400 days
+const DEVICE_COOKIE_MAX_AGE_DAYS: i64 = 400;
+
+/// Session started by a successful login; its id is carried in the access token as `sid`
+#[derive(Debug, Clone)]
+pub struct UserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub device_key: String, // groups the sessions of one device
+    pub device: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl UserSession {
+    pub fn new(user_id: Uuid, ctx: &RequestContext) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            device_key: device_key(ctx),
+            device: describe_device(ctx.user_agent.as_deref()),
+            ip_address: ctx.ip_address.clone(),
+            user_agent: ctx.user_agent.clone(),
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+}
+
+/// Fingerprint of the requesting device: its device cookie together with the user agent, so
+/// a cookie copied to another browser doesn't pass for the original
+pub fn device_key(ctx: &RequestContext) -> String {
+    hash_token(&format!(
+        "{}:{}",
+        ctx.device_id.as_deref().unwrap_or_default(),
+        ctx.user_agent.as_deref().unwrap_or_default()
+    ))
+}
+
+/// Device ids are issued by `generate_token`; anything else is ignored
+pub fn is_valid_device_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Long-lived cookie that lets later logins recognize the device
+pub fn device_cookie(device_id: &str) -> Cookie<'static> {
+    Cookie::build(DEVICE_COOKIE, device_id.to_string())
+        .path("/api/v1/auth")
+        .http_only(true)
+        .secure(CONFIG.api_base_url.starts_with("https://"))
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::days(DEVICE_COOKIE_MAX_AGE_DAYS))
+        .finish()
+}
+
+// Response models
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct DeviceInfo {
+    pub id: String, // device key, used to revoke the device
+    pub device: String,
+    pub ip_address: Option<String>, // of the most recent session
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub sessions: i64,
+    pub current: bool, // whether the request was made from this device
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceListResponse {
+    pub devices: Vec<DeviceInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::token::generate_token;
+
+    #[test]
+    fn test_sessions_from_one_browser_share_a_device() {
+        let user_id = Uuid::new_v4();
+        let ctx = RequestContext {
+            user_agent: Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+
+        let first = UserSession::new(user_id, &ctx);
+        let second = UserSession::new(user_id, &ctx);
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.device_key, second.device_key);
+        assert_eq!(first.device, "Firefox 128.0 on Linux");
+
+        // Same browser with another device cookie
+        let other = UserSession::new(
+            user_id,
+            &RequestContext {
+                device_id: Some(generate_token()),
+                ..ctx.clone()
+            },
+        );
+        assert_ne!(first.device_key, other.device_key);
+    }
+
+    #[test]
+    fn test_device_cookie() {
+        let device_id = generate_token();
+        assert!(is_valid_device_id(&device_id));
+        assert!(!is_valid_device_id("forged"));
+
+        let cookie = device_cookie(&device_id);
+        assert_eq!(cookie.name(), DEVICE_COOKIE);
+        assert_eq!(cookie.value(), device_id);
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+    }
+}
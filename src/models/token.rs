@@ -0,0 +1,147 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Generate a random single-use token to hand to the user.
+///
+/// Only `hash_token(token)` should ever be stored.
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// SHA-256 of a token as lowercase hex, the form tokens are stored and looked up in
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// Compare a presented token against a stored hash in constant time
+pub fn token_matches(token: &str, stored_hash: &str) -> bool {
+    constant_time_eq(hash_token(token).as_bytes(), stored_hash.as_bytes())
+}
+
+/// Lowercase hex encoding of `bytes`
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Bytes of a hex string in either case, or `None` if it isn't valid hex
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Compare secrets in constant time; only their lengths may differ observably
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// HMAC-SHA256 keyed with `key`, ready to be fed the message
+pub fn hmac_sha256(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+/// HMAC-SHA256 of `message` as lowercase hex
+pub fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    to_hex(
+        &hmac_sha256(key)
+            .chain_update(message)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Check a hex HMAC-SHA256 of `message` in constant time
+pub fn hmac_sha256_hex_matches(key: &[u8], message: &[u8], mac: &str) -> bool {
+    from_hex(mac).is_some_and(|tag| {
+        hmac_sha256(key)
+            .chain_update(message)
+            .verify_slice(&tag)
+            .is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_tokens_are_unique() {
+        let first = generate_token();
+        let second = generate_token();
+
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_hash_token() {
+        // echo -n "abc" | sha256sum
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_token_matches() {
+        let token = generate_token();
+        let stored = hash_token(&token);
+
+        assert!(token_matches(&token, &stored));
+        assert!(!token_matches("other", &stored));
+        assert!(!token_matches(&token, &token));
+        assert!(!token_matches(&token, ""));
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bytes = [0x00, 0x0f, 0xa5, 0xff];
+
+        assert_eq!(to_hex(&bytes), "000fa5ff");
+        assert_eq!(from_hex("000fa5ff").unwrap(), bytes);
+        assert_eq!(from_hex("000FA5FF").unwrap(), bytes);
+        assert!(from_hex("000fa5f").is_none());
+        assert!(from_hex("zz").is_none());
+        assert!(from_hex("éé").is_none());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"Secret"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_hmac_sha256_hex() {
+        // RFC 4231, test case 2
+        let mac = hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            mac,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        assert!(hmac_sha256_hex_matches(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac
+        ));
+        assert!(hmac_sha256_hex_matches(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac.to_uppercase()
+        ));
+        assert!(!hmac_sha256_hex_matches(b"Jefe", b"what do ya want?", &mac));
+        assert!(!hmac_sha256_hex_matches(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac[..62]
+        ));
+    }
+}
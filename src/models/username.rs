@@ -0,0 +1,125 @@
+use serde::{Deserialize, Deserializer};
+use validator::ValidationError;
+
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 30;
+
+/// Names that could be mistaken for the service or its staff, or collide with routes
+const RESERVED_USERNAMES: &[&str] = &[
+    "abuse",
+    "admin",
+    "administrator",
+    "anonymous",
+    "api",
+    "auth",
+    "help",
+    "hostmaster",
+    "info",
+    "login",
+    "logout",
+    "mail",
+    "me",
+    "moderator",
+    "no-reply",
+    "noreply",
+    "null",
+    "postmaster",
+    "register",
+    "root",
+    "security",
+    "settings",
+    "staff",
+    "support",
+    "system",
+    "undefined",
+    "user",
+    "webmaster",
+    "www",
+];
+
+/// Whether a username is reserved, ignoring case
+pub fn is_reserved_username(username: &str) -> bool {
+    let username = username.to_ascii_lowercase();
+    RESERVED_USERNAMES.contains(&username.as_str())
+}
+
+/// Usernames are 3-30 ASCII letters, digits, `.`, `_` or `-`, start with a letter and end
+/// with a letter or digit. They never contain `@`, so they cannot be confused with emails.
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    let error = |code: &'static str, message: &'static str| {
+        let mut error = ValidationError::new(code);
+        error.message = Some(message.into());
+        Err(error)
+    };
+
+    if username.len() < USERNAME_MIN_LENGTH || username.len() > USERNAME_MAX_LENGTH {
+        return error(
+            "username_length",
+            "Username must be between 3 and 30 characters long",
+        );
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+    {
+        return error(
+            "username_characters",
+            "Username may only contain letters, digits, '.', '_' and '-'",
+        );
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return error("username_start", "Username must start with a letter");
+    }
+    if !username.ends_with(|c: char| c.is_ascii_alphanumeric()) {
+        return error("username_end", "Username must end with a letter or a digit");
+    }
+    if is_reserved_username(username) {
+        return error("username_reserved", "Username is reserved");
+    }
+
+    Ok(())
+}
+
+/// Serde helper trimming optional username fields of incoming requests
+pub fn deserialize_username<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)
+        .map(|username| username.map(|username| username.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_usernames() {
+        for username in ["alice", "Bob_42", "j.doe", "mary-jane", "abc"] {
+            assert!(validate_username(username).is_ok(), "{username}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_usernames() {
+        for username in [
+            "al",
+            "a".repeat(31).as_str(),
+            "alice@example.com",
+            "1alice",
+            "_alice",
+            "alice.",
+            "al ice",
+            "älice",
+        ] {
+            assert!(validate_username(username).is_err(), "{username}");
+        }
+    }
+
+    #[test]
+    fn test_reserved_usernames() {
+        assert!(is_reserved_username("Admin"));
+        assert!(validate_username("ROOT").is_err());
+        assert!(!is_reserved_username("admiral"));
+    }
+}
@@ -0,0 +1,389 @@
+use chrono::{DateTime, Utc};
+use hmac::Mac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::models::{
+    profile::deserialize_patch,
+    token::{generate_token, hmac_sha256, to_hex},
+};
+
+pub const WEBHOOK_URL_MAX_LENGTH: usize = 2048;
+
+/// Keys of event `data` identifying the user, removed from logged payloads when the account
+/// is erased
+pub const WEBHOOK_PERSONAL_DATA: [&str; 2] = ["email", "username"];
+
+// Request headers of a delivery
+pub const WEBHOOK_ID_HEADER: &str = "X-Webhook-Id";
+pub const WEBHOOK_EVENT_HEADER: &str = "X-Webhook-Event";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Identity event that can be sent to webhook subscriptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEventType {
+    #[serde(rename = "user.registered")]
+    UserRegistered,
+    #[serde(rename = "user.email_verified")]
+    EmailVerified,
+    #[serde(rename = "user.password_changed")]
+    PasswordChanged, // by the user or through a reset link
+    #[serde(rename = "user.locked")]
+    UserLocked, // failed logins started a backoff block of the account
+}
+
+impl WebhookEventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEventType::UserRegistered => "user.registered",
+            WebhookEventType::EmailVerified => "user.email_verified",
+            WebhookEventType::PasswordChanged => "user.password_changed",
+            WebhookEventType::UserLocked => "user.locked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Delivered => "delivered",
+            WebhookDeliveryStatus::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub secret: String,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Column list matching the fields of `WebhookSubscription`, for use with `query_as`
+pub const WEBHOOK_SUBSCRIPTION_COLUMNS: &str = r#"
+    id, url, events, secret, description, is_active, created_at, updated_at
+"#;
+
+/// Body of every delivery; `id` stays the same when a delivery is retried or replayed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+    pub data: Value,
+}
+
+impl WebhookPayload {
+    pub fn new(event_type: WebhookEventType, data: Value) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type: event_type.as_str().to_string(),
+            created_at: Utc::now(),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: Json<Value>,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub replay_of: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Column list matching the fields of `WebhookDelivery`, for use with `query_as`
+pub const WEBHOOK_DELIVERY_COLUMNS: &str = r#"
+    id, subscription_id, event_id, event_type, payload, status, attempts, next_attempt_at,
+    last_status_code, last_error, replay_of, created_at, updated_at, delivered_at
+"#;
+
+/// Claimed delivery with the endpoint it goes to
+#[derive(Debug, Clone, FromRow)]
+pub struct WebhookDispatch {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: Json<Value>,
+    pub attempts: i32, // including the current one
+    pub url: String,
+    pub secret: String,
+}
+
+/// Result of one delivery attempt; any response but a 2xx is a failure
+#[derive(Debug, Clone, Default)]
+pub struct WebhookAttemptOutcome {
+    pub status_code: Option<u16>, // None if no response was received
+    pub error: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: i32,
+}
+
+impl WebhookAttemptOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct WebhookDeliveryAttempt {
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Secret generated for subscriptions created without one
+pub fn generate_webhook_secret() -> String {
+    format!("whsec_{}", generate_token())
+}
+
+/// Value of the signature header: HMAC-SHA256 of `"{timestamp}.{body}"`, keyed with the
+/// subscription secret, as lowercase hex
+pub fn webhook_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mac = hmac_sha256(secret.as_bytes())
+        .chain_update(format!("{timestamp}.").as_bytes())
+        .chain_update(body)
+        .finalize();
+    format!("sha256={}", to_hex(&mac.into_bytes()))
+}
+
+/// Receivers may be on the local network, so plain http:// is allowed
+pub fn validate_webhook_url(url: &str) -> Result<(), ValidationError> {
+    if url.len() > WEBHOOK_URL_MAX_LENGTH
+        || !(url.starts_with("https://") || url.starts_with("http://"))
+        || !validator::validate_url(url)
+    {
+        let mut error = ValidationError::new("webhook_url");
+        error.message =
+            Some("Webhook URL must be an http(s):// URL of at most 2048 characters".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+// Request models
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateWebhookSubscriptionRequest {
+    #[validate(custom = "validate_webhook_url")]
+    pub url: String,
+
+    #[validate(length(min = 1, message = "At least one event is required"))]
+    pub events: Vec<WebhookEventType>,
+
+    // Generated when omitted
+    #[validate(length(min = 16, max = 255, message = "Secret must be 16-255 characters"))]
+    pub secret: Option<String>,
+
+    #[validate(length(max = 255, message = "Description must be at most 255 characters"))]
+    pub description: Option<String>,
+}
+
+/// Partial subscription update: omitted fields are kept, a `null` description is cleared
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct UpdateWebhookSubscriptionRequest {
+    #[validate(custom = "validate_webhook_url")]
+    pub url: Option<String>,
+
+    #[validate(length(min = 1, message = "At least one event is required"))]
+    pub events: Option<Vec<WebhookEventType>>,
+
+    #[validate(length(min = 16, max = 255, message = "Secret must be 16-255 characters"))]
+    pub secret: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_patch")]
+    #[validate(length(max = 255, message = "Description must be at most 255 characters"))]
+    pub description: Option<Option<String>>,
+
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<WebhookDeliveryStatus>,
+
+    pub event_type: Option<WebhookEventType>,
+
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200"))]
+    pub limit: Option<i64>,
+
+    // next_cursor of the previous page
+    pub cursor: Option<String>,
+}
+
+// Response models
+#[derive(Debug, Serialize)]
+pub struct WebhookSubscriptionInfo {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<WebhookSubscription> for WebhookSubscriptionInfo {
+    fn from(subscription: WebhookSubscription) -> Self {
+        Self {
+            id: subscription.id,
+            url: subscription.url,
+            events: subscription.events,
+            description: subscription.description,
+            is_active: subscription.is_active,
+            created_at: subscription.created_at,
+            updated_at: subscription.updated_at,
+        }
+    }
+}
+
+/// Returned when the subscription is created; the secret can't be read back afterwards
+#[derive(Debug, Serialize)]
+pub struct WebhookSubscriptionWithSecret {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscriptionInfo,
+    pub secret: String,
+}
+
+impl From<WebhookSubscription> for WebhookSubscriptionWithSecret {
+    fn from(subscription: WebhookSubscription) -> Self {
+        let secret = subscription.secret.clone();
+        Self {
+            subscription: WebhookSubscriptionInfo::from(subscription),
+            secret,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDeliveryListResponse {
+    pub deliveries: Vec<WebhookDelivery>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDeliveryDetails {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub attempt_log: Vec<WebhookDeliveryAttempt>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_webhook_signature() {
+        let body = br#"{"id":"1"}"#;
+        let signature = webhook_signature("whsec_test", 1_700_000_000, body);
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(
+            signature,
+            webhook_signature("whsec_test", 1_700_000_000, body)
+        );
+        assert_ne!(
+            signature,
+            webhook_signature("whsec_test", 1_700_000_001, body)
+        );
+        assert_ne!(
+            signature,
+            webhook_signature("whsec_other", 1_700_000_000, body)
+        );
+        assert_ne!(
+            signature,
+            webhook_signature("whsec_test", 1_700_000_000, br#"{"id":"2"}"#)
+        );
+    }
+
+    #[test]
+    fn test_webhook_payload_serialization() {
+        let payload = WebhookPayload::new(
+            WebhookEventType::UserLocked,
+            json!({ "user_id": Uuid::nil(), "retry_after": 60 }),
+        );
+        let value = serde_json::to_value(&payload).unwrap();
+
+        assert_eq!(value["type"], "user.locked");
+        assert_eq!(value["data"]["retry_after"], 60);
+        assert!(value["id"].is_string());
+        assert!(value["created_at"].is_string());
+    }
+
+    #[test]
+    fn test_validate_webhook_url() {
+        assert!(validate_webhook_url("https://crm.example.com/hooks/identity").is_ok());
+        assert!(validate_webhook_url("http://localhost:9000/webhook").is_ok());
+        assert!(validate_webhook_url("ftp://crm.example.com/hooks").is_err());
+        assert!(validate_webhook_url("javascript:alert(1)").is_err());
+        assert!(validate_webhook_url("https://").is_err());
+    }
+
+    #[test]
+    fn test_subscription_request_events() {
+        let request: CreateWebhookSubscriptionRequest = serde_json::from_value(json!({
+            "url": "https://crm.example.com/hooks",
+            "events": ["user.registered", "user.password_changed"]
+        }))
+        .unwrap();
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.events,
+            vec![
+                WebhookEventType::UserRegistered,
+                WebhookEventType::PasswordChanged
+            ]
+        );
+
+        assert!(
+            serde_json::from_value::<CreateWebhookSubscriptionRequest>(json!({
+                "url": "https://crm.example.com/hooks",
+                "events": ["user.deleted"]
+            }))
+            .is_err()
+        );
+
+        let request: CreateWebhookSubscriptionRequest = serde_json::from_value(json!({
+            "url": "https://crm.example.com/hooks",
+            "events": [],
+            "secret": "short"
+        }))
+        .unwrap();
+        let errors = request.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("events"));
+        assert!(errors.field_errors().contains_key("secret"));
+    }
+}
@@ -0,0 +1,106 @@
+use futures_util::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    errors::ServiceResult,
+    rate_limit::{RateLimitDecision, RateLimitRule, RateLimitStore, PRUNE_EVERY},
+};
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant, // from then on the bucket is as good as absent
+}
+
+/// Buckets kept in process memory; each instance limits on its own, so only suitable for
+/// single-instance deployments
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    requests: AtomicU64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take_token(&self, key: &str, rule: RateLimitRule) -> RateLimitDecision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self
+            .requests
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(PRUNE_EVERY)
+        {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let (tokens, elapsed) = buckets
+            .get(key)
+            .map_or((f64::from(rule.capacity), 0.0), |bucket| {
+                (bucket.tokens, (now - bucket.updated_at).as_secs_f64())
+            });
+        let (tokens, decision) = rule.take(tokens, elapsed);
+
+        buckets.insert(
+            key.to_string(),
+            Bucket {
+                tokens,
+                updated_at: now,
+                full_at: now + Duration::from_secs_f64(rule.seconds_until_full(tokens)),
+            },
+        );
+
+        decision
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn take<'a>(
+        &'a self,
+        key: &'a str,
+        rule: RateLimitRule,
+    ) -> BoxFuture<'a, ServiceResult<RateLimitDecision>> {
+        Box::pin(async move { Ok(self.take_token(key, rule)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store_limits_each_key() {
+        let store = MemoryStore::new();
+        let rule = RateLimitRule::new(2, 3600);
+
+        assert_eq!(
+            store.take("a", rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+        assert_eq!(
+            store.take("a", rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+        assert!(matches!(
+            store.take("a", rule).await.unwrap(),
+            RateLimitDecision::Deny { retry_after } if retry_after > 1700
+        ));
+
+        // Other keys have their own bucket
+        assert_eq!(
+            store.take("b", rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+    }
+}
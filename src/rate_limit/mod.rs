@@ -0,0 +1,176 @@
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{
+    config::Config,
+    errors::{ServiceError, ServiceResult},
+};
+
+pub mod memory_store;
+pub mod postgres_store;
+
+pub use memory_store::MemoryStore;
+pub use postgres_store::PostgresStore;
+
+// Buckets are pruned once every this many requests
+const PRUNE_EVERY: u64 = 1000;
+
+/// Token bucket: up to `capacity` requests at once, refilled evenly over `period` seconds
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RateLimitRule {
+    pub capacity: u32,
+    pub period: u64, // in seconds
+}
+
+impl RateLimitRule {
+    pub const fn new(capacity: u32, period: u64) -> Self {
+        Self { capacity, period }
+    }
+
+    /// Tokens added back per second
+    pub fn refill_rate(&self) -> f64 {
+        f64::from(self.capacity) / self.period as f64
+    }
+
+    /// Seconds until a bucket holding `tokens` is full again, i.e. as good as new
+    pub fn seconds_until_full(&self, tokens: f64) -> f64 {
+        (f64::from(self.capacity) - tokens).max(0.0) / self.refill_rate()
+    }
+
+    /// Refill a bucket that last held `tokens` `elapsed` seconds ago and try to take a token
+    /// from it, returning the tokens left and the decision
+    pub fn take(&self, tokens: f64, elapsed: f64) -> (f64, RateLimitDecision) {
+        let tokens = (tokens + elapsed.max(0.0) * self.refill_rate()).min(f64::from(self.capacity));
+
+        if tokens >= 1.0 {
+            (tokens - 1.0, RateLimitDecision::Allow)
+        } else {
+            // The epsilon keeps float noise from adding a second to exact waits
+            let wait = (1.0 - tokens) / self.refill_rate() - 1e-9;
+            let retry_after = wait.ceil().max(1.0) as u64;
+            (tokens, RateLimitDecision::Deny { retry_after })
+        }
+    }
+}
+
+/// Parses "capacity/period", e.g. "10/60" for 10 requests per minute
+impl FromStr for RateLimitRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate limit '{value}', expected capacity/seconds");
+
+        let (capacity, period) = value.trim().split_once('/').ok_or_else(invalid)?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| invalid())?;
+        let period: u64 = period.trim().parse().map_err(|_| invalid())?;
+        if capacity == 0 || period == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self { capacity, period })
+    }
+}
+
+impl fmt::Display for RateLimitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.capacity, self.period)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allow,
+    Deny { retry_after: u64 }, // in seconds
+}
+
+/// Keeps token buckets; implementations are selected with `RATE_LIMIT_BACKEND`
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket under `key`, creating a full one if there is none
+    fn take<'a>(
+        &'a self,
+        key: &'a str,
+        rule: RateLimitRule,
+    ) -> BoxFuture<'a, ServiceResult<RateLimitDecision>>;
+}
+
+/// Shared by the `RateLimit` middleware of every rate-limited route
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>) -> Self {
+        Self { store }
+    }
+
+    /// Build the rate limiter configured by `RATE_LIMIT_BACKEND`, or `None` when disabled
+    pub fn from_config(config: &Config, db_pool: Pool<Postgres>) -> Option<Self> {
+        let store: Arc<dyn RateLimitStore> = match config.rate_limit_backend.as_str() {
+            "memory" => Arc::new(MemoryStore::new()),
+            "postgres" => Arc::new(PostgresStore::new(db_pool)),
+            _ => return None,
+        };
+
+        Some(Self::new(store))
+    }
+
+    /// Take a token for `key`, failing with `RateLimited` when the bucket is empty.
+    ///
+    /// If the store is unavailable the request is let through rather than locking everyone
+    /// out.
+    pub async fn check(&self, key: &str, rule: RateLimitRule) -> ServiceResult<()> {
+        match self.store.take(key, rule).await {
+            Ok(RateLimitDecision::Allow) => Ok(()),
+            Ok(RateLimitDecision::Deny { retry_after }) => {
+                Err(ServiceError::RateLimited(retry_after))
+            }
+            Err(e) => {
+                log::error!("Rate limit check failed, allowing the request: {e}");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            "10/60".parse::<RateLimitRule>(),
+            Ok(RateLimitRule::new(10, 60))
+        );
+        assert_eq!(
+            " 5 / 900 ".parse::<RateLimitRule>(),
+            Ok(RateLimitRule::new(5, 900))
+        );
+        for value in ["", "10", "0/60", "10/0", "-1/60", "ten/60"] {
+            assert!(value.parse::<RateLimitRule>().is_err(), "{value}");
+        }
+        assert_eq!(RateLimitRule::new(3, 900).to_string(), "3/900");
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let rule = RateLimitRule::new(2, 60); // one token every 30 seconds
+
+        let (tokens, decision) = rule.take(2.0, 0.0);
+        assert_eq!(decision, RateLimitDecision::Allow);
+        let (tokens, decision) = rule.take(tokens, 0.0);
+        assert_eq!(decision, RateLimitDecision::Allow);
+        let (tokens, decision) = rule.take(tokens, 10.0);
+        assert_eq!(decision, RateLimitDecision::Deny { retry_after: 20 });
+
+        // Refilled after waiting, but never beyond the capacity
+        let (_, decision) = rule.take(tokens, 20.0);
+        assert_eq!(decision, RateLimitDecision::Allow);
+        let (tokens, _) = rule.take(0.0, 3600.0);
+        assert_eq!(tokens, 1.0);
+        assert_eq!(rule.seconds_until_full(tokens), 30.0);
+    }
+}
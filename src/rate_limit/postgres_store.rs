@@ -0,0 +1,185 @@
+use futures_util::future::BoxFuture;
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    errors::ServiceResult,
+    rate_limit::{RateLimitDecision, RateLimitRule, RateLimitStore, PRUNE_EVERY},
+};
+
+/// Buckets in the `rate_limit_buckets` table, shared by every instance of the service
+pub struct PostgresStore {
+    db_pool: Pool<Postgres>,
+    requests: AtomicU64,
+}
+
+impl PostgresStore {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self {
+            db_pool,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    async fn take_token(&self, key: &str, rule: RateLimitRule) -> ServiceResult<RateLimitDecision> {
+        if self
+            .requests
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(PRUNE_EVERY)
+        {
+            self.prune().await?;
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+
+        // Create a full bucket or lock the existing one; elapsed time uses the database
+        // clock, so instances with skewed clocks agree
+        let (tokens, elapsed): (f64, f64) = sqlx::query_as(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at, full_at)
+            VALUES ($1, $2, NOW(), NOW())
+            ON CONFLICT (key) DO UPDATE SET key = EXCLUDED.key
+            RETURNING tokens, EXTRACT(EPOCH FROM NOW() - updated_at)::FLOAT8
+            "#,
+        )
+        .bind(key)
+        .bind(f64::from(rule.capacity))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (tokens, decision) = rule.take(tokens, elapsed);
+
+        sqlx::query(
+            r#"
+            UPDATE rate_limit_buckets
+            SET tokens = $1, updated_at = NOW(), full_at = NOW() + make_interval(secs => $2)
+            WHERE key = $3
+            "#,
+        )
+        .bind(tokens)
+        .bind(rule.seconds_until_full(tokens))
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(decision)
+    }
+
+    // Full buckets behave exactly like missing ones, so they can go
+    async fn prune(&self) -> ServiceResult<()> {
+        sqlx::query("DELETE FROM rate_limit_buckets WHERE full_at < NOW()")
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn take<'a>(
+        &'a self,
+        key: &'a str,
+        rule: RateLimitRule,
+    ) -> BoxFuture<'a, ServiceResult<RateLimitDecision>> {
+        Box::pin(self.take_token(key, rule))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+    use futures_util::future::join_all;
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_postgres_store_limits_each_key() {
+        let store = PostgresStore::new(test_pool().await);
+        let rule = RateLimitRule::new(2, 3600);
+        let key = uuid::Uuid::new_v4().to_string();
+
+        assert_eq!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+        assert_eq!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+        assert!(matches!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Deny { retry_after } if retry_after > 0 && retry_after <= 1800
+        ));
+
+        // Other keys have their own bucket
+        let other = uuid::Uuid::new_v4().to_string();
+        assert_eq!(
+            store.take(&other, rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_postgres_store_is_consistent_under_concurrency() {
+        let store = PostgresStore::new(test_pool().await);
+        let rule = RateLimitRule::new(5, 3600);
+        let key = uuid::Uuid::new_v4().to_string();
+
+        let decisions = join_all((0..20).map(|_| store.take(&key, rule))).await;
+        let allowed = decisions
+            .into_iter()
+            .filter(|decision| matches!(decision, Ok(RateLimitDecision::Allow)))
+            .count();
+        assert_eq!(allowed, 5);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_postgres_store_refills_and_prunes() {
+        let pool = test_pool().await;
+        let store = PostgresStore::new(pool.clone());
+        let rule = RateLimitRule::new(1, 60);
+        let key = uuid::Uuid::new_v4().to_string();
+
+        assert_eq!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+        assert!(matches!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Deny { .. }
+        ));
+
+        // A period later the bucket has refilled
+        sqlx::query(
+            "UPDATE rate_limit_buckets SET updated_at = updated_at - INTERVAL '61 seconds' WHERE key = $1",
+        )
+        .bind(&key)
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            store.take(&key, rule).await.unwrap(),
+            RateLimitDecision::Allow
+        );
+
+        // Buckets past the time they are full again are pruned
+        let count = || {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM rate_limit_buckets WHERE key = $1")
+                .bind(&key)
+                .fetch_one(&pool)
+        };
+        store.prune().await.unwrap();
+        assert_eq!(count().await.unwrap(), 1);
+        sqlx::query(
+            "UPDATE rate_limit_buckets SET full_at = NOW() - INTERVAL '1 second' WHERE key = $1",
+        )
+        .bind(&key)
+        .execute(&pool)
+        .await
+        .unwrap();
+        store.prune().await.unwrap();
+        assert_eq!(count().await.unwrap(), 0);
+    }
+}
@@ -1,26 +1,75 @@
 use actix_web::web;
 
+use crate::config::CONFIG;
 use crate::handlers::auth_handlers::{
-    change_password, confirm_password_reset, get_user_info, health_check, login, logout,
-    refresh_token, register, request_password_reset, verify_email,
+    change_password, confirm_email_change, confirm_password_reset, delete_account,
+    download_data_export, get_data_export, get_devices, get_login_history, get_user_info,
+    health_check, login, logout, password_strength, refresh_token, register, request_data_export,
+    request_email_change, request_password_reset, resend_verification, revoke_device, set_username,
+    update_profile, verify_email, verify_login,
 };
-use crate::middleware::auth::JwtAuth;
+use crate::middleware::{auth::JwtAuth, rate_limit::RateLimit};
 
 pub fn configure_auth_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1/auth")
             // Public routes (no authentication required)
-            .route("/register", web::post().to(register))
-            .route("/login", web::post().to(login))
-            .route("/verify-email", web::get().to(verify_email))
-            .route(
-                "/request-password-reset",
-                web::post().to(request_password_reset),
+            .service(
+                web::resource("/register")
+                    .wrap(
+                        RateLimit::new("register")
+                            .per_ip(CONFIG.rate_limit_register_ip)
+                            .per_account(CONFIG.rate_limit_register_account),
+                    )
+                    .route(web::post().to(register)),
             )
-            .route(
-                "/confirm-password-reset",
-                web::post().to(confirm_password_reset),
+            .service(
+                web::resource("/login")
+                    .wrap(
+                        // The login backoff protects the account as a whole; this bucket
+                        // must not let one client lock out the others
+                        RateLimit::new("login")
+                            .per_ip(CONFIG.rate_limit_login_ip)
+                            .per_ip_and_account(CONFIG.rate_limit_login_ip_account),
+                    )
+                    .route(web::post().to(login)),
+            )
+            .service(
+                web::resource("/login/verify")
+                    .wrap(RateLimit::new("login_verify").per_ip(CONFIG.rate_limit_login_ip))
+                    .route(web::post().to(verify_login)),
+            )
+            .service(
+                web::resource("/verify-email")
+                    .wrap(RateLimit::new("verify_email").per_ip(CONFIG.rate_limit_token_ip))
+                    .route(web::get().to(verify_email)),
+            )
+            .service(
+                web::resource("/resend-verification")
+                    .wrap(
+                        RateLimit::new("resend_verification")
+                            .per_ip(CONFIG.rate_limit_resend_verification_ip)
+                            .per_account(CONFIG.rate_limit_resend_verification_account),
+                    )
+                    .route(web::post().to(resend_verification)),
+            )
+            .service(
+                web::resource("/request-password-reset")
+                    .wrap(
+                        RateLimit::new("password_reset")
+                            .per_ip(CONFIG.rate_limit_password_reset_ip)
+                            .per_account(CONFIG.rate_limit_password_reset_account),
+                    )
+                    .route(web::post().to(request_password_reset)),
             )
+            .service(
+                web::resource("/confirm-password-reset")
+                    .wrap(
+                        RateLimit::new("confirm_password_reset").per_ip(CONFIG.rate_limit_token_ip),
+                    )
+                    .route(web::post().to(confirm_password_reset)),
+            )
+            .route("/password-strength", web::post().to(password_strength))
             .route("/health", web::get().to(health_check))
             // Protected routes (authentication required)
             .service(
@@ -28,6 +77,19 @@ pub fn configure_auth_routes(cfg: &mut web::ServiceConfig) {
                     .wrap(JwtAuth)
                     .route("/info", web::get().to(get_user_info))
                     .route("/change-password", web::post().to(change_password))
+                    .route("/email-change", web::post().to(request_email_change))
+                    .route(
+                        "/confirm-email-change",
+                        web::post().to(confirm_email_change),
+                    )
+                    .route("/profile", web::patch().to(update_profile))
+                    .route("/username", web::put().to(set_username))
+                    .route("/delete-account", web::post().to(delete_account))
+                    .route("/data-export", web::post().to(request_data_export))
+                    .route("/data-export/{export_id}", web::get().to(get_data_export))
+                    .route("/login-history", web::get().to(get_login_history))
+                    .route("/devices", web::get().to(get_devices))
+                    .route("/devices/{device_id}", web::delete().to(revoke_device))
                     .route("/refresh-token", web::post().to(refresh_token))
                     .route("/logout", web::post().to(logout)),
             ),
@@ -35,13 +97,31 @@ pub fn configure_auth_routes(cfg: &mut web::ServiceConfig) {
 }
 
 pub fn configure_public_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/api/v1").route("/health", web::get().to(health_check)));
+    cfg.service(
+        web::scope("/api/v1")
+            .route("/health", web::get().to(health_check))
+            // Authorized by the signature in the link
+            .route(
+                "/data-exports/{export_id}/download",
+                web::get().to(download_data_export),
+            ),
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::{test, App};
+    use crate::rate_limit::{MemoryStore, RateLimiter};
+    use actix_web::{dev::ServiceResponse, http::StatusCode, test, App};
+    use std::sync::Arc;
+
+    // Status of a response or of the error the service failed with
+    fn status<B>(result: Result<ServiceResponse<B>, actix_web::Error>) -> StatusCode {
+        match result {
+            Ok(resp) => resp.status(),
+            Err(err) => err.error_response().status(),
+        }
+    }
 
     #[actix_web::test]
     async fn test_route_configuration() {
@@ -80,6 +160,12 @@ mod tests {
             .to_request();
         let resp = test::try_call_service(&app, req).await;
         assert!(resp.is_err());
+
+        let req = test::TestRequest::patch()
+            .uri("/api/v1/auth/user/profile")
+            .to_request();
+        let resp = test::try_call_service(&app, req).await;
+        assert!(resp.is_err());
     }
 
     #[actix_web::test]
@@ -99,4 +185,61 @@ mod tests {
             assert_ne!(resp.status(), 401);
         }
     }
+
+    #[actix_web::test]
+    async fn test_verification_emails_are_rate_limited() {
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .configure(configure_auth_routes),
+        )
+        .await;
+
+        // The handler has no services here; only whether the limit let the request through matters
+        let resend = |email: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/auth/resend-verification")
+                .peer_addr("203.0.113.7:5000".parse().unwrap())
+                .set_json(serde_json::json!({ "email": email }))
+                .to_request()
+        };
+        for _ in 0..CONFIG.rate_limit_resend_verification_account.capacity {
+            let result = test::try_call_service(&app, resend("alice@example.com")).await;
+            assert_ne!(status(result), StatusCode::TOO_MANY_REQUESTS);
+        }
+        let result = test::try_call_service(&app, resend("alice@example.com")).await;
+        assert_eq!(status(result), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_login_limit_does_not_lock_out_other_ips() {
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(limiter))
+                .configure(configure_auth_routes),
+        )
+        .await;
+
+        let login = |ip: &str| {
+            test::TestRequest::post()
+                .uri("/api/v1/auth/login")
+                .peer_addr(format!("{ip}:5000").parse().unwrap())
+                .set_json(serde_json::json!({ "email": "alice@example.com", "password": "x" }))
+                .to_request()
+        };
+
+        // The first IP exhausts its attempts for the account
+        for _ in 0..CONFIG.rate_limit_login_ip_account.capacity {
+            let result = test::try_call_service(&app, login("203.0.113.7")).await;
+            assert_ne!(status(result), StatusCode::TOO_MANY_REQUESTS);
+        }
+        let result = test::try_call_service(&app, login("203.0.113.7")).await;
+        assert_eq!(status(result), StatusCode::TOO_MANY_REQUESTS);
+
+        // The owner can still log in from elsewhere
+        let result = test::try_call_service(&app, login("198.51.100.2")).await;
+        assert_ne!(status(result), StatusCode::TOO_MANY_REQUESTS);
+    }
 }
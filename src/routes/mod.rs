@@ -1,3 +1,5 @@
+pub mod admin_routes;
 pub mod auth_routes;
 
+pub use admin_routes::*;
 pub use auth_routes::*;
@@ -0,0 +1,385 @@
+use sqlx::{FromRow, PgConnection, Pool, Postgres};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::ServiceResult,
+    models::{
+        audit::{AuditAction, AuditEvent, AUDIT_PERSONAL_METADATA},
+        outbox::OutboxStatus,
+        request_context::RequestContext,
+        webhook::{WebhookDeliveryStatus, WEBHOOK_PERSONAL_DATA},
+    },
+    services::audit_service::AuditService,
+};
+
+// Accounts erased per transaction
+const PURGE_BATCH_SIZE: i64 = 100;
+
+// Failed logins from IPs that never logged in are forgotten after this many days
+const LOGIN_THROTTLE_RETENTION_DAYS: i32 = 30;
+
+// Placeholder that replaces personal data in rows kept after an account is erased
+const ERASED_PLACEHOLDER: &str = "[erased]";
+
+#[derive(Debug, FromRow)]
+struct DueAccount {
+    id: Uuid,
+    email: String,
+    pending_email: Option<String>,
+}
+
+/// Background task that erases accounts whose deletion grace period has ended, and forgets
+/// stale failed logins and old login history
+pub struct AccountPurgeWorker {
+    db_pool: Pool<Postgres>,
+}
+
+impl AccountPurgeWorker {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Run the purge every `ACCOUNT_PURGE_INTERVAL` seconds for the lifetime of the process
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(CONFIG.account_purge_interval));
+            loop {
+                interval.tick().await;
+
+                loop {
+                    match self.purge_due().await {
+                        Ok(count) if count as i64 >= PURGE_BATCH_SIZE => continue,
+                        Ok(_) => break,
+                        Err(e) => {
+                            log::error!("Account purge failed: {e}");
+                            break;
+                        }
+                    }
+                }
+
+                if let Err(e) = self.prune_login_throttles().await {
+                    log::error!("Pruning login throttles failed: {e}");
+                }
+                if let Err(e) = self.prune_login_history().await {
+                    log::error!("Pruning login history failed: {e}");
+                }
+                if let Err(e) = self.prune_webhook_deliveries().await {
+                    log::error!("Pruning webhook deliveries failed: {e}");
+                }
+                if let Err(e) = self.prune_dead_letters().await {
+                    log::error!("Pruning dead-lettered emails failed: {e}");
+                }
+            }
+        })
+    }
+
+    /// Erase one batch of accounts due for deletion, returning how many were erased
+    pub async fn purge_due(&self) -> ServiceResult<usize> {
+        let mut tx = self.db_pool.begin().await?;
+
+        // Logging in clears deletion_scheduled_at, so only accounts still waiting are erased
+        let accounts = sqlx::query_as::<_, DueAccount>(
+            r#"
+            SELECT id, email, pending_email FROM auth_users
+            WHERE deletion_scheduled_at <= NOW() AND is_active = false
+            ORDER BY deletion_scheduled_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+        )
+        .bind(PURGE_BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for account in &accounts {
+            erase_account(&mut tx, account).await?;
+            log::info!("Erased account {}", account.id);
+        }
+        tx.commit().await?;
+
+        Ok(accounts.len())
+    }
+
+    /// Delete the backoff state of IPs that never logged in and of unknown login identifiers
+    /// once they stopped trying long ago
+    pub async fn prune_login_throttles(&self) -> ServiceResult<u64> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let accounts = sqlx::query(
+            r#"
+            DELETE FROM login_throttles
+            WHERE last_success_at IS NULL AND updated_at < NOW() - make_interval(days => $1)
+            "#,
+        )
+        .bind(LOGIN_THROTTLE_RETENTION_DAYS)
+        .execute(&mut *tx)
+        .await?;
+
+        let unknown = sqlx::query(
+            "DELETE FROM unknown_login_throttles WHERE updated_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(LOGIN_THROTTLE_RETENTION_DAYS)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(accounts.rows_affected() + unknown.rows_affected())
+    }
+
+    /// Delete login events and unused sessions older than `LOGIN_HISTORY_RETENTION_DAYS`, and
+    /// login challenges that can no longer be answered
+    pub async fn prune_login_history(&self) -> ServiceResult<u64> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let events = sqlx::query(
+            "DELETE FROM login_events WHERE created_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(CONFIG.login_history_retention_days)
+        .execute(&mut *tx)
+        .await?;
+
+        // Tokens of sessions unused for this long have long expired
+        let sessions = sqlx::query(
+            "DELETE FROM user_sessions WHERE last_seen_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(CONFIG.login_history_retention_days)
+        .execute(&mut *tx)
+        .await?;
+
+        let challenges = sqlx::query("DELETE FROM login_challenges WHERE expires_at < NOW()")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(events.rows_affected() + sessions.rows_affected() + challenges.rows_affected())
+    }
+
+    /// Delete finished webhook deliveries and their attempts older than
+    /// `WEBHOOK_DELIVERY_RETENTION_DAYS`
+    pub async fn prune_webhook_deliveries(&self) -> ServiceResult<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM webhook_deliveries
+            WHERE status <> $1 AND updated_at < NOW() - make_interval(days => $2)
+            "#,
+        )
+        .bind(WebhookDeliveryStatus::Pending.as_str())
+        .bind(CONFIG.webhook_delivery_retention_days)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete dead-lettered emails, whose bodies may hold tokens, older than
+    /// `OUTBOX_DEAD_LETTER_RETENTION_DAYS`
+    pub async fn prune_dead_letters(&self) -> ServiceResult<u64> {
+        let result = sqlx::query(
+            "DELETE FROM outbox WHERE status = $1 AND updated_at < NOW() - make_interval(days => $2)",
+        )
+        .bind(OutboxStatus::Dead.as_str())
+        .bind(CONFIG.outbox_dead_letter_retention_days)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Delete the user (password history cascades) and strip personal data from related rows
+async fn erase_account(conn: &mut PgConnection, account: &DueAccount) -> ServiceResult<()> {
+    let addresses: Vec<String> = std::iter::once(account.email.to_lowercase())
+        .chain(account.pending_email.as_deref().map(str::to_lowercase))
+        .collect();
+
+    // Undelivered emails would otherwise still go out to the erased address
+    sqlx::query("DELETE FROM outbox WHERE status = $1 AND LOWER(recipient) = ANY($2)")
+        .bind(OutboxStatus::Pending.as_str())
+        .bind(&addresses)
+        .execute(&mut *conn)
+        .await?;
+
+    // Keep the delivery log, without the address or message contents
+    sqlx::query(
+        r#"
+        UPDATE outbox
+        SET recipient = $1, payload = NULL, last_error = NULL, updated_at = NOW()
+        WHERE LOWER(recipient) = ANY($2)
+        "#,
+    )
+    .bind(ERASED_PLACEHOLDER)
+    .bind(&addresses)
+    .execute(&mut *conn)
+    .await?;
+
+    // Subscribers are not told about the account anymore, and logged payloads lose its
+    // address and username
+    sqlx::query(
+        "DELETE FROM webhook_deliveries WHERE status = $1 AND payload->'data'->>'user_id' = $2",
+    )
+    .bind(WebhookDeliveryStatus::Pending.as_str())
+    .bind(account.id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE webhook_deliveries
+        SET payload = jsonb_set(payload, '{data}', (payload->'data') - $2::TEXT[]),
+            updated_at = NOW()
+        WHERE payload->'data'->>'user_id' = $1
+        "#,
+    )
+    .bind(account.id.to_string())
+    .bind(&WEBHOOK_PERSONAL_DATA[..])
+    .execute(&mut *conn)
+    .await?;
+
+    // Audit entries stay, without the client details and addresses they recorded
+    sqlx::query(
+        r#"
+        UPDATE audit_events
+        SET ip_address = NULL, user_agent = NULL, metadata = metadata - $2::TEXT[]
+        WHERE actor_id = $1 OR target_id = $1
+        "#,
+    )
+    .bind(account.id)
+    .bind(&AUDIT_PERSONAL_METADATA[..])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM auth_users WHERE id = $1")
+        .bind(account.id)
+        .execute(&mut *conn)
+        .await?;
+
+    let event =
+        AuditEvent::new(AuditAction::AccountErased, &RequestContext::default()).target(account.id);
+    AuditService::record(conn, &event).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::EmailMessage,
+        test_support::{test_pool, unique_email},
+    };
+    use serde_json::json;
+    use sqlx::types::Json;
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_old_dead_letters_are_pruned() {
+        let pool = test_pool().await;
+
+        let mut ids = Vec::new();
+        for age_days in [CONFIG.outbox_dead_letter_retention_days + 1, 0] {
+            let message = EmailMessage {
+                template: "password_reset".to_string(),
+                to: unique_email("dead-letter"),
+                subject: "Reset".to_string(),
+                text_body: "token".to_string(),
+                html_body: None,
+            };
+            let id: Uuid = sqlx::query_scalar(
+                r#"
+                INSERT INTO outbox (id, kind, recipient, payload, status, attempts, updated_at)
+                VALUES ($1, 'password_reset', $2, $3, $4, 8, NOW() - make_interval(days => $5))
+                RETURNING id
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(&message.to)
+            .bind(Json(&message))
+            .bind(OutboxStatus::Dead.as_str())
+            .bind(age_days)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+
+        AccountPurgeWorker::new(pool.clone())
+            .prune_dead_letters()
+            .await
+            .unwrap();
+
+        let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM outbox WHERE id = ANY($1)")
+            .bind(&ids)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![ids[1]]);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_erasure_strips_webhook_payloads() {
+        let pool = test_pool().await;
+        let account = DueAccount {
+            id: Uuid::new_v4(),
+            email: unique_email("erased"),
+            pending_email: None,
+        };
+        sqlx::query("INSERT INTO auth_users (id, email, password_hash) VALUES ($1, $2, 'hash')")
+            .bind(account.id)
+            .bind(&account.email)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let subscription_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO webhook_subscriptions (url, events, secret)
+            VALUES ('https://hooks.example.com/erasure', '{user.registered}', 'secret')
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let payload = json!({
+            "type": "user.registered",
+            "data": { "user_id": account.id, "email": account.email, "username": "erased_user" },
+        });
+        let delivery_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO webhook_deliveries (subscription_id, event_id, event_type, payload, status)
+            VALUES ($1, $2, 'user.registered', $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(Uuid::new_v4())
+        .bind(&payload)
+        .bind(WebhookDeliveryStatus::Delivered.as_str())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        erase_account(&mut conn, &account).await.unwrap();
+
+        let erased: serde_json::Value =
+            sqlx::query_scalar("SELECT payload FROM webhook_deliveries WHERE id = $1")
+                .bind(delivery_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            erased,
+            json!({ "type": "user.registered", "data": { "user_id": account.id } })
+        );
+
+        sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1")
+            .bind(subscription_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    errors::{ServiceError, ServiceResult},
+    models::{
+        admin::{
+            like_prefix, AdminUserInfo, UpdatePasswordPolicyRequest, UserListQuery,
+            UserListResponse,
+        },
+        auth_user::{AuthUser, MessageResponse, AUTH_USER_COLUMNS},
+        pagination::PageCursor,
+        request_context::RequestContext,
+    },
+    services::{notification_service::NotificationService, session_service::SessionService},
+};
+
+#[derive(Clone)]
+pub struct AdminService {
+    db_pool: Pool<Postgres>,
+    notifications: NotificationService,
+}
+
+impl AdminService {
+    pub fn new(db_pool: Pool<Postgres>, notifications: NotificationService) -> Self {
+        Self {
+            db_pool,
+            notifications,
+        }
+    }
+
+    /// List users, newest first, one page at a time
+    pub async fn list_users(&self, query: UserListQuery) -> ServiceResult<UserListResponse> {
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(PageCursor::decode)
+            .transpose()?;
+        let limit = query.limit.unwrap_or(50);
+
+        // Fetch one extra row to know whether there is a next page
+        let mut users = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            SELECT {AUTH_USER_COLUMNS} FROM auth_users
+            WHERE ($1::BOOLEAN IS NULL OR is_verified = $1)
+              AND ($2::BOOLEAN IS NULL OR is_active = $2)
+              AND ($3::BOOLEAN IS NULL OR COALESCE(locked_until > NOW(), false) = $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+              AND ($6::TEXT IS NULL OR LOWER(email) LIKE $6)
+              AND ($7::TIMESTAMPTZ IS NULL OR (created_at, id) < ($7, $8))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $9
+            "#
+        ))
+        .bind(query.verified)
+        .bind(query.active)
+        .bind(query.locked)
+        .bind(query.created_after)
+        .bind(query.created_before)
+        .bind(
+            query
+                .email_prefix
+                .map(|prefix| like_prefix(&prefix.trim().to_lowercase())),
+        )
+        .bind(cursor.as_ref().map(|cursor| cursor.created_at))
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(limit + 1)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let next_cursor = if users.len() as i64 > limit {
+            users.truncate(limit as usize);
+            users.last().map(|user| {
+                PageCursor {
+                    created_at: user.created_at,
+                    id: user.id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(UserListResponse {
+            users: users.into_iter().map(AdminUserInfo::from).collect(),
+            next_cursor,
+        })
+    }
+
+    pub async fn get_user(&self, user_id: Uuid) -> ServiceResult<AdminUserInfo> {
+        let user = self.get_user_by_id(user_id).await?;
+        Ok(AdminUserInfo::from(user))
+    }
+
+    /// Activate or deactivate an account; activating cancels a pending self-deletion, while
+    /// deactivating leaves it scheduled
+    pub async fn set_active(
+        &self,
+        admin_id: Uuid,
+        user_id: Uuid,
+        is_active: bool,
+    ) -> ServiceResult<AdminUserInfo> {
+        if !is_active && admin_id == user_id {
+            return Err(ServiceError::BadRequest(
+                "Admins cannot deactivate their own account".to_string(),
+            ));
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            UPDATE auth_users
+            SET is_active = $1,
+                deletion_scheduled_at = CASE WHEN $1 THEN NULL ELSE deletion_scheduled_at END,
+                updated_at = NOW()
+            WHERE id = $2
+            RETURNING {AUTH_USER_COLUMNS}
+            "#
+        ))
+        .bind(is_active)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        // Log the user out everywhere, so issued tokens stop working at once
+        if !is_active {
+            SessionService::revoke_all_sessions(&mut tx, user_id).await?;
+        }
+        tx.commit().await?;
+
+        Ok(AdminUserInfo::from(user))
+    }
+
+    /// Mark the email as verified without the user following the verification link
+    pub async fn force_verify(&self, user_id: Uuid) -> ServiceResult<AdminUserInfo> {
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            UPDATE auth_users
+            SET is_verified = true, verification_token = NULL,
+                verification_token_expires = NULL, updated_at = NOW()
+            WHERE id = $1
+            RETURNING {AUTH_USER_COLUMNS}
+            "#
+        ))
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(AdminUserInfo::from(user))
+    }
+
+    /// Lift the login backoff caused by failed login attempts, from any IP
+    pub async fn unlock(&self, user_id: Uuid) -> ServiceResult<AdminUserInfo> {
+        let mut tx = self.db_pool.begin().await?;
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            UPDATE auth_users
+            SET failed_login_attempts = 0, locked_until = NULL, updated_at = NOW()
+            WHERE id = $1
+            RETURNING {AUTH_USER_COLUMNS}
+            "#
+        ))
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE login_throttles
+            SET failed_attempts = 0, blocked_until = NULL, updated_at = NOW()
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(AdminUserInfo::from(user))
+    }
+
+    /// Email the user a password reset link, as if they had requested it
+    pub async fn trigger_password_reset(&self, user_id: Uuid) -> ServiceResult<MessageResponse> {
+        let mut user = self.get_user_by_id(user_id).await?;
+        let reset_token = user.generate_reset_token();
+
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE auth_users
+            SET reset_token = $1, reset_token_expires = $2, updated_at = $3
+            WHERE id = $4
+            "#,
+        )
+        .bind(&user.reset_token)
+        .bind(user.reset_token_expires)
+        .bind(user.updated_at)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+        // The admin's request details (language, IP) must not leak into the user's email
+        self.notifications
+            .queue_password_reset_email(&mut tx, &user, &reset_token, &RequestContext::default())
+            .await?;
+        tx.commit().await?;
+
+        Ok(MessageResponse::new(
+            "A password reset link has been sent to the user.",
+        ))
+    }
+
+    /// Flag a user as having to change their password, or opt them in to password expiry
+    pub async fn update_password_policy(
+        &self,
+        user_id: Uuid,
+        request: UpdatePasswordPolicyRequest,
+    ) -> ServiceResult<AdminUserInfo> {
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            UPDATE auth_users
+            SET must_change_password = COALESCE($1, must_change_password),
+                password_expires = COALESCE($2, password_expires),
+                updated_at = NOW()
+            WHERE id = $3
+            RETURNING {AUTH_USER_COLUMNS}
+            "#
+        ))
+        .bind(request.must_change_password)
+        .bind(request.password_expires)
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        log::info!(
+            "Password policy updated for {}: must_change_password={}, password_expires={}",
+            user.email,
+            user.must_change_password,
+            user.password_expires
+        );
+
+        Ok(AdminUserInfo::from(user))
+    }
+
+    async fn get_user_by_id(&self, user_id: Uuid) -> ServiceResult<AuthUser> {
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE id = $1"
+        ))
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::templates::EmailTemplates,
+        models::session::UserSession,
+        test_support::{test_pool, unique_email},
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_deactivation_revokes_sessions() {
+        let pool = test_pool().await;
+        let admin = AdminService::new(
+            pool.clone(),
+            NotificationService::new(Arc::new(EmailTemplates::builtin())),
+        );
+        let sessions = SessionService::new(pool.clone());
+
+        let user = AuthUser::new(unique_email("deactivated"), "hash".to_string());
+        sqlx::query("INSERT INTO auth_users (id, email, password_hash) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(&user.email)
+            .bind(&user.password_hash)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let session = UserSession::new(user.id, &RequestContext::default());
+        let mut conn = pool.acquire().await.unwrap();
+        SessionService::create_session(&mut conn, &session)
+            .await
+            .unwrap();
+        assert!(sessions.is_active(user.id, session.id).await.unwrap());
+
+        let deactivated = admin
+            .set_active(Uuid::new_v4(), user.id, false)
+            .await
+            .unwrap();
+        assert!(!deactivated.is_active);
+        assert!(!sessions.is_active(user.id, session.id).await.unwrap());
+
+        // Reactivating doesn't bring the sessions back
+        admin
+            .set_active(Uuid::new_v4(), user.id, true)
+            .await
+            .unwrap();
+        assert!(!sessions.is_active(user.id, session.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_deactivation_keeps_scheduled_deletion() {
+        let pool = test_pool().await;
+        let admin = AdminService::new(
+            pool.clone(),
+            NotificationService::new(Arc::new(EmailTemplates::builtin())),
+        );
+
+        let mut user = AuthUser::new(unique_email("leaving"), "hash".to_string());
+        user.schedule_deletion(30);
+        sqlx::query(
+            r#"
+            INSERT INTO auth_users (id, email, password_hash, is_active, deletion_scheduled_at)
+            VALUES ($1, $2, $3, false, $4)
+            "#,
+        )
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(&user.password_hash)
+        .bind(user.deletion_scheduled_at)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let deactivated = admin
+            .set_active(Uuid::new_v4(), user.id, false)
+            .await
+            .unwrap();
+        assert!(deactivated.deletion_scheduled_at.is_some());
+
+        let reactivated = admin
+            .set_active(Uuid::new_v4(), user.id, true)
+            .await
+            .unwrap();
+        assert!(reactivated.is_active);
+        assert!(reactivated.deletion_scheduled_at.is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    models::audit::AuditCheckpoint,
+    services::audit_service::AuditService,
+};
+
+/// Background task that appends a signed checkpoint of the audit chain to
+/// `AUDIT_CHECKPOINT_FILE` whenever the chain has grown
+pub struct AuditCheckpointWorker {
+    audit: AuditService,
+    path: PathBuf,
+}
+
+impl AuditCheckpointWorker {
+    pub fn new(audit: AuditService, path: impl Into<PathBuf>) -> Self {
+        Self {
+            audit,
+            path: path.into(),
+        }
+    }
+
+    /// Run every `AUDIT_CHECKPOINT_INTERVAL` seconds for the lifetime of the process
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(CONFIG.audit_checkpoint_interval));
+            loop {
+                interval.tick().await;
+
+                match self.export().await {
+                    Ok(Some(checkpoint)) => log::info!(
+                        "Exported audit checkpoint at sequence {}",
+                        checkpoint.sequence
+                    ),
+                    Ok(None) => {}
+                    Err(e) => log::error!("Exporting an audit checkpoint failed: {e}"),
+                }
+            }
+        })
+    }
+
+    /// Append a checkpoint of the chain head unless the last one in the file already has it
+    pub async fn export(&self) -> ServiceResult<Option<AuditCheckpoint>> {
+        let Some(head) = self.audit.head().await? else {
+            return Ok(None);
+        };
+
+        let checkpoints = read_checkpoints(&self.path).map_err(checkpoint_file_error)?;
+        if checkpoints
+            .last()
+            .is_some_and(|last| last.sequence >= head.sequence)
+        {
+            return Ok(None);
+        }
+
+        let checkpoint = AuditCheckpoint::new(head, CONFIG.audit_hmac_key.as_bytes());
+        append_checkpoint(&self.path, &checkpoint).map_err(checkpoint_file_error)?;
+        Ok(Some(checkpoint))
+    }
+}
+
+/// Checkpoints from `AUDIT_CHECKPOINT_FILE`, if one is configured
+pub fn configured_checkpoints() -> ServiceResult<Vec<AuditCheckpoint>> {
+    match &CONFIG.audit_checkpoint_file {
+        Some(path) => read_checkpoints(Path::new(path)).map_err(checkpoint_file_error),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Checkpoints in a file of one JSON object per line; a missing file has none
+pub fn read_checkpoints(path: &Path) -> io::Result<Vec<AuditCheckpoint>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {e}", index + 1),
+                )
+            })
+        })
+        .collect()
+}
+
+fn append_checkpoint(path: &Path, checkpoint: &AuditCheckpoint) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(checkpoint)?)?;
+    file.sync_data()
+}
+
+fn checkpoint_file_error(e: io::Error) -> ServiceError {
+    log::error!("Audit checkpoint file error: {e}");
+    ServiceError::InternalError
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audit::AuditChainHead;
+
+    #[test]
+    fn test_checkpoint_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()));
+        assert!(read_checkpoints(&path).unwrap().is_empty());
+
+        let key = b"this_is_a_very_long_secret_key_for_audit_chain";
+        for sequence in [10, 20] {
+            let head = AuditChainHead {
+                sequence,
+                hash: format!("hash-{sequence}"),
+            };
+            append_checkpoint(&path, &AuditCheckpoint::new(head, key)).unwrap();
+        }
+
+        let checkpoints = read_checkpoints(&path).unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[1].sequence, 20);
+        assert!(checkpoints.iter().all(|cp| cp.signature_valid(key)));
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(read_checkpoints(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use sqlx::{PgConnection, Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::ServiceResult,
+    models::{
+        audit::{
+            AuditAction, AuditChainHead, AuditChainReport, AuditCheckpoint, AuditEvent,
+            AuditEventListResponse, AuditEventQuery, BrokenLink, ChainBreak, AUDIT_EVENT_COLUMNS,
+        },
+        pagination::PageCursor,
+    },
+};
+
+// Advisory lock serializing appends to the chain ("audit" in ASCII)
+const AUDIT_CHAIN_LOCK: i64 = 0x61_7564_6974;
+
+// Entries loaded at a time while verifying the chain
+const VERIFY_BATCH_SIZE: i64 = 1000;
+
+/// Append-only audit log of security-relevant actions
+#[derive(Clone)]
+pub struct AuditService {
+    db_pool: Pool<Postgres>,
+}
+
+impl AuditService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Append an event to the hash chain; pass the transaction of the action it records.
+    /// Appends are serialized until that transaction ends.
+    pub async fn record(conn: &mut PgConnection, event: &AuditEvent) -> ServiceResult<()> {
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(AUDIT_CHAIN_LOCK)
+            .execute(&mut *conn)
+            .await?;
+        let last = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT sequence, hash FROM audit_events ORDER BY sequence DESC LIMIT 1",
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        // Entries from before the chain have no hash; the first chained one follows ""
+        let (sequence, prev_hash) = match last {
+            Some((sequence, hash)) => (sequence + 1, hash.unwrap_or_default()),
+            None => (1, String::new()),
+        };
+        let mut event = event.clone();
+        event.chain(sequence, prev_hash, CONFIG.audit_hmac_key.as_bytes());
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events
+                (id, action, actor_id, target_id, ip_address, user_agent, request_id, metadata,
+                 created_at, sequence, personal_hmac, prev_hash, hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            "#,
+        )
+        .bind(event.id)
+        .bind(&event.action)
+        .bind(event.actor_id)
+        .bind(event.target_id)
+        .bind(&event.ip_address)
+        .bind(&event.user_agent)
+        .bind(&event.request_id)
+        .bind(&event.metadata)
+        .bind(event.created_at)
+        .bind(event.sequence)
+        .bind(&event.personal_hmac)
+        .bind(&event.prev_hash)
+        .bind(&event.hash)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Latest chained entry, None while the chain is empty
+    pub async fn head(&self) -> ServiceResult<Option<AuditChainHead>> {
+        let head = sqlx::query_as::<_, AuditChainHead>(
+            r#"
+            SELECT sequence, hash FROM audit_events
+            WHERE hash IS NOT NULL
+            ORDER BY sequence DESC
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        Ok(head)
+    }
+
+    /// Walk the chain from the first entry, stopping at the first broken link. Exported
+    /// checkpoints must be signed with the key and match the chain at their sequence.
+    pub async fn verify(&self, checkpoints: &[AuditCheckpoint]) -> ServiceResult<AuditChainReport> {
+        let key = CONFIG.audit_hmac_key.as_bytes();
+        let mut report = AuditChainReport::default();
+        let broken = |mut report: AuditChainReport, sequence, id, reason| {
+            report.broken_link = Some(BrokenLink {
+                sequence,
+                id,
+                reason,
+            });
+            Ok(report)
+        };
+
+        if let Some(forged) = checkpoints.iter().find(|cp| !cp.signature_valid(key)) {
+            return broken(
+                report,
+                forged.sequence,
+                None,
+                ChainBreak::CheckpointSignature,
+            );
+        }
+        let checkpoint_hashes: HashMap<i64, &str> = checkpoints
+            .iter()
+            .map(|cp| (cp.sequence, cp.hash.as_str()))
+            .collect();
+
+        // Personal data of these accounts was erased, so it can't match its HMAC
+        let erased: HashSet<Uuid> = sqlx::query_scalar(
+            "SELECT target_id FROM audit_events WHERE action = $1 AND target_id IS NOT NULL",
+        )
+        .bind(AuditAction::AccountErased.as_str())
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let mut last_sequence = 0;
+        let mut prev_hash: Option<String> = None; // None until the chain starts
+        loop {
+            let events = sqlx::query_as::<_, AuditEvent>(&format!(
+                r#"
+                SELECT {AUDIT_EVENT_COLUMNS} FROM audit_events
+                WHERE sequence > $1
+                ORDER BY sequence
+                LIMIT $2
+                "#
+            ))
+            .bind(last_sequence)
+            .bind(VERIFY_BATCH_SIZE)
+            .fetch_all(&self.db_pool)
+            .await?;
+            if events.is_empty() {
+                break;
+            }
+
+            for event in events {
+                let sequence = last_sequence + 1;
+                if prev_hash.is_none() && event.hash.is_none() && event.sequence == sequence {
+                    report.unchained += 1;
+                    last_sequence = sequence;
+                    continue;
+                }
+
+                let is_erased = [event.actor_id, event.target_id]
+                    .iter()
+                    .flatten()
+                    .any(|id| erased.contains(id));
+                let link = event.check_link(
+                    sequence,
+                    prev_hash.as_deref().unwrap_or_default(),
+                    key,
+                    is_erased,
+                );
+                if let Err(reason) = link {
+                    return broken(report, sequence, Some(event.id), reason);
+                }
+                if let Some(hash) = checkpoint_hashes.get(&sequence) {
+                    if Some(*hash) != event.hash.as_deref() {
+                        return broken(
+                            report,
+                            sequence,
+                            Some(event.id),
+                            ChainBreak::CheckpointMismatch,
+                        );
+                    }
+                    report.checkpoints += 1;
+                }
+
+                report.entries += 1;
+                last_sequence = sequence;
+                prev_hash = event.hash;
+            }
+        }
+
+        // Entries at the end can be removed without breaking a link; checkpoints tell
+        if let Some(checkpoint) = checkpoints.iter().find(|cp| cp.sequence > last_sequence) {
+            return broken(
+                report,
+                checkpoint.sequence,
+                None,
+                ChainBreak::MissingEntries,
+            );
+        }
+
+        report.valid = true;
+        report.head = prev_hash.map(|hash| AuditChainHead {
+            sequence: last_sequence,
+            hash,
+        });
+        Ok(report)
+    }
+
+    /// Events matching the filters, most recent first (admin)
+    pub async fn list(&self, query: AuditEventQuery) -> ServiceResult<AuditEventListResponse> {
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(PageCursor::decode)
+            .transpose()?;
+        let limit = query.limit.unwrap_or(50);
+
+        // Fetch one extra row to know whether there is a next page
+        let mut events = sqlx::query_as::<_, AuditEvent>(&format!(
+            r#"
+            SELECT {AUDIT_EVENT_COLUMNS} FROM audit_events
+            WHERE ($1::UUID IS NULL OR actor_id = $1 OR target_id = $1)
+              AND ($2::TEXT IS NULL OR action = $2)
+              AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR (created_at, id) < ($5, $6))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            "#
+        ))
+        .bind(query.user_id)
+        .bind(&query.action)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(cursor.as_ref().map(|cursor| cursor.created_at))
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(limit + 1)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let next_cursor = if events.len() as i64 > limit {
+            events.truncate(limit as usize);
+            events.last().map(|event| {
+                PageCursor {
+                    created_at: event.created_at,
+                    id: event.id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(AuditEventListResponse {
+            events,
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{audit::AuditAction, request_context::RequestContext},
+        test_support::test_pool,
+    };
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_audit_events_are_append_only() {
+        let pool = test_pool().await;
+        let audit = AuditService::new(pool.clone());
+
+        let user_id = Uuid::new_v4();
+        let event = AuditEvent::by_user(
+            AuditAction::PasswordChanged,
+            user_id,
+            &RequestContext::default(),
+        );
+        let mut conn = pool.acquire().await.unwrap();
+        AuditService::record(&mut conn, &event).await.unwrap();
+
+        let page = audit
+            .list(AuditEventQuery {
+                user_id: Some(user_id),
+                action: Some("password.changed".to_string()),
+                from: None,
+                to: None,
+                limit: None,
+                cursor: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.events.len(), 1);
+
+        // Rejected by the trigger, not just by missing privileges
+        for statement in [
+            "DELETE FROM audit_events WHERE id = $1",
+            "UPDATE audit_events SET action = 'logout' WHERE id = $1",
+        ] {
+            let error = sqlx::query(statement)
+                .bind(event.id)
+                .execute(&pool)
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains("audit_events is append-only"));
+        }
+    }
+}
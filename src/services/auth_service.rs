@@ -1,49 +1,120 @@
-use bcrypt::{hash, verify};
-use sqlx::{Pool, Postgres, Row};
+use serde_json::json;
+use sqlx::{PgConnection, Pool, Postgres};
+use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::{
     config::CONFIG,
     errors::{ServiceError, ServiceResult},
-    middleware::auth::generate_jwt_token,
-    models::auth_user::{
-        AuthResponse, AuthUser, ChangePasswordRequest, ConfirmResetPasswordRequest, LoginRequest,
-        MessageResponse, RegisterRequest, ResetPasswordRequest, UserInfo, VerifyEmailRequest,
+    geoip::GeoIp,
+    middleware::auth::{generate_jwt_token, generate_password_change_token},
+    models::{
+        audit::{AuditAction, AuditEvent},
+        auth_user::{
+            AccountDeletionResponse, AuthResponse, AuthUser, ChangePasswordRequest,
+            ConfirmEmailChangeRequest, ConfirmResetPasswordRequest, DeleteAccountRequest,
+            LoginIdentifier, LoginRequest, MessageResponse, RegisterRequest,
+            RequestEmailChangeRequest, ResendVerificationRequest, ResetPasswordRequest,
+            SetUsernameRequest, UpdateProfileRequest, UserInfo, VerifyEmailRequest,
+            AUTH_USER_COLUMNS,
+        },
+        email::email_identity_key,
+        login_event::{LoginEvent, LoginFailure, LoginMethod},
+        login_risk::{
+            LoginAssessment, LoginChallenge, LoginChallengeResponse, LoginOutcome,
+            VerifyLoginRequest, LOGIN_CHALLENGE_COLUMNS,
+        },
+        login_throttle::{
+            retry_after, unknown_identifier_hash, LoginBackoff, LoginThrottle, UnknownLoginThrottle,
+        },
+        request_context::RequestContext,
+        session::{device_key, UserSession},
+        token::hash_token,
+        webhook::WebhookEventType,
+    },
+    services::{
+        audit_service::AuditService,
+        notification_service::NotificationService,
+        password_hasher::{
+            hash_password, is_legacy_hash, needs_rehash, verify_dummy_password, verify_password,
+        },
+        session_service::SessionService,
+        webhook_service::WebhookService,
     },
 };
 
+// Email about a completed login
+enum LoginNotice<'a> {
+    Regular,                     // sent if LOGIN_NOTIFICATIONS is enabled
+    Suspicious(Option<&'a str>), // always sent, with the login's location
+    None,
+}
+
+// Response to registrations, also to those for taken emails if CONCEAL_REGISTERED_EMAILS is set
+const REGISTERED_MESSAGE: &str =
+    "User registered successfully. Please check your email for verification.";
+
 #[derive(Clone)]
 pub struct AuthService {
     db_pool: Pool<Postgres>,
+    notifications: NotificationService,
+    geoip: Option<Arc<GeoIp>>,
 }
 
 impl AuthService {
-    pub fn new(db_pool: Pool<Postgres>) -> Self {
-        Self { db_pool }
+    pub fn new(
+        db_pool: Pool<Postgres>,
+        notifications: NotificationService,
+        geoip: Option<Arc<GeoIp>>,
+    ) -> Self {
+        Self {
+            db_pool,
+            notifications,
+            geoip,
+        }
     }
 
     /// Register a new user
-    pub async fn register(&self, request: RegisterRequest) -> ServiceResult<MessageResponse> {
+    pub async fn register(
+        &self,
+        request: RegisterRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<MessageResponse> {
         // Check if user already exists
-        if self.get_user_by_email(&request.email).await.is_ok() {
-            return Err(ServiceError::UserAlreadyExists);
+        match self.get_user_by_email(&request.email).await {
+            Ok(existing) if CONFIG.conceal_registered_emails => {
+                return self.registration_attempt(existing, &request, ctx).await
+            }
+            Ok(_) => return Err(ServiceError::UserAlreadyExists),
+            Err(ServiceError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        if let Some(username) = &request.username {
+            if self.get_user_by_username(username).await.is_ok() {
+                return Err(ServiceError::UsernameTaken);
+            }
         }
 
         // Hash the password
-        let password_hash = hash(&request.password, CONFIG.bcrypt_cost)?;
+        let password_hash = hash_password(&request.password)?;
 
         // Create new user
-        let user = AuthUser::new(request.email, password_hash);
-
-        // Insert user into database
+        let mut user = AuthUser::new(request.email, password_hash);
+        user.username = request.username;
+        user.locale = ctx.preferred_locales().into_iter().next();
+        let verification_token =
+            user.generate_verification_token(CONFIG.verification_token_expiration);
+
+        // Insert user and queue the verification email in one transaction
+        let mut tx = self.db_pool.begin().await?;
         sqlx::query(
             r#"
             INSERT INTO auth_users (
                 id, email, password_hash, is_active, is_verified,
                 created_at, updated_at, failed_login_attempts,
-                verification_token
+                verification_token, verification_token_expires, locale, username
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
             "#,
         )
         .bind(user.id)
@@ -55,67 +126,333 @@ impl AuthService {
         .bind(user.updated_at)
         .bind(user.failed_login_attempts)
         .bind(&user.verification_token)
-        .execute(&self.db_pool)
+        .bind(user.verification_token_expires)
+        .bind(&user.locale)
+        .bind(&user.username)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(MessageResponse::new(
-            "User registered successfully. Please check your email for verification.",
-        ))
+        let event = AuditEvent::by_user(AuditAction::UserRegistered, user.id, ctx);
+        AuditService::record(&mut tx, &event).await?;
+        WebhookService::enqueue(
+            &mut tx,
+            WebhookEventType::UserRegistered,
+            json!({ "user_id": user.id, "email": user.email, "username": user.username }),
+        )
+        .await?;
+        self.notifications
+            .queue_verification_email(&mut tx, &user, &verification_token, ctx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(MessageResponse::new(REGISTERED_MESSAGE))
     }
 
-    /// Login a user
-    pub async fn login(&self, request: LoginRequest) -> ServiceResult<AuthResponse> {
-        let mut user = self.get_user_by_email(&request.email).await?;
+    // Answer a registration for a taken email like a successful one and tell the owner,
+    // so that the response doesn't reveal which emails have accounts
+    async fn registration_attempt(
+        &self,
+        existing: AuthUser,
+        request: &RegisterRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<MessageResponse> {
+        // Hash the password anyway so this takes as long as a registration
+        hash_password(&request.password)?;
 
-        // Check if user can login (not locked, active)
-        if !user.can_login() {
-            return Err(ServiceError::Unauthorized);
+        let mut tx = self.db_pool.begin().await?;
+        self.notifications
+            .queue_registration_attempt_email(&mut tx, &existing, ctx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(MessageResponse::new(REGISTERED_MESSAGE))
+    }
+
+    /// Login a user; suspicious logins may have to be completed with `verify_login`
+    pub async fn login(
+        &self,
+        request: LoginRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<LoginOutcome> {
+        let Some(identifier) = request.identifier() else {
+            return Err(ServiceError::BadRequest(
+                "Provide either an email or a username".to_string(),
+            ));
+        };
+        let method = LoginMethod::from(&identifier);
+        let lookup = match identifier {
+            LoginIdentifier::Email(email) => self.get_user_by_email(email).await,
+            LoginIdentifier::Username(username) => self.get_user_by_username(username).await,
+        };
+        let mut user = match lookup {
+            Ok(user) => user,
+            Err(ServiceError::NotFound) => {
+                return self
+                    .reject_unknown_login(&identifier, method, &request.password, ctx)
+                    .await
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Back off after failed logins from this IP, and after failed logins from anywhere
+        // unless this IP has logged in before
+        let mut throttle = match &ctx.ip_address {
+            Some(ip) => Some(self.get_login_throttle(user.id, ip).await?),
+            None => None,
+        };
+        let known_ip = throttle.as_ref().is_some_and(LoginThrottle::is_known);
+        let blocked = throttle
+            .as_ref()
+            .and_then(|throttle| retry_after(throttle.blocked_until))
+            .or_else(|| {
+                if known_ip {
+                    None
+                } else {
+                    user.login_retry_after()
+                }
+            });
+        if let Some(seconds) = blocked {
+            let event = LoginEvent::failed(user.id, method, LoginFailure::Throttled, ctx);
+            let mut tx = self.db_pool.begin().await?;
+            Self::record_login(&mut tx, &event, ctx).await?;
+            tx.commit().await?;
+            return Err(ServiceError::RateLimited(seconds));
         }
 
         // Verify password
-        if !verify(&request.password, &user.password_hash)? {
-            // Increment failed attempts
-            user.increment_failed_attempts();
-            self.update_user_login_attempts(&user).await?;
+        if !verify_password(&request.password, &user.password_hash)? {
+            user.increment_failed_attempts(&LoginBackoff::for_account());
+            let mut tx = self.db_pool.begin().await?;
+            self.update_user_login_attempts(&mut tx, &user).await?;
+            if let Some(throttle) = &mut throttle {
+                throttle.record_failure(&LoginBackoff::for_ip());
+                self.save_login_throttle(&mut tx, throttle).await?;
+            }
+            let event = LoginEvent::failed(user.id, method, LoginFailure::InvalidCredentials, ctx);
+            Self::record_login(&mut tx, &event, ctx).await?;
+            // Audit the backoff blocks this failure started
+            let blocks = [
+                ("account", user.login_retry_after()),
+                (
+                    "ip",
+                    throttle
+                        .as_ref()
+                        .and_then(|throttle| retry_after(throttle.blocked_until)),
+                ),
+            ];
+            for (scope, seconds) in blocks {
+                if let Some(seconds) = seconds {
+                    let event = AuditEvent::new(AuditAction::LoginLocked, ctx)
+                        .target(user.id)
+                        .metadata(json!({ "scope": scope, "retry_after": seconds }));
+                    AuditService::record(&mut tx, &event).await?;
+                    // Blocks of one IP don't lock the account for everyone else
+                    if scope == "account" {
+                        WebhookService::enqueue(
+                            &mut tx,
+                            WebhookEventType::UserLocked,
+                            json!({ "user_id": user.id, "retry_after": seconds }),
+                        )
+                        .await?;
+                    }
+                }
+            }
+            tx.commit().await?;
             return Err(ServiceError::InvalidCredentials);
         }
 
-        // Reset failed attempts and update last login
-        user.reset_failed_attempts();
-        self.update_user_successful_login(&user).await?;
+        // Inactive accounts can't log in, except those awaiting deletion, to cancel it. Checked
+        // after the password, so deactivated accounts look like any other to strangers.
+        if !user.is_active && !user.is_deletion_pending() {
+            let event = LoginEvent::failed(user.id, method, LoginFailure::Inactive, ctx);
+            let mut tx = self.db_pool.begin().await?;
+            Self::record_login(&mut tx, &event, ctx).await?;
+            tx.commit().await?;
+            return Err(ServiceError::Unauthorized);
+        }
+
+        // Upgrade hashes created with an older pepper (or none), or in a legacy format, now that
+        // we know the password
+        if needs_rehash(&user.password_hash) {
+            if is_legacy_hash(&user.password_hash) {
+                log::info!("Migrating the legacy password hash of user {}", user.id);
+            }
+            user.password_hash = hash_password(&request.password)?;
+            self.update_user_password_hash(&user).await?;
+        }
+
+        // Compare with earlier logins before this one joins them
+        let assessment = self.assess_login(&user, ctx).await?;
+        let anomalies = assessment.anomaly_names();
+        let location = assessment.location_name();
+        if assessment.is_suspicious() {
+            log::warn!(
+                target: "security",
+                "Suspicious login to user {} from {} ({}): {}",
+                user.id,
+                ctx.ip_address.as_deref().unwrap_or("unknown IP"),
+                location.as_deref().unwrap_or("unknown location"),
+                anomalies.join(", ")
+            );
+
+            if CONFIG.suspicious_login_otp {
+                return self
+                    .challenge_login(&user, method, &assessment, ctx)
+                    .await
+                    .map(LoginOutcome::ChallengeRequired);
+            }
+        }
+
+        let session = UserSession::new(user.id, ctx);
+        let event = LoginEvent::succeeded(user.id, method, session.id, ctx)
+            .with_risk(anomalies, location.clone());
+        let notice = if assessment.is_suspicious() {
+            LoginNotice::Suspicious(location.as_deref())
+        } else {
+            LoginNotice::Regular
+        };
+
+        let mut tx = self.db_pool.begin().await?;
+        self.complete_login(&mut tx, &mut user, &session, &event, throttle, notice, ctx)
+            .await?;
+        tx.commit().await?;
 
         // Generate JWT token
-        let access_token = generate_jwt_token(user.id, user.email.clone())?;
+        Self::auth_response(user, Some(session.id)).map(LoginOutcome::Authenticated)
+    }
 
-        Ok(AuthResponse {
-            access_token,
-            token_type: "Bearer".to_string(),
-            expires_in: CONFIG.jwt_expiration,
-            user: UserInfo::from(user),
-        })
+    /// Complete a suspicious login with the code emailed by `login`, on the same device
+    pub async fn verify_login(
+        &self,
+        request: VerifyLoginRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<AuthResponse> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let challenge = sqlx::query_as::<_, LoginChallenge>(&format!(
+            "SELECT {LOGIN_CHALLENGE_COLUMNS} FROM login_challenges WHERE id = $1 FOR UPDATE"
+        ))
+        .bind(request.challenge_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|challenge| challenge.is_open() && challenge.device_key == device_key(ctx))
+        .ok_or(ServiceError::InvalidToken)?;
+        let method = LoginMethod::parse(&challenge.method).ok_or(ServiceError::InternalError)?;
+
+        if !challenge.code_matches(&request.code) {
+            sqlx::query("UPDATE login_challenges SET attempts = attempts + 1 WHERE id = $1")
+                .bind(challenge.id)
+                .execute(&mut *tx)
+                .await?;
+            let event =
+                LoginEvent::failed(challenge.user_id, method, LoginFailure::InvalidCode, ctx)
+                    .with_risk(challenge.anomalies.clone(), challenge.location.clone());
+            Self::record_login(&mut tx, &event, ctx).await?;
+            tx.commit().await?;
+            return Err(ServiceError::InvalidToken);
+        }
+
+        sqlx::query("UPDATE login_challenges SET completed_at = NOW() WHERE id = $1")
+            .bind(challenge.id)
+            .execute(&mut *tx)
+            .await?;
+
+        // The account may have been deactivated since the password was checked
+        let mut user = self.get_user_by_id(challenge.user_id).await?;
+        if !user.is_active && !user.is_deletion_pending() {
+            return Err(ServiceError::Unauthorized);
+        }
+
+        let throttle = match &ctx.ip_address {
+            Some(ip) => Some(self.get_login_throttle(user.id, ip).await?),
+            None => None,
+        };
+        let session = UserSession::new(user.id, ctx);
+        let event = LoginEvent::succeeded(user.id, method, session.id, ctx)
+            .with_risk(challenge.anomalies, challenge.location);
+
+        // The challenge email already told the owner about this login
+        self.complete_login(
+            &mut tx,
+            &mut user,
+            &session,
+            &event,
+            throttle,
+            LoginNotice::None,
+            ctx,
+        )
+        .await?;
+        tx.commit().await?;
+
+        Self::auth_response(user, Some(session.id))
     }
 
     /// Verify email address
     pub async fn verify_email(
         &self,
         request: VerifyEmailRequest,
+        ctx: &RequestContext,
     ) -> ServiceResult<MessageResponse> {
         let mut user = self.get_user_by_verification_token(&request.token).await?;
 
-        if user.verification_token.as_ref() != Some(&request.token) {
+        if !user.is_verification_token_valid(&request.token) {
             return Err(ServiceError::InvalidToken);
         }
 
         user.verify_email();
-        self.update_user_verification(&user).await?;
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_verification(&mut tx, &user).await?;
+        let event = AuditEvent::by_user(AuditAction::EmailVerified, user.id, ctx);
+        AuditService::record(&mut tx, &event).await?;
+        WebhookService::enqueue(
+            &mut tx,
+            WebhookEventType::EmailVerified,
+            json!({ "user_id": user.id, "email": user.email }),
+        )
+        .await?;
+        tx.commit().await?;
 
         Ok(MessageResponse::new("Email verified successfully."))
     }
 
+    /// Issue a fresh email verification token
+    pub async fn resend_verification(
+        &self,
+        request: ResendVerificationRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<MessageResponse> {
+        let response = MessageResponse::new(
+            "If the email exists and is not yet verified, a new verification link has been sent.",
+        );
+
+        let mut user = match self.get_user_by_email(&request.email).await {
+            Ok(user) => user,
+            // Don't reveal if email exists or not
+            Err(ServiceError::NotFound) => return Ok(response),
+            Err(e) => return Err(e),
+        };
+
+        if user.is_verified {
+            return Ok(response);
+        }
+
+        let verification_token =
+            user.generate_verification_token(CONFIG.verification_token_expiration);
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_verification(&mut tx, &user).await?;
+        self.notifications
+            .queue_verification_email(&mut tx, &user, &verification_token, ctx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(response)
+    }
+
     /// Request password reset
     pub async fn request_password_reset(
         &self,
         request: ResetPasswordRequest,
+        ctx: &RequestContext,
     ) -> ServiceResult<MessageResponse> {
         let mut user = match self.get_user_by_email(&request.email).await {
             Ok(user) => user,
@@ -128,16 +465,15 @@ impl AuthService {
             Err(e) => return Err(e),
         };
 
-        user.generate_reset_token();
-        self.update_user_reset_token(&user).await?;
-
-        // In a real application, you would send an email here
-        // For now, we'll just return the token (don't do this in production!)
-        log::info!(
-            "Password reset token for {}: {}",
-            user.email,
-            user.reset_token.as_ref().unwrap()
-        );
+        let reset_token = user.generate_reset_token();
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_reset_token(&mut tx, &user).await?;
+        let event = AuditEvent::new(AuditAction::PasswordResetRequested, ctx).target(user.id);
+        AuditService::record(&mut tx, &event).await?;
+        self.notifications
+            .queue_password_reset_email(&mut tx, &user, &reset_token, ctx)
+            .await?;
+        tx.commit().await?;
 
         Ok(MessageResponse::new(
             "If the email exists, a password reset link has been sent.",
@@ -148,6 +484,7 @@ impl AuthService {
     pub async fn confirm_password_reset(
         &self,
         request: ConfirmResetPasswordRequest,
+        ctx: &RequestContext,
     ) -> ServiceResult<MessageResponse> {
         let mut user = self.get_user_by_reset_token(&request.token).await?;
 
@@ -155,11 +492,30 @@ impl AuthService {
             return Err(ServiceError::InvalidToken);
         }
 
+        // Reject recently used passwords
+        self.ensure_password_not_reused(&user, &request.new_password)
+            .await?;
+
         // Hash new password
-        let new_password_hash = hash(&request.new_password, CONFIG.bcrypt_cost)?;
+        let previous_password_hash = user.password_hash.clone();
+        let new_password_hash = hash_password(&request.new_password)?;
         user.update_password(new_password_hash);
 
-        self.update_user_password(&user).await?;
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_password(&mut tx, &user, &previous_password_hash)
+            .await?;
+        let event = AuditEvent::by_user(AuditAction::PasswordReset, user.id, ctx);
+        AuditService::record(&mut tx, &event).await?;
+        WebhookService::enqueue(
+            &mut tx,
+            WebhookEventType::PasswordChanged,
+            json!({ "user_id": user.id, "method": "reset" }),
+        )
+        .await?;
+        self.notifications
+            .queue_password_changed_email(&mut tx, &user, ctx)
+            .await?;
+        tx.commit().await?;
 
         Ok(MessageResponse::new("Password reset successfully."))
     }
@@ -169,181 +525,628 @@ impl AuthService {
         &self,
         user_id: Uuid,
         request: ChangePasswordRequest,
+        ctx: &RequestContext,
     ) -> ServiceResult<MessageResponse> {
         let mut user = self.get_user_by_id(user_id).await?;
 
         // Verify current password
-        if !verify(&request.current_password, &user.password_hash)? {
+        if !verify_password(&request.current_password, &user.password_hash)? {
             return Err(ServiceError::InvalidCredentials);
         }
 
+        // Reject recently used passwords
+        self.ensure_password_not_reused(&user, &request.new_password)
+            .await?;
+
         // Hash new password
-        let new_password_hash = hash(&request.new_password, CONFIG.bcrypt_cost)?;
+        let previous_password_hash = user.password_hash.clone();
+        let new_password_hash = hash_password(&request.new_password)?;
         user.update_password(new_password_hash);
 
-        self.update_user_password(&user).await?;
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_password(&mut tx, &user, &previous_password_hash)
+            .await?;
+        let event = AuditEvent::by_user(AuditAction::PasswordChanged, user.id, ctx);
+        AuditService::record(&mut tx, &event).await?;
+        WebhookService::enqueue(
+            &mut tx,
+            WebhookEventType::PasswordChanged,
+            json!({ "user_id": user.id, "method": "change" }),
+        )
+        .await?;
+        self.notifications
+            .queue_password_changed_email(&mut tx, &user, ctx)
+            .await?;
+        tx.commit().await?;
 
         Ok(MessageResponse::new("Password changed successfully."))
     }
 
+    /// Start an email change; the new address must be confirmed before it takes effect
+    pub async fn request_email_change(
+        &self,
+        user_id: Uuid,
+        request: RequestEmailChangeRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<MessageResponse> {
+        let mut user = self.get_user_by_id(user_id).await?;
+
+        // Verify current password
+        if !verify_password(&request.current_password, &user.password_hash)? {
+            return Err(ServiceError::InvalidCredentials);
+        }
+
+        if email_identity_key(&request.new_email) == email_identity_key(&user.email) {
+            return Err(ServiceError::BadRequest(
+                "New email must differ from the current email".to_string(),
+            ));
+        }
+
+        if self.get_user_by_email(&request.new_email).await.is_ok() {
+            return Err(ServiceError::UserAlreadyExists);
+        }
+
+        let token = user
+            .generate_email_change_token(request.new_email, CONFIG.email_change_token_expiration);
+
+        // Confirmation goes to the new address, a notice to the current one
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_email(&mut tx, &user).await?;
+        let event = AuditEvent::by_user(AuditAction::EmailChangeRequested, user.id, ctx)
+            .metadata(json!({ "new_email": user.pending_email }));
+        AuditService::record(&mut tx, &event).await?;
+        self.notifications
+            .queue_email_change_emails(&mut tx, &user, &token, ctx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(MessageResponse::new(
+            "A confirmation link has been sent to the new email address.",
+        ))
+    }
+
+    /// Confirm an email change and issue a token carrying the new email
+    pub async fn confirm_email_change(
+        &self,
+        user_id: Uuid,
+        session_id: Option<Uuid>,
+        request: ConfirmEmailChangeRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<AuthResponse> {
+        let mut user = self.get_user_by_id(user_id).await?;
+
+        if !user.is_email_change_token_valid(&request.token) {
+            return Err(ServiceError::InvalidToken);
+        }
+
+        let previous_email = user.email.clone();
+        user.confirm_email_change();
+
+        // The unique index rejects the change if the address was taken in the meantime
+        let mut tx = self.db_pool.begin().await?;
+        self.update_user_email(&mut tx, &user).await?;
+        let event = AuditEvent::by_user(AuditAction::EmailChanged, user.id, ctx).metadata(json!({
+            "previous_email": previous_email,
+            "new_email": user.email,
+        }));
+        AuditService::record(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        log::info!(
+            "User {} changed email from {} to {}",
+            user.id,
+            previous_email,
+            user.email
+        );
+
+        Self::auth_response(user, session_id)
+    }
+
+    /// Set or remove the username used as an alternative login identifier
+    pub async fn set_username(
+        &self,
+        user_id: Uuid,
+        request: SetUsernameRequest,
+    ) -> ServiceResult<UserInfo> {
+        let mut user = self.get_user_by_id(user_id).await?;
+
+        if let Some(username) = &request.username {
+            if let Ok(owner) = self.get_user_by_username(username).await {
+                if owner.id != user.id {
+                    return Err(ServiceError::UsernameTaken);
+                }
+            }
+        }
+
+        // The unique index still rejects a username claimed concurrently
+        user.username = request.username;
+        user.updated_at = chrono::Utc::now();
+        sqlx::query("UPDATE auth_users SET username = $1, updated_at = $2 WHERE id = $3")
+            .bind(&user.username)
+            .bind(user.updated_at)
+            .bind(user.id)
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(UserInfo::from(user))
+    }
+
+    /// Update the fields present in the request, leaving the rest of the profile unchanged
+    pub async fn update_profile(
+        &self,
+        user_id: Uuid,
+        request: UpdateProfileRequest,
+    ) -> ServiceResult<UserInfo> {
+        let mut user = self.get_user_by_id(user_id).await?;
+        user.update_profile(request);
+
+        sqlx::query(
+            r#"
+            UPDATE auth_users
+            SET display_name = $1, locale = $2, timezone = $3, avatar_url = $4, updated_at = $5
+            WHERE id = $6
+            "#,
+        )
+        .bind(&user.display_name)
+        .bind(&user.locale)
+        .bind(&user.timezone)
+        .bind(&user.avatar_url)
+        .bind(user.updated_at)
+        .bind(user.id)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(UserInfo::from(user))
+    }
+
+    /// Deactivate the account and schedule its erasure; logging in before then cancels it
+    pub async fn request_account_deletion(
+        &self,
+        user_id: Uuid,
+        request: DeleteAccountRequest,
+        ctx: &RequestContext,
+    ) -> ServiceResult<AccountDeletionResponse> {
+        let mut user = self.get_user_by_id(user_id).await?;
+
+        // Verify current password
+        if !verify_password(&request.current_password, &user.password_hash)? {
+            return Err(ServiceError::InvalidCredentials);
+        }
+
+        user.schedule_deletion(CONFIG.account_deletion_grace_days);
+
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE auth_users
+            SET is_active = $1, deletion_scheduled_at = $2, updated_at = $3
+            WHERE id = $4
+            "#,
+        )
+        .bind(user.is_active)
+        .bind(user.deletion_scheduled_at)
+        .bind(user.updated_at)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+        // Logging in again cancels the deletion; until then no token is accepted
+        SessionService::revoke_all_sessions(&mut tx, user.id).await?;
+        let event = AuditEvent::by_user(AuditAction::AccountDeletionRequested, user.id, ctx)
+            .metadata(json!({ "deletion_scheduled_at": user.deletion_scheduled_at }));
+        AuditService::record(&mut tx, &event).await?;
+        self.notifications
+            .queue_account_deletion_email(&mut tx, &user, ctx)
+            .await?;
+        tx.commit().await?;
+
+        log::info!("User {} scheduled the deletion of their account", user.id);
+
+        Ok(AccountDeletionResponse {
+            message: "Your account has been deactivated and will be deleted. Log in before the \
+                      scheduled date to cancel."
+                .to_string(),
+            deletion_scheduled_at: user.deletion_scheduled_at.unwrap_or_else(chrono::Utc::now),
+        })
+    }
+
     /// Get user info (for authenticated users)
     pub async fn get_user_info(&self, user_id: Uuid) -> ServiceResult<UserInfo> {
         let user = self.get_user_by_id(user_id).await?;
         Ok(UserInfo::from(user))
     }
 
-    /// Refresh JWT token
-    pub async fn refresh_token(&self, user_id: Uuid) -> ServiceResult<AuthResponse> {
+    /// Refresh JWT token, keeping it in the same session
+    pub async fn refresh_token(
+        &self,
+        user_id: Uuid,
+        session_id: Option<Uuid>,
+    ) -> ServiceResult<AuthResponse> {
         let user = self.get_user_by_id(user_id).await?;
 
-        if !user.can_login() {
+        // Login backoff doesn't end sessions, so a user under attack stays logged in
+        if !user.is_active {
             return Err(ServiceError::Unauthorized);
         }
 
-        let access_token = generate_jwt_token(user.id, user.email.clone())?;
+        if let Some(session_id) = session_id {
+            let mut conn = self.db_pool.acquire().await?;
+            SessionService::touch_session(&mut conn, user_id, session_id).await?;
+        }
+
+        Self::auth_response(user, session_id)
+    }
+
+    /// End the session of the token used, so neither it nor its refreshes are accepted
+    pub async fn logout(
+        &self,
+        user_id: Uuid,
+        session_id: Option<Uuid>,
+        ctx: &RequestContext,
+    ) -> ServiceResult<MessageResponse> {
+        let mut tx = self.db_pool.begin().await?;
+        if let Some(session_id) = session_id {
+            SessionService::revoke_session(&mut tx, user_id, session_id).await?;
+        }
+        let event = AuditEvent::by_user(AuditAction::Logout, user_id, ctx)
+            .metadata(json!({ "session_id": session_id }));
+        AuditService::record(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        log::info!("User {} logged out", user_id);
+        Ok(MessageResponse::new("Logged out successfully"))
+    }
+
+    // Private helper methods
+    /// Fail a login to an account that doesn't exist like one with a wrong password: backed off
+    /// by the same rules, and otherwise only after the time of a password check
+    async fn reject_unknown_login(
+        &self,
+        identifier: &LoginIdentifier<'_>,
+        method: LoginMethod,
+        password: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<LoginOutcome> {
+        let identifier_hash = unknown_identifier_hash(identifier);
+        let mut throttles = Vec::new();
+        if let Some(ip) = &ctx.ip_address {
+            throttles.push(
+                self.get_unknown_login_throttle(&identifier_hash, ip)
+                    .await?,
+            );
+        }
+        throttles.push(
+            self.get_unknown_login_throttle(&identifier_hash, "")
+                .await?,
+        );
+        // The IP's block first, as for existing accounts, which no IP has logged in to
+        let blocked = throttles
+            .iter()
+            .find_map(|throttle| retry_after(throttle.blocked_until));
+
+        let mut tx = self.db_pool.begin().await?;
+        let failure_reason = match blocked {
+            Some(_) => LoginFailure::Throttled.as_str(),
+            None => {
+                verify_dummy_password(password);
+                for throttle in &mut throttles {
+                    let backoff = if throttle.ip_address.is_empty() {
+                        LoginBackoff::for_account()
+                    } else {
+                        LoginBackoff::for_ip()
+                    };
+                    throttle.record_failure(&backoff);
+                    self.save_unknown_login_throttle(&mut tx, throttle).await?;
+                }
+                "unknown_account"
+            }
+        };
+        let event = AuditEvent::new(AuditAction::LoginFailed, ctx).metadata(json!({
+            "method": method.as_str(),
+            "failure_reason": failure_reason,
+        }));
+        AuditService::record(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        Err(blocked.map_or(ServiceError::InvalidCredentials, ServiceError::RateLimited))
+    }
+
+    /// Add a login attempt to the account's login history and the audit log
+    async fn record_login(
+        conn: &mut PgConnection,
+        event: &LoginEvent,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        SessionService::record_login_event(conn, event).await?;
+        AuditService::record(conn, &AuditEvent::login(event, ctx)).await
+    }
+
+    /// Compare a login with the password already verified against the account's earlier ones
+    async fn assess_login(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<LoginAssessment> {
+        let mut conn = self.db_pool.acquire().await?;
+        let baseline = SessionService::login_baseline(&mut conn, user.id, &device_key(ctx)).await?;
+
+        let geoip = self.geoip.as_deref();
+        Ok(LoginAssessment::evaluate(
+            &baseline,
+            ctx.ip_address.as_deref(),
+            |ip| geoip.and_then(|geoip| geoip.locate_str(ip)),
+            CONFIG.suspicious_login_distance_km,
+        ))
+    }
+
+    /// Hold back a suspicious login until the code emailed to the owner is entered
+    async fn challenge_login(
+        &self,
+        user: &AuthUser,
+        method: LoginMethod,
+        assessment: &LoginAssessment,
+        ctx: &RequestContext,
+    ) -> ServiceResult<LoginChallengeResponse> {
+        let (challenge, code) = LoginChallenge::new(
+            user.id,
+            method,
+            device_key(ctx),
+            assessment,
+            CONFIG.login_challenge_expiration,
+        );
+        let event = LoginEvent::failed(user.id, method, LoginFailure::ChallengeRequired, ctx)
+            .with_risk(challenge.anomalies.clone(), challenge.location.clone());
+
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO login_challenges
+                (id, user_id, code_hash, method, device_key, anomalies, location, attempts,
+                 expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .bind(challenge.id)
+        .bind(challenge.user_id)
+        .bind(&challenge.code_hash)
+        .bind(&challenge.method)
+        .bind(&challenge.device_key)
+        .bind(&challenge.anomalies)
+        .bind(&challenge.location)
+        .bind(challenge.attempts)
+        .bind(challenge.expires_at)
+        .bind(challenge.created_at)
+        .execute(&mut *tx)
+        .await?;
+        Self::record_login(&mut tx, &event, ctx).await?;
+        self.notifications
+            .queue_login_challenge_email(&mut tx, user, &challenge, &code, ctx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(LoginChallengeResponse {
+            challenge_id: challenge.id,
+            expires_at: challenge.expires_at,
+            message: "This sign-in looks unusual. Enter the code we emailed you to continue."
+                .to_string(),
+        })
+    }
+
+    /// Record a successful login: reset failed attempts, start the session and log the event
+    #[allow(clippy::too_many_arguments)]
+    async fn complete_login(
+        &self,
+        conn: &mut PgConnection,
+        user: &mut AuthUser,
+        session: &UserSession,
+        event: &LoginEvent,
+        throttle: Option<LoginThrottle>,
+        notice: LoginNotice<'_>,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        if user.is_deletion_pending() {
+            user.cancel_deletion();
+            log::info!("User {} cancelled the deletion of their account", user.id);
+        }
+
+        user.reset_failed_attempts();
+        self.update_user_successful_login(conn, user).await?;
+        SessionService::create_session(conn, session).await?;
+        Self::record_login(conn, event, ctx).await?;
+        if let Some(mut throttle) = throttle {
+            throttle.record_success();
+            self.save_login_throttle(conn, &throttle).await?;
+        }
+
+        match notice {
+            LoginNotice::Suspicious(location) => {
+                self.notifications
+                    .queue_suspicious_login_email(conn, user, location, ctx)
+                    .await
+            }
+            LoginNotice::Regular if CONFIG.login_notifications => {
+                self.notifications
+                    .queue_new_login_email(conn, user, ctx)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Build a token response, restricting the token to password changes when one is due
+    fn auth_response(user: AuthUser, session_id: Option<Uuid>) -> ServiceResult<AuthResponse> {
+        if user.password_change_required(CONFIG.password_max_age_days) {
+            return Ok(AuthResponse {
+                access_token: generate_password_change_token(&user, session_id)?,
+                token_type: "Bearer".to_string(),
+                expires_in: CONFIG.password_change_token_expiration,
+                password_change_required: true,
+                user: UserInfo::from(user),
+            });
+        }
 
         Ok(AuthResponse {
-            access_token,
+            access_token: generate_jwt_token(&user, session_id)?,
             token_type: "Bearer".to_string(),
             expires_in: CONFIG.jwt_expiration,
+            password_change_required: false,
             user: UserInfo::from(user),
         })
     }
 
-    // Private helper methods
     async fn get_user_by_email(&self, email: &str) -> ServiceResult<AuthUser> {
-        let row = sqlx::query(
-            r#"
-            SELECT id, email, password_hash, is_active, is_verified,
-                   created_at, updated_at, last_login, failed_login_attempts,
-                   locked_until, verification_token, reset_token, reset_token_expires
-            FROM auth_users
-            WHERE email = $1
-            "#,
-        )
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE LOWER(email) = LOWER($1)"
+        ))
         .bind(email)
         .fetch_one(&self.db_pool)
         .await?;
 
-        let user = AuthUser {
-            id: row.get("id"),
-            email: row.get("email"),
-            password_hash: row.get("password_hash"),
-            is_active: row.get("is_active"),
-            is_verified: row.get("is_verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            last_login: row.get("last_login"),
-            failed_login_attempts: row.get("failed_login_attempts"),
-            locked_until: row.get("locked_until"),
-            verification_token: row.get("verification_token"),
-            reset_token: row.get("reset_token"),
-            reset_token_expires: row.get("reset_token_expires"),
-        };
+        Ok(user)
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> ServiceResult<AuthUser> {
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE LOWER(username) = LOWER($1)"
+        ))
+        .bind(username)
+        .fetch_one(&self.db_pool)
+        .await?;
 
         Ok(user)
     }
 
     async fn get_user_by_id(&self, user_id: Uuid) -> ServiceResult<AuthUser> {
-        let row = sqlx::query(
-            r#"
-            SELECT id, email, password_hash, is_active, is_verified,
-                   created_at, updated_at, last_login, failed_login_attempts,
-                   locked_until, verification_token, reset_token, reset_token_expires
-            FROM auth_users
-            WHERE id = $1
-            "#,
-        )
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE id = $1"
+        ))
         .bind(user_id)
         .fetch_one(&self.db_pool)
         .await?;
 
-        let user = AuthUser {
-            id: row.get("id"),
-            email: row.get("email"),
-            password_hash: row.get("password_hash"),
-            is_active: row.get("is_active"),
-            is_verified: row.get("is_verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            last_login: row.get("last_login"),
-            failed_login_attempts: row.get("failed_login_attempts"),
-            locked_until: row.get("locked_until"),
-            verification_token: row.get("verification_token"),
-            reset_token: row.get("reset_token"),
-            reset_token_expires: row.get("reset_token_expires"),
-        };
-
         Ok(user)
     }
 
     async fn get_user_by_verification_token(&self, token: &str) -> ServiceResult<AuthUser> {
-        let row = sqlx::query(
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE verification_token = $1"
+        ))
+        .bind(hash_token(token))
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn get_user_by_reset_token(&self, token: &str) -> ServiceResult<AuthUser> {
+        let user = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE reset_token = $1"
+        ))
+        .bind(hash_token(token))
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn get_login_throttle(&self, user_id: Uuid, ip: &str) -> ServiceResult<LoginThrottle> {
+        let throttle = sqlx::query_as::<_, LoginThrottle>(
             r#"
-            SELECT id, email, password_hash, is_active, is_verified,
-                   created_at, updated_at, last_login, failed_login_attempts,
-                   locked_until, verification_token, reset_token, reset_token_expires
-            FROM auth_users
-            WHERE verification_token = $1
+            SELECT user_id, ip_address, failed_attempts, blocked_until, last_success_at, updated_at
+            FROM login_throttles
+            WHERE user_id = $1 AND ip_address = $2
             "#,
         )
-        .bind(token)
-        .fetch_one(&self.db_pool)
+        .bind(user_id)
+        .bind(ip)
+        .fetch_optional(&self.db_pool)
         .await?;
 
-        let user = AuthUser {
-            id: row.get("id"),
-            email: row.get("email"),
-            password_hash: row.get("password_hash"),
-            is_active: row.get("is_active"),
-            is_verified: row.get("is_verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            last_login: row.get("last_login"),
-            failed_login_attempts: row.get("failed_login_attempts"),
-            locked_until: row.get("locked_until"),
-            verification_token: row.get("verification_token"),
-            reset_token: row.get("reset_token"),
-            reset_token_expires: row.get("reset_token_expires"),
-        };
+        Ok(throttle.unwrap_or_else(|| LoginThrottle::new(user_id, ip.to_string())))
+    }
 
-        Ok(user)
+    async fn save_login_throttle(
+        &self,
+        conn: &mut PgConnection,
+        throttle: &LoginThrottle,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO login_throttles
+                (user_id, ip_address, failed_attempts, blocked_until, last_success_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, ip_address) DO UPDATE
+            SET failed_attempts = EXCLUDED.failed_attempts,
+                blocked_until = EXCLUDED.blocked_until,
+                last_success_at = EXCLUDED.last_success_at,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(throttle.user_id)
+        .bind(&throttle.ip_address)
+        .bind(throttle.failed_attempts)
+        .bind(throttle.blocked_until)
+        .bind(throttle.last_success_at)
+        .bind(throttle.updated_at)
+        .execute(conn)
+        .await?;
+
+        Ok(())
     }
 
-    async fn get_user_by_reset_token(&self, token: &str) -> ServiceResult<AuthUser> {
-        let row = sqlx::query(
+    async fn get_unknown_login_throttle(
+        &self,
+        identifier_hash: &str,
+        ip: &str,
+    ) -> ServiceResult<UnknownLoginThrottle> {
+        let throttle = sqlx::query_as::<_, UnknownLoginThrottle>(
             r#"
-            SELECT id, email, password_hash, is_active, is_verified,
-                   created_at, updated_at, last_login, failed_login_attempts,
-                   locked_until, verification_token, reset_token, reset_token_expires
-            FROM auth_users
-            WHERE reset_token = $1
+            SELECT identifier_hash, ip_address, failed_attempts, blocked_until, updated_at
+            FROM unknown_login_throttles
+            WHERE identifier_hash = $1 AND ip_address = $2
             "#,
         )
-        .bind(token)
-        .fetch_one(&self.db_pool)
+        .bind(identifier_hash)
+        .bind(ip)
+        .fetch_optional(&self.db_pool)
         .await?;
 
-        let user = AuthUser {
-            id: row.get("id"),
-            email: row.get("email"),
-            password_hash: row.get("password_hash"),
-            is_active: row.get("is_active"),
-            is_verified: row.get("is_verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            last_login: row.get("last_login"),
-            failed_login_attempts: row.get("failed_login_attempts"),
-            locked_until: row.get("locked_until"),
-            verification_token: row.get("verification_token"),
-            reset_token: row.get("reset_token"),
-            reset_token_expires: row.get("reset_token_expires"),
-        };
+        Ok(throttle.unwrap_or_else(|| {
+            UnknownLoginThrottle::new(identifier_hash.to_string(), ip.to_string())
+        }))
+    }
 
-        Ok(user)
+    async fn save_unknown_login_throttle(
+        &self,
+        conn: &mut PgConnection,
+        throttle: &UnknownLoginThrottle,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO unknown_login_throttles
+                (identifier_hash, ip_address, failed_attempts, blocked_until, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (identifier_hash, ip_address) DO UPDATE
+            SET failed_attempts = EXCLUDED.failed_attempts,
+                blocked_until = EXCLUDED.blocked_until,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(&throttle.identifier_hash)
+        .bind(&throttle.ip_address)
+        .bind(throttle.failed_attempts)
+        .bind(throttle.blocked_until)
+        .bind(throttle.updated_at)
+        .execute(conn)
+        .await?;
+
+        Ok(())
     }
 
-    async fn update_user_login_attempts(&self, user: &AuthUser) -> ServiceResult<()> {
+    async fn update_user_login_attempts(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+    ) -> ServiceResult<()> {
         sqlx::query(
             r#"
             UPDATE auth_users
@@ -355,50 +1158,67 @@ impl AuthService {
         .bind(user.locked_until)
         .bind(user.updated_at)
         .bind(user.id)
-        .execute(&self.db_pool)
+        .execute(conn)
         .await?;
 
         Ok(())
     }
 
-    async fn update_user_successful_login(&self, user: &AuthUser) -> ServiceResult<()> {
+    async fn update_user_successful_login(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+    ) -> ServiceResult<()> {
         sqlx::query(
             r#"
             UPDATE auth_users
-            SET failed_login_attempts = $1, locked_until = $2, last_login = $3, updated_at = $4
-            WHERE id = $5
+            SET failed_login_attempts = $1, locked_until = $2, last_login = $3, is_active = $4,
+                deletion_scheduled_at = $5, updated_at = $6
+            WHERE id = $7
             "#,
         )
         .bind(user.failed_login_attempts)
         .bind(user.locked_until)
         .bind(user.last_login)
+        .bind(user.is_active)
+        .bind(user.deletion_scheduled_at)
         .bind(user.updated_at)
         .bind(user.id)
-        .execute(&self.db_pool)
+        .execute(conn)
         .await?;
 
         Ok(())
     }
 
-    async fn update_user_verification(&self, user: &AuthUser) -> ServiceResult<()> {
+    async fn update_user_verification(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+    ) -> ServiceResult<()> {
         sqlx::query(
             r#"
             UPDATE auth_users
-            SET is_verified = $1, verification_token = $2, updated_at = $3
-            WHERE id = $4
+            SET is_verified = $1, verification_token = $2, verification_token_expires = $3,
+                updated_at = $4
+            WHERE id = $5
             "#,
         )
         .bind(user.is_verified)
         .bind(&user.verification_token)
+        .bind(user.verification_token_expires)
         .bind(user.updated_at)
         .bind(user.id)
-        .execute(&self.db_pool)
+        .execute(conn)
         .await?;
 
         Ok(())
     }
 
-    async fn update_user_reset_token(&self, user: &AuthUser) -> ServiceResult<()> {
+    async fn update_user_reset_token(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+    ) -> ServiceResult<()> {
         sqlx::query(
             r#"
             UPDATE auth_users
@@ -410,78 +1230,363 @@ impl AuthService {
         .bind(user.reset_token_expires)
         .bind(user.updated_at)
         .bind(user.id)
-        .execute(&self.db_pool)
+        .execute(conn)
         .await?;
 
         Ok(())
     }
 
-    async fn update_user_password(&self, user: &AuthUser) -> ServiceResult<()> {
+    async fn update_user_password(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        previous_password_hash: &str,
+    ) -> ServiceResult<()> {
         sqlx::query(
             r#"
             UPDATE auth_users
-            SET password_hash = $1, reset_token = $2, reset_token_expires = $3, updated_at = $4
-            WHERE id = $5
+            SET password_hash = $1, reset_token = $2, reset_token_expires = $3,
+                password_changed_at = $4, must_change_password = $5, updated_at = $6
+            WHERE id = $7
             "#,
         )
         .bind(&user.password_hash)
         .bind(&user.reset_token)
         .bind(user.reset_token_expires)
+        .bind(user.password_changed_at)
+        .bind(user.must_change_password)
         .bind(user.updated_at)
         .bind(user.id)
+        .execute(&mut *conn)
+        .await?;
+
+        if CONFIG.password_history_size > 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO password_history (id, user_id, password_hash, created_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(user.id)
+            .bind(previous_password_hash)
+            .bind(user.updated_at)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        // Keep only the most recent entries
+        sqlx::query(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = $1
+              AND id NOT IN (
+                  SELECT id FROM password_history
+                  WHERE user_id = $1
+                  ORDER BY created_at DESC
+                  LIMIT $2
+              )
+            "#,
+        )
+        .bind(user.id)
+        .bind(CONFIG.password_history_size)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_user_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE auth_users
+            SET email = $1, is_verified = $2, verification_token = $3,
+                verification_token_expires = $4, pending_email = $5, email_change_token = $6,
+                email_change_token_expires = $7, updated_at = $8
+            WHERE id = $9
+            "#,
+        )
+        .bind(&user.email)
+        .bind(user.is_verified)
+        .bind(&user.verification_token)
+        .bind(user.verification_token_expires)
+        .bind(&user.pending_email)
+        .bind(&user.email_change_token)
+        .bind(user.email_change_token_expires)
+        .bind(user.updated_at)
+        .bind(user.id)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    // Replace the stored hash without treating it as a password change
+    async fn update_user_password_hash(&self, user: &AuthUser) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE auth_users
+            SET password_hash = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(&user.password_hash)
+        .bind(user.id)
         .execute(&self.db_pool)
         .await?;
 
         Ok(())
     }
+
+    /// Check a new password against the current and the last N previous passwords
+    async fn ensure_password_not_reused(
+        &self,
+        user: &AuthUser,
+        new_password: &str,
+    ) -> ServiceResult<()> {
+        if CONFIG.password_history_size == 0 {
+            return Ok(());
+        }
+
+        if verify_password(new_password, &user.password_hash)? {
+            return Err(ServiceError::PasswordReused);
+        }
+
+        let previous_hashes: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT password_hash
+            FROM password_history
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(user.id)
+        .bind(CONFIG.password_history_size)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for previous_hash in previous_hashes {
+            if verify_password(new_password, &previous_hash).unwrap_or(false) {
+                return Err(ServiceError::PasswordReused);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    // use sqlx::postgres::PgPoolOptions; // Commented out due to unused import
+    use crate::{
+        mailer::templates::EmailTemplates,
+        middleware::auth::verify_jwt_token,
+        test_support::{test_pool, unique_email},
+    };
+    use std::sync::Arc;
 
-    async fn create_test_pool() -> Pool<Postgres> {
-        // This would need a test database URL
-        // For now, we'll skip actual database tests
-        todo!("Implement test database setup")
+    const PASSWORD: &str = "Quartz!Lantern7&Meadow";
+
+    fn test_notifications() -> NotificationService {
+        NotificationService::new(Arc::new(EmailTemplates::builtin()))
+    }
+
+    async fn register_user(auth_service: &AuthService, email: &str) -> AuthUser {
+        let request = RegisterRequest {
+            email: email.to_string(),
+            password: PASSWORD.to_string(),
+            username: None,
+        };
+        auth_service
+            .register(request, &RequestContext::default())
+            .await
+            .unwrap();
+        auth_service.get_user_by_email(email).await.unwrap()
+    }
+
+    // Log in with the password and return the session the token belongs to
+    async fn login_session(auth_service: &AuthService, email: &str) -> Uuid {
+        let request = LoginRequest {
+            email: Some(email.to_string()),
+            username: None,
+            password: PASSWORD.to_string(),
+        };
+        match auth_service
+            .login(request, &RequestContext::default())
+            .await
+            .unwrap()
+        {
+            LoginOutcome::Authenticated(response) => verify_jwt_token(&response.access_token)
+                .unwrap()
+                .sid
+                .unwrap(),
+            LoginOutcome::ChallengeRequired(_) => panic!("login was challenged"),
+        }
     }
 
     #[tokio::test]
     #[ignore] // Requires test database
     async fn test_user_registration() {
-        let pool = create_test_pool().await;
-        let auth_service = AuthService::new(pool);
+        let pool = test_pool().await;
+        let auth_service = AuthService::new(pool, test_notifications(), None);
 
         let request = RegisterRequest {
-            email: "test@example.com".to_string(),
+            email: unique_email("register"),
             password: "Test123!@#".to_string(),
+            username: None,
         };
 
-        let result = auth_service.register(request).await;
+        let result = auth_service
+            .register(request, &RequestContext::default())
+            .await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     #[ignore] // Requires test database
     async fn test_user_login() {
-        let pool = create_test_pool().await;
-        let auth_service = AuthService::new(pool);
+        let pool = test_pool().await;
+        let auth_service = AuthService::new(pool, test_notifications(), None);
+        let email = unique_email("login");
 
         // First register a user
         let register_request = RegisterRequest {
-            email: "test@example.com".to_string(),
+            email: email.clone(),
             password: "Test123!@#".to_string(),
+            username: None,
         };
-        auth_service.register(register_request).await.unwrap();
+        auth_service
+            .register(register_request, &RequestContext::default())
+            .await
+            .unwrap();
 
         // Then try to login
         let login_request = LoginRequest {
-            email: "test@example.com".to_string(),
+            email: Some(email),
+            username: None,
             password: "Test123!@#".to_string(),
         };
 
-        let result = auth_service.login(login_request).await;
-        assert!(result.is_ok());
+        let result = auth_service
+            .login(login_request, &RequestContext::default())
+            .await;
+        assert!(matches!(result, Ok(LoginOutcome::Authenticated(_))));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_change_password_rejects_reuse() {
+        let pool = test_pool().await;
+        let auth_service = AuthService::new(pool, test_notifications(), None);
+        let email = unique_email("reuse");
+
+        let register_request = RegisterRequest {
+            email: email.clone(),
+            password: "Quartz!Lantern7&Meadow".to_string(),
+            username: None,
+        };
+        auth_service
+            .register(register_request, &RequestContext::default())
+            .await
+            .unwrap();
+        let user = auth_service.get_user_by_email(&email).await.unwrap();
+
+        let change = |current: &str, new: &str| ChangePasswordRequest {
+            current_password: current.to_string(),
+            new_password: new.to_string(),
+        };
+
+        auth_service
+            .change_password(
+                user.id,
+                change("Quartz!Lantern7&Meadow", "Harbor$Velvet9!Cobalt"),
+                &RequestContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = auth_service
+            .change_password(
+                user.id,
+                change("Harbor$Velvet9!Cobalt", "Quartz!Lantern7&Meadow"),
+                &RequestContext::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(ServiceError::PasswordReused)));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_account_deletion_revokes_sessions() {
+        let pool = test_pool().await;
+        let auth_service = AuthService::new(pool.clone(), test_notifications(), None);
+        let sessions = SessionService::new(pool);
+        let email = unique_email("deletion");
+        let user = register_user(&auth_service, &email).await;
+        let session_id = login_session(&auth_service, &email).await;
+        assert!(sessions.is_active(user.id, session_id).await.unwrap());
+
+        auth_service
+            .request_account_deletion(
+                user.id,
+                DeleteAccountRequest {
+                    current_password: PASSWORD.to_string(),
+                },
+                &RequestContext::default(),
+            )
+            .await
+            .unwrap();
+        assert!(!sessions.is_active(user.id, session_id).await.unwrap());
+
+        // Logging in cancels the deletion with a new session
+        let session_id = login_session(&auth_service, &email).await;
+        assert!(sessions.is_active(user.id, session_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_unknown_accounts_back_off_like_existing_ones() {
+        let pool = test_pool().await;
+        let auth_service = AuthService::new(pool, test_notifications(), None);
+        let existing = unique_email("backoff");
+        register_user(&auth_service, &existing).await;
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.44".to_string()),
+            ..Default::default()
+        };
+
+        // Status and Retry-After of failed logins, enough to trigger the per-IP backoff
+        let attempts = |email: String| {
+            let auth_service = auth_service.clone();
+            let ctx = ctx.clone();
+            async move {
+                let mut responses = Vec::new();
+                for _ in 0..=CONFIG.login_backoff_ip_threshold {
+                    let request = LoginRequest {
+                        email: Some(email.clone()),
+                        username: None,
+                        password: "Wrong!Password9".to_string(),
+                    };
+                    match auth_service.login(request, &ctx).await {
+                        Err(ServiceError::InvalidCredentials) => responses.push((401, None)),
+                        Err(ServiceError::RateLimited(seconds)) => {
+                            responses.push((429, Some(seconds)))
+                        }
+                        other => panic!("unexpected login result {other:?}"),
+                    }
+                }
+                responses
+            }
+        };
+
+        let for_existing = attempts(existing).await;
+        let for_unknown = attempts(unique_email("unknown")).await;
+        assert_eq!(for_existing, for_unknown);
+        assert_eq!(for_unknown.last().unwrap().0, 429);
     }
 }
@@ -0,0 +1,637 @@
+use actix_web::web::Bytes;
+use futures_util::{Stream, StreamExt};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::fmt::Display;
+use uuid::Uuid;
+use validator::ValidationError;
+
+use crate::{
+    errors::{ServiceError, ServiceResult},
+    models::{
+        auth_user::{AuthUser, AUTH_USER_COLUMNS},
+        bulk_user::{
+            BulkFormat, ExportedUser, ImportReport, ImportRow, RecordSplitter,
+            MAX_IMPORT_RECORD_LENGTH,
+        },
+        email::{email_identity_key, normalize_email},
+        pagination::PageCursor,
+        profile::{validate_display_name, validate_locale, validate_timezone},
+        username::validate_username,
+    },
+    services::password_hasher::{is_importable_hash, UNUSABLE_PASSWORD_HASH},
+};
+
+// Users inserted per transaction during an import
+const IMPORT_BATCH_SIZE: usize = 500;
+
+// Users read per query during an export
+const EXPORT_PAGE_SIZE: i64 = 1000;
+
+const EMAIL_MAX_LENGTH: usize = 255;
+
+/// Bulk import and export of users, e.g. to migrate from another system.
+///
+/// Both directions stream: imports are parsed as the upload arrives and exports are written
+/// page by page, so neither holds the whole file in memory.
+#[derive(Clone)]
+pub struct BulkUserService {
+    db_pool: Pool<Postgres>,
+}
+
+impl BulkUserService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Import users from a CSV or JSON Lines upload.
+    ///
+    /// Invalid rows are reported and skipped; valid rows are inserted in batches of
+    /// `IMPORT_BATCH_SIZE`, each batch in its own transaction. Existing users are never
+    /// modified, so an interrupted import can be retried with the same file.
+    pub async fn import<S, E>(
+        &self,
+        format: BulkFormat,
+        dry_run: bool,
+        mut body: S,
+    ) -> ServiceResult<ImportReport>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let mut import = Import::new(format, dry_run);
+        let mut splitter = RecordSplitter::new(format);
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk
+                .map_err(|e| ServiceError::BadRequest(format!("Failed to read upload: {e}")))?;
+            let records = splitter.push(&chunk).map_err(|line| {
+                ServiceError::BadRequest(format!(
+                    "Record on line {line} is longer than {MAX_IMPORT_RECORD_LENGTH} bytes"
+                ))
+            })?;
+
+            for (line, record) in records {
+                import.add_record(line, &record)?;
+                if import.batch.len() >= IMPORT_BATCH_SIZE {
+                    self.insert_batch(&mut import).await?;
+                }
+            }
+        }
+
+        if let Some((line, record)) = splitter.finish() {
+            import.add_record(line, &record)?;
+        }
+        self.insert_batch(&mut import).await?;
+
+        // Conflicts are only found when a batch is inserted, after later rows were parsed
+        import.report.errors.sort_by_key(|error| error.line);
+
+        log::info!(
+            "Imported {} of {} users ({} failed{})",
+            import.report.imported,
+            import.report.rows,
+            import.report.failed,
+            if dry_run { ", dry run" } else { "" }
+        );
+
+        Ok(import.report)
+    }
+
+    /// Stream all users, oldest first, as CSV or JSON Lines without any secrets.
+    ///
+    /// Pages are read one at a time, so users created during the export may or may not be
+    /// included.
+    pub fn export(&self, format: BulkFormat) -> impl Stream<Item = ServiceResult<Bytes>> {
+        let service = self.clone();
+
+        futures_util::stream::try_unfold(ExportState::default(), move |state: ExportState| {
+            let service = service.clone();
+            async move {
+                if state.done {
+                    return Ok(None);
+                }
+
+                let users = service.export_page(state.cursor.as_ref()).await?;
+                let next = ExportState {
+                    cursor: users.last().map(|user| PageCursor {
+                        created_at: user.created_at,
+                        id: user.id,
+                    }),
+                    header_written: state.header_written || !users.is_empty(),
+                    done: (users.len() as i64) < EXPORT_PAGE_SIZE,
+                };
+
+                let chunk = encode_users(format, users, !state.header_written)?;
+                Ok(Some((chunk, next)))
+            }
+        })
+    }
+
+    async fn export_page(&self, after: Option<&PageCursor>) -> ServiceResult<Vec<AuthUser>> {
+        let users = sqlx::query_as::<_, AuthUser>(&format!(
+            r#"
+            SELECT {AUTH_USER_COLUMNS} FROM auth_users
+            WHERE ($1::TIMESTAMPTZ IS NULL OR (created_at, id) > ($1, $2))
+            ORDER BY created_at, id
+            LIMIT $3
+            "#
+        ))
+        .bind(after.map(|cursor| cursor.created_at))
+        .bind(after.map(|cursor| cursor.id))
+        .bind(EXPORT_PAGE_SIZE)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    // Insert the pending batch, reporting rows that conflict with existing users
+    async fn insert_batch(&self, import: &mut Import) -> ServiceResult<()> {
+        if import.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut import.batch);
+
+        let mut tx = self.db_pool.begin().await?;
+        let inserted: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO auth_users (
+                id, email, password_hash, is_active, is_verified, created_at, updated_at,
+                password_changed_at, must_change_password, username, display_name, locale,
+                timezone
+            )
+            SELECT * FROM UNNEST(
+                $1::UUID[], $2::TEXT[], $3::TEXT[], $4::BOOLEAN[], $5::BOOLEAN[],
+                $6::TIMESTAMPTZ[], $7::TIMESTAMPTZ[], $8::TIMESTAMPTZ[], $9::BOOLEAN[],
+                $10::TEXT[], $11::TEXT[], $12::TEXT[], $13::TEXT[]
+            )
+            ON CONFLICT DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(column(&batch, |user| user.id))
+        .bind(column(&batch, |user| user.email.clone()))
+        .bind(column(&batch, |user| user.password_hash.clone()))
+        .bind(column(&batch, |user| user.is_active))
+        .bind(column(&batch, |user| user.is_verified))
+        .bind(column(&batch, |user| user.created_at))
+        .bind(column(&batch, |user| user.updated_at))
+        .bind(column(&batch, |user| user.password_changed_at))
+        .bind(column(&batch, |user| user.must_change_password))
+        .bind(column(&batch, |user| user.username.clone()))
+        .bind(column(&batch, |user| user.display_name.clone()))
+        .bind(column(&batch, |user| user.locale.clone()))
+        .bind(column(&batch, |user| user.timezone.clone()))
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let rejected: Vec<&(u64, AuthUser)> = batch
+            .iter()
+            .filter(|(_, user)| !inserted.contains(&user.id))
+            .collect();
+        if !rejected.is_empty() {
+            // Duplicates within the file were caught while parsing, so these already exist
+            let emails: Vec<String> = rejected
+                .iter()
+                .map(|(_, user)| user.email.to_lowercase())
+                .collect();
+            let existing: Vec<String> = sqlx::query_scalar(
+                "SELECT LOWER(email) FROM auth_users WHERE LOWER(email) = ANY($1)",
+            )
+            .bind(&emails)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for (line, user) in rejected {
+                let error = if existing.contains(&user.email.to_lowercase()) {
+                    ServiceError::UserAlreadyExists
+                } else {
+                    ServiceError::UsernameTaken
+                };
+                import
+                    .report
+                    .record_error(*line, Some(user.email.clone()), error.to_string());
+            }
+        }
+
+        if import.report.dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        import.report.imported += inserted.len() as u64;
+
+        Ok(())
+    }
+}
+
+// Progress of one import
+struct Import {
+    format: BulkFormat,
+    csv_headers: Option<csv::StringRecord>,
+    batch: Vec<(u64, AuthUser)>,
+    // First line of each email and username, to reject duplicates within the file
+    emails: HashMap<String, u64>,
+    usernames: HashMap<String, u64>,
+    report: ImportReport,
+}
+
+impl Import {
+    fn new(format: BulkFormat, dry_run: bool) -> Self {
+        Self {
+            format,
+            csv_headers: None,
+            batch: Vec::with_capacity(IMPORT_BATCH_SIZE),
+            emails: HashMap::new(),
+            usernames: HashMap::new(),
+            report: ImportReport {
+                dry_run,
+                ..Default::default()
+            },
+        }
+    }
+
+    // Parse and validate a record, queueing the user for insertion or reporting the error.
+    // Only a malformed CSV header fails the whole import.
+    fn add_record(&mut self, line: u64, record: &[u8]) -> ServiceResult<()> {
+        if self.format == BulkFormat::Csv && self.csv_headers.is_none() {
+            let headers = parse_csv_record(record)
+                .map_err(|e| ServiceError::BadRequest(format!("Invalid CSV header: {e}")))?;
+            let headers: csv::StringRecord = headers.iter().map(str::trim).collect();
+            if !headers.iter().any(|header| header == "email") {
+                return Err(ServiceError::BadRequest(
+                    "CSV header must include an email column".to_string(),
+                ));
+            }
+            self.csv_headers = Some(headers);
+            return Ok(());
+        }
+
+        self.report.rows += 1;
+        let row = match self.parse_row(record) {
+            Ok(row) => row,
+            Err(error) => {
+                self.report.record_error(line, None, error);
+                return Ok(());
+            }
+        };
+
+        let email = row.email.trim().to_string();
+        let user = match user_from_row(row) {
+            Ok(user) => user,
+            Err(error) => {
+                self.report.record_error(line, Some(email), error);
+                return Ok(());
+            }
+        };
+
+        if let Some(first) = self.emails.get(&email_identity_key(&user.email)) {
+            let error = format!("Email already appears on line {first}");
+            self.report.record_error(line, Some(user.email), error);
+            return Ok(());
+        }
+        let username_key = user.username.as_ref().map(|name| name.to_lowercase());
+        if let Some(first) = username_key
+            .as_ref()
+            .and_then(|key| self.usernames.get(key))
+        {
+            let error = format!("Username already appears on line {first}");
+            self.report.record_error(line, Some(user.email), error);
+            return Ok(());
+        }
+
+        self.emails.insert(email_identity_key(&user.email), line);
+        if let Some(key) = username_key {
+            self.usernames.insert(key, line);
+        }
+        self.batch.push((line, user));
+
+        Ok(())
+    }
+
+    fn parse_row(&self, record: &[u8]) -> Result<ImportRow, String> {
+        match &self.csv_headers {
+            Some(headers) => {
+                let fields = parse_csv_record(record).map_err(|e| e.to_string())?;
+                if fields.len() != headers.len() {
+                    return Err(format!(
+                        "Expected {} columns, found {}",
+                        headers.len(),
+                        fields.len()
+                    ));
+                }
+                fields
+                    .deserialize(Some(headers))
+                    .map_err(|e| format!("Invalid row: {e}"))
+            }
+            None => serde_json::from_slice(record).map_err(|e| format!("Invalid JSON: {e}")),
+        }
+    }
+}
+
+// One column of the batch, as an array for UNNEST
+fn column<T>(batch: &[(u64, AuthUser)], value: impl Fn(&AuthUser) -> T) -> Vec<T> {
+    batch.iter().map(|(_, user)| value(user)).collect()
+}
+
+fn parse_csv_record(record: &[u8]) -> Result<csv::StringRecord, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(record);
+    let mut fields = csv::StringRecord::new();
+    reader.read_record(&mut fields)?;
+    Ok(fields)
+}
+
+/// Validate an import row the way registration and the profile endpoints would, returning
+/// the user to insert or the reason the row was rejected.
+///
+/// Rows without a password hash become accounts that must reset their password: they get
+/// an unusable hash and `must_change_password`.
+fn user_from_row(row: ImportRow) -> Result<AuthUser, String> {
+    let email = normalize_email(&row.email);
+    if email.len() > EMAIL_MAX_LENGTH || !validator::validate_email(&email) {
+        return Err("Invalid email format".to_string());
+    }
+
+    let password_hash = match non_empty(row.password_hash) {
+        Some(hash) if is_importable_hash(&hash) => Some(hash),
+        Some(_) => return Err("Password hash is not in a supported format".to_string()),
+        None => None,
+    };
+
+    let username = non_empty(row.username);
+    let display_name = non_empty(row.display_name);
+    let locale = non_empty(row.locale);
+    let timezone = non_empty(row.timezone);
+    check(username.as_deref(), validate_username)?;
+    check(display_name.as_deref(), validate_display_name)?;
+    check(locale.as_deref(), validate_locale)?;
+    check(timezone.as_deref(), validate_timezone)?;
+
+    let must_change_password = password_hash.is_none();
+    let mut user = AuthUser::new(
+        email,
+        password_hash.unwrap_or_else(|| UNUSABLE_PASSWORD_HASH.to_string()),
+    );
+    user.must_change_password = must_change_password;
+    user.username = username;
+    user.display_name = display_name;
+    user.locale = locale;
+    user.timezone = timezone;
+    user.is_verified = row.is_verified.unwrap_or(false);
+    if let Some(created_at) = row.created_at {
+        user.created_at = created_at;
+    }
+
+    Ok(user)
+}
+
+// Trim the value, treating empty CSV cells and blank strings as missing
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn check(
+    value: Option<&str>,
+    validate: fn(&str) -> Result<(), ValidationError>,
+) -> Result<(), String> {
+    match value.map(validate) {
+        Some(Err(error)) => Err(error
+            .message
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| error.code.to_string())),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Default)]
+struct ExportState {
+    cursor: Option<PageCursor>,
+    header_written: bool,
+    done: bool,
+}
+
+fn encode_users(
+    format: BulkFormat,
+    users: Vec<AuthUser>,
+    with_header: bool,
+) -> ServiceResult<Bytes> {
+    let mut buffer = Vec::new();
+
+    match format {
+        BulkFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(with_header)
+                .from_writer(&mut buffer);
+            for user in users {
+                writer
+                    .serialize(ExportedUser::from(user))
+                    .map_err(|_| ServiceError::InternalError)?;
+            }
+            writer.flush().map_err(|_| ServiceError::InternalError)?;
+        }
+        BulkFormat::Jsonl => {
+            for user in users {
+                serde_json::to_writer(&mut buffer, &ExportedUser::from(user))
+                    .map_err(|_| ServiceError::InternalError)?;
+                buffer.push(b'\n');
+            }
+        }
+    }
+
+    Ok(Bytes::from(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_pool, unique_email};
+
+    fn row(email: &str) -> ImportRow {
+        ImportRow {
+            email: email.to_string(),
+            password_hash: None,
+            username: None,
+            display_name: None,
+            locale: None,
+            timezone: None,
+            is_verified: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_row_without_hash_must_reset_password() {
+        let user = user_from_row(row(" Alice@Example.COM ")).unwrap();
+        assert_eq!(user.email, "Alice@example.com");
+        assert_eq!(user.password_hash, UNUSABLE_PASSWORD_HASH);
+        assert!(user.must_change_password);
+        assert!(!user.is_verified);
+    }
+
+    #[test]
+    fn test_row_validation() {
+        let mut with_hash = row("bob@example.com");
+        with_hash.password_hash = Some(bcrypt::hash("Quartz!Lantern7&Meadow", 4).unwrap());
+        with_hash.username = Some(" bob ".to_string());
+        with_hash.is_verified = Some(true);
+        let user = user_from_row(with_hash).unwrap();
+        assert!(!user.must_change_password);
+        assert_eq!(user.username.as_deref(), Some("bob"));
+        assert!(user.is_verified);
+
+        assert!(user_from_row(row("not-an-email")).is_err());
+
+        let mut plaintext = row("carol@example.com");
+        plaintext.password_hash = Some("hunter2".to_string());
+        assert!(user_from_row(plaintext).is_err());
+
+        let mut reserved = row("dave@example.com");
+        reserved.username = Some("admin".to_string());
+        assert_eq!(user_from_row(reserved).unwrap_err(), "Username is reserved");
+
+        let mut timezone = row("erin@example.com");
+        timezone.timezone = Some("Mars/Olympus_Mons".to_string());
+        assert!(user_from_row(timezone).is_err());
+    }
+
+    #[test]
+    fn test_csv_rows_are_parsed_against_the_header() {
+        let mut import = Import::new(BulkFormat::Csv, true);
+        import
+            .add_record(1, b"email,username,is_verified,unknown_column")
+            .unwrap();
+        import
+            .add_record(2, b"a@example.com,alice,true,ignored")
+            .unwrap();
+        import.add_record(3, b"A@EXAMPLE.COM,,false,x").unwrap();
+        import.add_record(4, b"c@example.com,carol").unwrap();
+        import.add_record(5, b"d@example.com,,maybe,x").unwrap();
+
+        assert_eq!(import.batch.len(), 1);
+        assert!(import.batch[0].1.is_verified);
+        assert_eq!(import.report.rows, 4);
+        assert_eq!(import.report.failed, 3);
+        let errors: Vec<(u64, &str)> = import
+            .report
+            .errors
+            .iter()
+            .map(|error| (error.line, error.error.as_str()))
+            .collect();
+        assert_eq!(errors[0], (3, "Email already appears on line 2"));
+        assert_eq!(errors[1], (4, "Expected 4 columns, found 2"));
+        assert_eq!(errors[2].0, 5);
+    }
+
+    #[test]
+    fn test_csv_header_requires_email() {
+        let mut import = Import::new(BulkFormat::Csv, false);
+        assert!(matches!(
+            import.add_record(1, b"mail,username"),
+            Err(ServiceError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_jsonl_rows() {
+        let mut import = Import::new(BulkFormat::Jsonl, false);
+        import
+            .add_record(1, br#"{"email":"a@example.com","display_name":"A"}"#)
+            .unwrap();
+        import.add_record(2, b"{not json").unwrap();
+        import
+            .add_record(3, br#"{"email":"b@example.com","username":"ALICE2"}"#)
+            .unwrap();
+        import
+            .add_record(4, br#"{"email":"c@example.com","username":"alice2"}"#)
+            .unwrap();
+
+        assert_eq!(import.batch.len(), 2);
+        assert_eq!(import.report.failed, 2);
+        assert_eq!(import.report.errors[0].line, 2);
+        assert_eq!(
+            import.report.errors[1].error,
+            "Username already appears on line 3"
+        );
+    }
+
+    #[test]
+    fn test_export_encoding() {
+        let user = AuthUser::new("a@example.com".to_string(), "$2b$secret".to_string());
+
+        let csv = encode_users(BulkFormat::Csv, vec![user.clone()], true).unwrap();
+        let csv = String::from_utf8(csv.to_vec()).unwrap();
+        assert!(csv.starts_with("id,email,username,"));
+        assert!(!csv.contains("secret"));
+
+        let csv = encode_users(BulkFormat::Csv, vec![user.clone()], false).unwrap();
+        assert!(!String::from_utf8(csv.to_vec()).unwrap().starts_with("id,"));
+
+        let jsonl = encode_users(BulkFormat::Jsonl, vec![user.clone(), user], false).unwrap();
+        let jsonl = String::from_utf8(jsonl.to_vec()).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        assert!(!jsonl.contains("secret"));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_import_then_export() {
+        let pool = test_pool().await;
+        let service = BulkUserService::new(pool.clone());
+        let imported = unique_email("imported");
+        let reset = unique_email("reset");
+
+        let hash = bcrypt::hash("Quartz!Lantern7&Meadow", 4).unwrap();
+        let file = format!("email,password_hash\n{imported},{hash}\n{reset},\n");
+        let body = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from(file))]);
+        let report = service.import(BulkFormat::Csv, false, body).await.unwrap();
+        assert_eq!(report.imported, 2);
+
+        let users = sqlx::query_as::<_, AuthUser>(&format!(
+            "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE email = ANY($1) ORDER BY email"
+        ))
+        .bind([imported.clone(), reset.clone()])
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].email, imported);
+        assert_eq!(users[0].password_hash, hash);
+        assert!(!users[0].must_change_password);
+        assert_eq!(users[1].password_hash, UNUSABLE_PASSWORD_HASH);
+        assert!(users[1].must_change_password);
+
+        // Importing again reports the existing users instead of changing them
+        let file = format!("email,username\n{imported},changed\n");
+        let body = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from(file))]);
+        let report = service.import(BulkFormat::Csv, false, body).await.unwrap();
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.errors[0].error, "User already exists");
+
+        // A dry run validates without writing
+        let dry_run = unique_email("dry-run");
+        let body = futures_util::stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from(format!(
+            "email\n{dry_run}\n"
+        )))]);
+        service.import(BulkFormat::Csv, true, body).await.unwrap();
+
+        let chunks: Vec<Bytes> = service
+            .export(BulkFormat::Jsonl)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let export = String::from_utf8(chunks.concat()).unwrap();
+        let exported = export
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|user| user["email"] == imported.as_str())
+            .unwrap();
+        assert!(exported["username"].is_null());
+        assert!(!export.contains(&hash));
+        assert!(!export.contains(&dry_run));
+    }
+}
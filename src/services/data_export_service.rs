@@ -0,0 +1,335 @@
+use chrono::{Duration, Utc};
+use sqlx::{types::Json, PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    models::{
+        auth_user::{AuthUser, AUTH_USER_COLUMNS},
+        data_export::{
+            verify_download_signature, AccountData, DataExport, DataExportInfo, DataExportStatus,
+            DownloadQuery, EmailRecord, PersonalDataArchive, SessionRecord, DATA_EXPORT_COLUMNS,
+        },
+        login_event::{LoginEvent, LOGIN_EVENT_COLUMNS},
+    },
+};
+
+/// Personal data exports, generated in the background by `DataExportWorker`
+#[derive(Clone)]
+pub struct DataExportService {
+    db_pool: Pool<Postgres>,
+}
+
+impl DataExportService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Queue an export, or return the user's pending or still downloadable one
+    pub async fn request_export(&self, user_id: Uuid) -> ServiceResult<DataExportInfo> {
+        let existing = sqlx::query_as::<_, DataExport>(&format!(
+            r#"
+            SELECT {DATA_EXPORT_COLUMNS} FROM data_exports
+            WHERE user_id = $1 AND status IN ($2, $3) AND expires_at > NOW()
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#
+        ))
+        .bind(user_id)
+        .bind(DataExportStatus::Pending.as_str())
+        .bind(DataExportStatus::Ready.as_str())
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        if let Some(export) = existing {
+            return Ok(DataExportInfo::from(export));
+        }
+
+        // Pending exports expire too, so a stuck export does not block new requests forever
+        let export = sqlx::query_as::<_, DataExport>(&format!(
+            r#"
+            INSERT INTO data_exports (id, user_id, status, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {DATA_EXPORT_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(DataExportStatus::Pending.as_str())
+        .bind(Utc::now() + Duration::seconds(CONFIG.data_export_expiration))
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        log::info!("User {user_id} requested a data export");
+
+        Ok(DataExportInfo::from(export))
+    }
+
+    /// Status of one of the user's exports, with a signed download link once it is ready
+    pub async fn get_export(
+        &self,
+        user_id: Uuid,
+        export_id: Uuid,
+    ) -> ServiceResult<DataExportInfo> {
+        let export = sqlx::query_as::<_, DataExport>(&format!(
+            "SELECT {DATA_EXPORT_COLUMNS} FROM data_exports WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(export_id)
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(DataExportInfo::from(export))
+    }
+
+    /// Archive behind a signed download link; the link is the only credential
+    pub async fn download(
+        &self,
+        export_id: Uuid,
+        query: &DownloadQuery,
+    ) -> ServiceResult<serde_json::Value> {
+        if !verify_download_signature(export_id, query.expires, &query.signature) {
+            return Err(ServiceError::InvalidToken);
+        }
+
+        let archive: Option<Json<serde_json::Value>> = sqlx::query_scalar(
+            r#"
+            SELECT archive FROM data_exports
+            WHERE id = $1 AND status = $2 AND expires_at > NOW()
+            "#,
+        )
+        .bind(export_id)
+        .bind(DataExportStatus::Ready.as_str())
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        archive
+            .map(|archive| archive.0)
+            .ok_or(ServiceError::NotFound)
+    }
+
+    /// Generate one pending export, returning whether there was one to generate
+    pub async fn generate_next(&self) -> ServiceResult<bool> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let export = sqlx::query_as::<_, DataExport>(&format!(
+            r#"
+            SELECT {DATA_EXPORT_COLUMNS} FROM data_exports
+            WHERE status = $1 AND expires_at > NOW()
+            ORDER BY created_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#
+        ))
+        .bind(DataExportStatus::Pending.as_str())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(export) = export else {
+            return Ok(false);
+        };
+
+        let archive = build_archive(&mut tx, export.user_id)
+            .await
+            .and_then(|archive| {
+                serde_json::to_value(archive).map_err(|_| ServiceError::InternalError)
+            });
+
+        let archive = match archive {
+            Ok(archive) => archive,
+            Err(e) => {
+                // The transaction may be aborted, so record the failure outside of it
+                tx.rollback().await?;
+                log::error!("Data export {} failed: {e}", export.id);
+                sqlx::query(
+                    r#"
+                    UPDATE data_exports
+                    SET status = $1, error = $2, completed_at = NOW()
+                    WHERE id = $3
+                    "#,
+                )
+                .bind(DataExportStatus::Failed.as_str())
+                .bind(e.to_string())
+                .bind(export.id)
+                .execute(&self.db_pool)
+                .await?;
+                return Ok(true);
+            }
+        };
+
+        // The download window starts once the archive is ready
+        sqlx::query(
+            r#"
+            UPDATE data_exports
+            SET status = $1, archive = $2, completed_at = NOW(), expires_at = $3
+            WHERE id = $4
+            "#,
+        )
+        .bind(DataExportStatus::Ready.as_str())
+        .bind(Json(archive))
+        .bind(Utc::now() + Duration::seconds(CONFIG.data_export_expiration))
+        .bind(export.id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Remove expired exports together with their archives
+    pub async fn delete_expired(&self) -> ServiceResult<u64> {
+        let result = sqlx::query("DELETE FROM data_exports WHERE expires_at <= NOW()")
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Collect everything stored about the user, leaving out secrets
+async fn build_archive(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> ServiceResult<PersonalDataArchive> {
+    let user = sqlx::query_as::<_, AuthUser>(&format!(
+        "SELECT {AUTH_USER_COLUMNS} FROM auth_users WHERE id = $1"
+    ))
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // Previous password hashes are secrets; only when passwords changed is exported
+    let password_changes = sqlx::query_scalar(
+        "SELECT created_at FROM password_history WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let addresses: Vec<String> = std::iter::once(user.email.to_lowercase())
+        .chain(user.pending_email.as_deref().map(str::to_lowercase))
+        .collect();
+    let emails = sqlx::query_as::<_, EmailRecord>(
+        r#"
+        SELECT kind, recipient, status, created_at, sent_at FROM outbox
+        WHERE LOWER(recipient) = ANY($1)
+        ORDER BY created_at
+        "#,
+    )
+    .bind(&addresses)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let login_history = sqlx::query_as::<_, LoginEvent>(&format!(
+        "SELECT {LOGIN_EVENT_COLUMNS} FROM login_events WHERE user_id = $1 ORDER BY created_at"
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let sessions = sqlx::query_as::<_, SessionRecord>(
+        r#"
+        SELECT id, device, ip_address, user_agent, created_at, last_seen_at, revoked_at
+        FROM user_sessions WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(PersonalDataArchive {
+        generated_at: Utc::now(),
+        account: AccountData::from(user),
+        password_changes,
+        emails,
+        login_history,
+        sessions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            data_export::download_signature, request_context::RequestContext, session::UserSession,
+        },
+        services::session_service::SessionService,
+        test_support::{test_pool, unique_email},
+    };
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_export_is_generated_and_downloadable() {
+        let pool = test_pool().await;
+        let exports = DataExportService::new(pool.clone());
+
+        let user = AuthUser::new(unique_email("export"), "hash".to_string());
+        sqlx::query("INSERT INTO auth_users (id, email, password_hash) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(&user.email)
+            .bind(&user.password_hash)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.7".to_string()),
+            ..Default::default()
+        };
+        let session = UserSession::new(user.id, &ctx);
+        let mut conn = pool.acquire().await.unwrap();
+        SessionService::create_session(&mut conn, &session)
+            .await
+            .unwrap();
+
+        let requested = exports.request_export(user.id).await.unwrap();
+        assert_eq!(requested.status, "pending");
+        assert!(requested.download_url.is_none());
+
+        // A second request returns the same export
+        let again = exports.request_export(user.id).await.unwrap();
+        assert_eq!(again.id, requested.id);
+
+        // Other tests' exports may be generated first
+        while exports
+            .get_export(user.id, requested.id)
+            .await
+            .unwrap()
+            .status
+            == "pending"
+        {
+            assert!(exports.generate_next().await.unwrap());
+        }
+        let ready = exports.get_export(user.id, requested.id).await.unwrap();
+        assert_eq!(ready.status, "ready");
+        assert!(ready.download_url.is_some());
+
+        let export: DataExport = sqlx::query_as(&format!(
+            "SELECT {DATA_EXPORT_COLUMNS} FROM data_exports WHERE id = $1"
+        ))
+        .bind(requested.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let expires = export.expires_at.timestamp();
+        let query = DownloadQuery {
+            expires,
+            signature: download_signature(export.id, expires),
+        };
+        let archive = exports.download(export.id, &query).await.unwrap();
+        assert_eq!(archive["account"]["email"], user.email);
+        assert_eq!(archive["sessions"][0]["id"], session.id.to_string());
+        assert_eq!(archive["sessions"][0]["ip_address"], "203.0.113.7");
+
+        let forged = DownloadQuery {
+            expires: expires + 1,
+            ..query
+        };
+        assert!(matches!(
+            exports.download(export.id, &forged).await,
+            Err(ServiceError::InvalidToken)
+        ));
+    }
+}
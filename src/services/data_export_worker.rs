@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::{config::CONFIG, services::data_export_service::DataExportService};
+
+/// Background task that generates requested data exports and removes expired ones
+pub struct DataExportWorker {
+    exports: DataExportService,
+}
+
+impl DataExportWorker {
+    pub fn new(exports: DataExportService) -> Self {
+        Self { exports }
+    }
+
+    /// Poll every `DATA_EXPORT_POLL_INTERVAL` seconds for the lifetime of the process
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(CONFIG.data_export_poll_interval));
+            loop {
+                interval.tick().await;
+
+                // Exports are generated one at a time; keep going while there are more
+                loop {
+                    match self.exports.generate_next().await {
+                        Ok(true) => continue,
+                        Ok(false) => break,
+                        Err(e) => {
+                            log::error!("Data export generation failed: {e}");
+                            break;
+                        }
+                    }
+                }
+
+                match self.exports.delete_expired().await {
+                    Ok(0) => {}
+                    Ok(count) => log::info!("Deleted {count} expired data exports"),
+                    Err(e) => log::error!("Deleting expired data exports failed: {e}"),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,311 @@
+use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    errors::{ServiceError, ServiceResult},
+    models::token::{constant_time_eq, to_hex},
+};
+
+/// Verifies passwords against a hash format this service no longer creates, such as hashes
+/// imported from a previous system.
+///
+/// Legacy hashes are only ever verified: once a user logs in with one, `needs_rehash` asks
+/// for it to be replaced with a current hash. To support another format, implement this
+/// trait and add the verifier to `LEGACY_VERIFIERS`.
+pub trait LegacyHashVerifier: Send + Sync {
+    /// Prefix identifying the format, e.g. `$1$` for MD5-crypt
+    fn prefix(&self) -> &'static str;
+
+    /// Whether the hash is well-formed, checked before hashes are imported
+    fn is_valid(&self, hash: &str) -> bool;
+
+    fn verify(&self, password: &str, hash: &str) -> ServiceResult<bool>;
+}
+
+static LEGACY_VERIFIERS: Lazy<Vec<Box<dyn LegacyHashVerifier>>> = Lazy::new(|| {
+    vec![
+        Box::new(Argon2Verifier),
+        Box::new(Pbkdf2Sha256Verifier),
+        Box::new(SaltedSha256Verifier),
+        Box::new(Md5CryptVerifier),
+    ]
+});
+
+/// The verifier for a stored hash, if it uses a legacy format
+pub fn legacy_verifier(hash: &str) -> Option<&'static dyn LegacyHashVerifier> {
+    LEGACY_VERIFIERS
+        .iter()
+        .find(|verifier| hash.starts_with(verifier.prefix()))
+        .map(|verifier| verifier.as_ref())
+}
+
+// PBKDF2 iteration counts above this are rejected rather than run on login
+const PBKDF2_MAX_ITERATIONS: u32 = 10_000_000;
+
+const MD5_CRYPT_PREFIX: &str = "$1$";
+
+// Alphabet of the base64 variant used by crypt(3)
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Argon2 (i, d or id) in PHC string format: `$argon2id$v=19$m=...,t=...,p=...$salt$hash`
+struct Argon2Verifier;
+
+impl LegacyHashVerifier for Argon2Verifier {
+    fn prefix(&self) -> &'static str {
+        "$argon2"
+    }
+
+    fn is_valid(&self, hash: &str) -> bool {
+        // The PHC format allows leaving out the salt and digest, which we need to verify
+        PasswordHash::new(hash).is_ok_and(|hash| hash.salt.is_some() && hash.hash.is_some())
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> ServiceResult<bool> {
+        let hash = PasswordHash::new(hash).map_err(|_| ServiceError::PasswordHashError)?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 as stored by Django: `pbkdf2_sha256$<iterations>$<salt>$<base64 hash>`
+struct Pbkdf2Sha256Verifier;
+
+impl Pbkdf2Sha256Verifier {
+    fn parse(hash: &str) -> Option<(u32, &str, Vec<u8>)> {
+        let mut parts = hash.strip_prefix("pbkdf2_sha256$")?.split('$');
+        let iterations = parts.next()?.parse().ok()?;
+        let salt = parts.next()?;
+        let digest = STANDARD.decode(parts.next()?).ok()?;
+
+        let valid = parts.next().is_none()
+            && (1..=PBKDF2_MAX_ITERATIONS).contains(&iterations)
+            && !salt.is_empty()
+            && !digest.is_empty();
+        valid.then_some((iterations, salt, digest))
+    }
+}
+
+impl LegacyHashVerifier for Pbkdf2Sha256Verifier {
+    fn prefix(&self) -> &'static str {
+        "pbkdf2_sha256$"
+    }
+
+    fn is_valid(&self, hash: &str) -> bool {
+        Self::parse(hash).is_some()
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> ServiceResult<bool> {
+        let (iterations, salt, expected) =
+            Self::parse(hash).ok_or(ServiceError::PasswordHashError)?;
+
+        let mut computed = vec![0u8; expected.len()];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            password.as_bytes(),
+            salt.as_bytes(),
+            iterations,
+            &mut computed,
+        );
+        Ok(constant_time_eq(&computed, &expected))
+    }
+}
+
+/// Single-round salted SHA-256: `sha256$<salt>$<hex of SHA-256(salt || password)>`
+struct SaltedSha256Verifier;
+
+impl SaltedSha256Verifier {
+    fn parse(hash: &str) -> Option<(&str, &str)> {
+        let (salt, digest) = hash.strip_prefix("sha256$")?.split_once('$')?;
+        let valid = !salt.is_empty()
+            && digest.len() == 64
+            && digest.bytes().all(|byte| byte.is_ascii_hexdigit());
+        valid.then_some((salt, digest))
+    }
+}
+
+impl LegacyHashVerifier for SaltedSha256Verifier {
+    fn prefix(&self) -> &'static str {
+        "sha256$"
+    }
+
+    fn is_valid(&self, hash: &str) -> bool {
+        Self::parse(hash).is_some()
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> ServiceResult<bool> {
+        let (salt, expected) = Self::parse(hash).ok_or(ServiceError::PasswordHashError)?;
+
+        let computed = to_hex(
+            &Sha256::new()
+                .chain_update(salt.as_bytes())
+                .chain_update(password.as_bytes())
+                .finalize(),
+        );
+        Ok(constant_time_eq(
+            computed.as_bytes(),
+            expected.to_ascii_lowercase().as_bytes(),
+        ))
+    }
+}
+
+/// MD5-crypt as produced by `crypt(3)` and `openssl passwd -1`: `$1$<salt>$<checksum>`
+struct Md5CryptVerifier;
+
+impl Md5CryptVerifier {
+    fn parse(hash: &str) -> Option<(&str, &str)> {
+        let (salt, checksum) = hash.strip_prefix(MD5_CRYPT_PREFIX)?.split_once('$')?;
+        let valid = salt.len() <= 8
+            && checksum.len() == 22
+            && checksum.bytes().all(|byte| CRYPT_ALPHABET.contains(&byte));
+        valid.then_some((salt, checksum))
+    }
+}
+
+impl LegacyHashVerifier for Md5CryptVerifier {
+    fn prefix(&self) -> &'static str {
+        MD5_CRYPT_PREFIX
+    }
+
+    fn is_valid(&self, hash: &str) -> bool {
+        Self::parse(hash).is_some()
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> ServiceResult<bool> {
+        let (salt, expected) = Self::parse(hash).ok_or(ServiceError::PasswordHashError)?;
+        let computed = md5_crypt_checksum(password.as_bytes(), salt.as_bytes());
+        Ok(constant_time_eq(computed.as_bytes(), expected.as_bytes()))
+    }
+}
+
+// The MD5-crypt algorithm from FreeBSD, returning the 22-character checksum
+fn md5_crypt_checksum(password: &[u8], salt: &[u8]) -> String {
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut context = Md5::new()
+        .chain_update(password)
+        .chain_update(MD5_CRYPT_PREFIX)
+        .chain_update(salt);
+    for chunk in password.chunks(alternate.len()) {
+        context.update(&alternate[..chunk.len()]);
+    }
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            context.update([0u8]);
+        } else {
+            context.update(&password[..1]);
+        }
+        length >>= 1;
+    }
+    let mut digest = context.finalize();
+
+    // 1000 rounds to slow down brute force, as slow as it was in 1994
+    for round in 0..1000 {
+        let mut context = Md5::new();
+        if round % 2 == 1 {
+            context.update(password);
+        } else {
+            context.update(digest);
+        }
+        if round % 3 != 0 {
+            context.update(salt);
+        }
+        if round % 7 != 0 {
+            context.update(password);
+        }
+        if round % 2 == 1 {
+            context.update(digest);
+        } else {
+            context.update(password);
+        }
+        digest = context.finalize();
+    }
+
+    let mut checksum = String::with_capacity(22);
+    let mut encode = |value: u32, chars: usize| {
+        for index in 0..chars {
+            checksum.push(CRYPT_ALPHABET[((value >> (6 * index)) & 0x3f) as usize] as char);
+        }
+    };
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        encode(
+            (u32::from(digest[a]) << 16) | (u32::from(digest[b]) << 8) | u32::from(digest[c]),
+            4,
+        );
+    }
+    encode(u32::from(digest[11]), 2);
+
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify(password: &str, hash: &str) -> bool {
+        let verifier = legacy_verifier(hash).expect("legacy hash");
+        assert!(verifier.is_valid(hash), "{hash}");
+        verifier.verify(password, hash).unwrap()
+    }
+
+    #[test]
+    fn test_md5_crypt() {
+        // openssl passwd -1 -salt 5pZSV9va password
+        let hash = "$1$5pZSV9va$azfrPr6af3Fc7dLblQXVa0";
+        assert!(verify("password", hash));
+        assert!(!verify("Password", hash));
+    }
+
+    #[test]
+    fn test_pbkdf2_sha256() {
+        // Python: base64(hashlib.pbkdf2_hmac("sha256", password, b"seasalt", 1000))
+        let hash = "pbkdf2_sha256$1000$seasalt$LZZFV79n8cMO8v4pXDRfs8Qrz2ODmVLO3I4q7JVT0ro=";
+        assert!(verify("Legacy!Pass99", hash));
+        assert!(!verify("Legacy!Pass98", hash));
+
+        let verifier = legacy_verifier(hash).unwrap();
+        assert!(!verifier.is_valid("pbkdf2_sha256$0$seasalt$LZZF"));
+        assert!(!verifier.is_valid("pbkdf2_sha256$99999999999$seasalt$LZZF"));
+    }
+
+    #[test]
+    fn test_salted_sha256() {
+        // echo -n "NaClLegacy!Pass99" | sha256sum
+        let hash = "sha256$NaCl$2e196a32c933d9f2b96e9d3d2d01d060fb4c88d762b8339fbe80960ee4170ba1";
+        assert!(verify("Legacy!Pass99", hash));
+        assert!(!verify("Legacy!Pass9", hash));
+        assert!(verify(
+            "Legacy!Pass99",
+            &hash.replace("2e196a32c9", "2E196A32C9")
+        ));
+    }
+
+    #[test]
+    fn test_malformed_legacy_hashes() {
+        for hash in [
+            "$1$toolongsalt$azfrPr6af3Fc7dLblQXVa0",
+            "sha256$NaCl$abc",
+            "pbkdf2_sha256$1000$seasalt$",
+        ] {
+            let verifier = legacy_verifier(hash).unwrap();
+            assert!(!verifier.is_valid(hash), "{hash}");
+            assert!(matches!(
+                verifier.verify("password", hash),
+                Err(ServiceError::PasswordHashError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_current_hashes_are_not_legacy() {
+        assert!(legacy_verifier("$2b$04$abcdefghijklmnopqrstuv").is_none());
+        assert!(legacy_verifier("pv2$$2b$04$abcdefghijklmnopqrstuv").is_none());
+    }
+}
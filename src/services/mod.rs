@@ -1,3 +1,31 @@
+pub mod account_purge_worker;
+pub mod admin_service;
+pub mod audit_checkpoint_worker;
+pub mod audit_service;
 pub mod auth_service;
+pub mod bulk_user_service;
+pub mod data_export_service;
+pub mod data_export_worker;
+pub mod legacy_hashes;
+pub mod notification_service;
+pub mod outbox_service;
+pub mod outbox_worker;
+pub mod password_hasher;
+pub mod session_service;
+pub mod webhook_service;
+pub mod webhook_worker;
 
+pub use account_purge_worker::*;
+pub use admin_service::*;
+pub use audit_checkpoint_worker::AuditCheckpointWorker;
+pub use audit_service::*;
 pub use auth_service::*;
+pub use bulk_user_service::*;
+pub use data_export_service::*;
+pub use data_export_worker::*;
+pub use notification_service::*;
+pub use outbox_service::*;
+pub use outbox_worker::*;
+pub use session_service::*;
+pub use webhook_service::*;
+pub use webhook_worker::*;
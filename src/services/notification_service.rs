@@ -0,0 +1,490 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    config::CONFIG,
+    errors::ServiceResult,
+    mailer::{
+        templates::{EmailTemplate, EmailTemplates},
+        EmailMessage,
+    },
+    models::{
+        auth_user::AuthUser, login_event::describe_device, login_risk::LoginChallenge,
+        request_context::RequestContext,
+    },
+    services::outbox_service::OutboxService,
+};
+
+/// Renders transactional email templates for a user and queues them in the outbox.
+///
+/// Every `queue_*` method takes the connection of the transaction that performs the
+/// related user change, so the email is recorded if and only if that change commits.
+#[derive(Clone)]
+pub struct NotificationService {
+    templates: Arc<EmailTemplates>,
+}
+
+impl NotificationService {
+    pub fn new(templates: Arc<EmailTemplates>) -> Self {
+        Self { templates }
+    }
+
+    pub async fn queue_verification_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.verification_email(user, token, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::Verification.name(), &message).await
+    }
+
+    pub async fn queue_password_reset_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.password_reset_email(user, token, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::PasswordReset.name(), &message).await
+    }
+
+    pub async fn queue_password_changed_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.password_changed_email(user, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::PasswordChanged.name(), &message).await
+    }
+
+    pub async fn queue_new_login_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.new_login_email(user, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::NewLogin.name(), &message).await
+    }
+
+    pub async fn queue_email_change_emails(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let confirmation = self.email_change_email(user, token, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::EmailChange.name(), &confirmation).await?;
+
+        let notice = self.email_change_notice_email(user, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::EmailChangeNotice.name(), &notice).await
+    }
+
+    pub async fn queue_account_deletion_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.account_deletion_email(user, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::AccountDeletion.name(), &message).await
+    }
+
+    pub async fn queue_registration_attempt_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.registration_attempt_email(user, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::RegistrationAttempt.name(), &message).await
+    }
+
+    pub async fn queue_suspicious_login_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        location: Option<&str>,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.suspicious_login_email(user, location, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::SuspiciousLogin.name(), &message).await
+    }
+
+    pub async fn queue_login_challenge_email(
+        &self,
+        conn: &mut PgConnection,
+        user: &AuthUser,
+        challenge: &LoginChallenge,
+        code: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<()> {
+        let message = self.login_challenge_email(user, challenge, code, ctx)?;
+        OutboxService::enqueue(conn, EmailTemplate::LoginChallenge.name(), &message).await
+    }
+
+    fn verification_email(
+        &self,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert(
+            "action_url",
+            action_url(&format!("/verify-email?token={token}")),
+        );
+        variables.insert("expires_at", format_time(user.verification_token_expires));
+
+        self.render(
+            EmailTemplate::Verification,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn password_reset_email(
+        &self,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert(
+            "action_url",
+            action_url(&format!("/reset-password?token={token}")),
+        );
+        variables.insert("expires_at", format_time(user.reset_token_expires));
+
+        self.render(
+            EmailTemplate::PasswordReset,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn password_changed_email(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert("action_url", action_url("/forgot-password"));
+
+        self.render(
+            EmailTemplate::PasswordChanged,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn new_login_email(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert("action_url", action_url("/forgot-password"));
+        variables.insert(
+            "ip_address",
+            ctx.ip_address
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+        );
+        variables.insert(
+            "user_agent",
+            ctx.user_agent
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+        );
+
+        self.render(EmailTemplate::NewLogin, user, &user.email, ctx, variables)
+    }
+
+    // Sent to the requested new address
+    fn email_change_email(
+        &self,
+        user: &AuthUser,
+        token: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let new_email = user.pending_email.clone().unwrap_or_default();
+        let mut variables = HashMap::new();
+        variables.insert(
+            "action_url",
+            action_url(&format!("/confirm-email-change?token={token}")),
+        );
+        variables.insert("expires_at", format_time(user.email_change_token_expires));
+        variables.insert("new_email", new_email.clone());
+
+        self.render(EmailTemplate::EmailChange, user, &new_email, ctx, variables)
+    }
+
+    // Sent to the current address
+    fn email_change_notice_email(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert("action_url", action_url("/forgot-password"));
+        variables.insert("new_email", user.pending_email.clone().unwrap_or_default());
+
+        self.render(
+            EmailTemplate::EmailChangeNotice,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn account_deletion_email(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert("action_url", action_url("/login"));
+        variables.insert("expires_at", format_time(user.deletion_scheduled_at));
+
+        self.render(
+            EmailTemplate::AccountDeletion,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    // Sent to the owner when someone registers with their address, instead of an error
+    fn registration_attempt_email(
+        &self,
+        user: &AuthUser,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = HashMap::new();
+        variables.insert("action_url", action_url("/forgot-password"));
+
+        self.render(
+            EmailTemplate::RegistrationAttempt,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn suspicious_login_email(
+        &self,
+        user: &AuthUser,
+        location: Option<&str>,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = login_variables(location, ctx);
+        variables.insert("action_url", action_url("/forgot-password"));
+
+        self.render(
+            EmailTemplate::SuspiciousLogin,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn login_challenge_email(
+        &self,
+        user: &AuthUser,
+        challenge: &LoginChallenge,
+        code: &str,
+        ctx: &RequestContext,
+    ) -> ServiceResult<EmailMessage> {
+        let mut variables = login_variables(challenge.location.as_deref(), ctx);
+        variables.insert("action_url", action_url("/forgot-password"));
+        variables.insert("code", code.to_string());
+        variables.insert("expires_at", format_time(Some(challenge.expires_at)));
+
+        self.render(
+            EmailTemplate::LoginChallenge,
+            user,
+            &user.email,
+            ctx,
+            variables,
+        )
+    }
+
+    fn render(
+        &self,
+        template: EmailTemplate,
+        user: &AuthUser,
+        to: &str,
+        ctx: &RequestContext,
+        mut variables: HashMap<&str, String>,
+    ) -> ServiceResult<EmailMessage> {
+        variables.insert("app_name", CONFIG.app_name.clone());
+        variables.insert("email", user.email.clone());
+        variables.insert("event_time", format_time(Some(Utc::now())));
+
+        // The user's stored locale wins over the request's Accept-Language
+        let preferences: Vec<String> = user
+            .locale
+            .iter()
+            .cloned()
+            .chain(ctx.preferred_locales())
+            .collect();
+
+        self.templates
+            .render(template, &preferences, to, &variables)
+    }
+}
+
+// Where a login came from, for the suspicious-login emails
+fn login_variables(location: Option<&str>, ctx: &RequestContext) -> HashMap<&'static str, String> {
+    let mut variables = HashMap::new();
+    variables.insert("device", describe_device(ctx.user_agent.as_deref()));
+    variables.insert(
+        "ip_address",
+        ctx.ip_address
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string()),
+    );
+    variables.insert("location", location.unwrap_or("unknown").to_string());
+    variables
+}
+
+fn action_url(path: &str) -> String {
+    format!("{}{path}", CONFIG.public_base_url)
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn service() -> NotificationService {
+        let dir = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/emails"));
+        let templates = EmailTemplates::load(dir, "en").unwrap();
+        NotificationService::new(Arc::new(templates))
+    }
+
+    #[test]
+    fn test_verification_email_uses_request_locale() {
+        let mut user = AuthUser::new("user@example.com".to_string(), "hash".to_string());
+        let token = user.generate_verification_token(3600);
+        let ctx = RequestContext {
+            accept_language: Some("es-ES,es;q=0.9".to_string()),
+            ..Default::default()
+        };
+
+        let message = service().verification_email(&user, &token, &ctx).unwrap();
+
+        assert_eq!(message.to, "user@example.com");
+        assert!(message.subject.starts_with("Verifica"));
+        assert!(message.text_body.contains(&format!(
+            "{}/verify-email?token={token}",
+            CONFIG.public_base_url
+        )));
+    }
+
+    #[test]
+    fn test_user_locale_takes_precedence() {
+        let mut user = AuthUser::new("user@example.com".to_string(), "hash".to_string());
+        user.locale = Some("en".to_string());
+        let ctx = RequestContext {
+            accept_language: Some("es".to_string()),
+            ip_address: Some("203.0.113.7".to_string()),
+            ..Default::default()
+        };
+
+        let message = service().new_login_email(&user, &ctx).unwrap();
+
+        assert!(message.subject.starts_with("New sign-in"));
+        assert!(message.text_body.contains("IP address: 203.0.113.7"));
+        assert!(message.text_body.contains("Device: unknown"));
+    }
+
+    #[test]
+    fn test_email_change_emails_go_to_both_addresses() {
+        let mut user = AuthUser::new("old@example.com".to_string(), "hash".to_string());
+        let token = user.generate_email_change_token("new@example.com".to_string(), 3600);
+        let ctx = RequestContext::default();
+
+        let confirmation = service().email_change_email(&user, &token, &ctx).unwrap();
+        assert_eq!(confirmation.to, "new@example.com");
+        assert!(confirmation
+            .text_body
+            .contains(&format!("/confirm-email-change?token={token}")));
+
+        let notice = service().email_change_notice_email(&user, &ctx).unwrap();
+        assert_eq!(notice.to, "old@example.com");
+        assert!(notice.text_body.contains("new@example.com"));
+        assert!(!notice.text_body.contains(&token));
+    }
+
+    #[test]
+    fn test_registration_attempt_email_goes_to_owner() {
+        let user = AuthUser::new("owner@example.com".to_string(), "hash".to_string());
+        let ctx = RequestContext {
+            accept_language: Some("es".to_string()),
+            ..Default::default()
+        };
+
+        let message = service().registration_attempt_email(&user, &ctx).unwrap();
+
+        assert_eq!(message.to, "owner@example.com");
+        assert!(message.subject.starts_with("Intento de registro"));
+        assert!(message.text_body.contains("/forgot-password"));
+    }
+
+    #[test]
+    fn test_login_challenge_email_contains_code() {
+        use crate::models::{
+            login_event::LoginMethod,
+            login_risk::{LoginAssessment, LoginChallenge},
+        };
+
+        let user = AuthUser::new("owner@example.com".to_string(), "hash".to_string());
+        let ctx = RequestContext {
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let (challenge, code) = LoginChallenge::new(
+            user.id,
+            LoginMethod::EmailPassword,
+            "device".to_string(),
+            &LoginAssessment::default(),
+            600,
+        );
+
+        let message = service()
+            .login_challenge_email(&user, &challenge, &code, &ctx)
+            .unwrap();
+
+        assert!(message.text_body.contains(&code));
+        assert!(message.text_body.contains("Firefox 128.0 on Linux"));
+        assert!(message.text_body.contains("203.0.113.7"));
+        assert!(message.html_body.unwrap().contains(&code));
+    }
+}
@@ -0,0 +1,319 @@
+use chrono::{Duration, Utc};
+use sqlx::{types::Json, PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    mailer::EmailMessage,
+    models::outbox::{
+        retry_delay, OutboxListQuery, OutboxMessage, OutboxMessageInfo, OutboxStatus,
+        OUTBOX_COLUMNS,
+    },
+};
+
+// How long a claimed message stays invisible to other workers before it is retried
+const CLAIM_LEASE_SECONDS: i64 = 300;
+
+/// Durable queue of outgoing notifications, delivered by `OutboxWorker`
+#[derive(Clone)]
+pub struct OutboxService {
+    db_pool: Pool<Postgres>,
+}
+
+impl OutboxService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Queue an email; pass the transaction that performs the related user change
+    pub async fn enqueue(
+        conn: &mut PgConnection,
+        kind: &str,
+        message: &EmailMessage,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO outbox (id, kind, recipient, payload, status, next_attempt_at)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(&message.to)
+        .bind(Json(message))
+        .bind(OutboxStatus::Pending.as_str())
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claim due messages, counting the attempt and hiding them from other workers for a lease
+    pub async fn claim_due(&self, batch_size: i64) -> ServiceResult<Vec<OutboxMessage>> {
+        let messages = sqlx::query_as::<_, OutboxMessage>(&format!(
+            r#"
+            UPDATE outbox
+            SET attempts = attempts + 1, next_attempt_at = $1, updated_at = NOW()
+            WHERE id IN (
+                SELECT id FROM outbox
+                WHERE status = $2 AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING {OUTBOX_COLUMNS}
+            "#
+        ))
+        .bind(Utc::now() + Duration::seconds(CLAIM_LEASE_SECONDS))
+        .bind(OutboxStatus::Pending.as_str())
+        .bind(batch_size)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(messages)
+    }
+
+    /// Mark a message as delivered and drop its payload, which may contain tokens
+    pub async fn mark_sent(&self, id: Uuid) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET status = $1, payload = NULL, last_error = NULL, sent_at = NOW(), updated_at = NOW()
+            WHERE id = $2
+            "#,
+        )
+        .bind(OutboxStatus::Sent.as_str())
+        .bind(id)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Schedule a retry with exponential backoff, or dead-letter the message after the last attempt
+    pub async fn mark_failed(&self, message: &OutboxMessage, error: &str) -> ServiceResult<()> {
+        let (status, next_attempt_at) = if message.attempts >= CONFIG.outbox_max_attempts {
+            (OutboxStatus::Dead, Utc::now())
+        } else {
+            let delay = retry_delay(
+                message.attempts,
+                CONFIG.outbox_retry_base,
+                CONFIG.outbox_retry_max,
+            );
+            (OutboxStatus::Pending, Utc::now() + Duration::seconds(delay))
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET status = $1, next_attempt_at = $2, last_error = $3, updated_at = NOW()
+            WHERE id = $4
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(next_attempt_at)
+        .bind(error)
+        .bind(message.id)
+        .execute(&self.db_pool)
+        .await?;
+
+        if status == OutboxStatus::Dead {
+            log::error!(
+                "Outbox message {} ({} to {}) dead-lettered after {} attempts: {error}",
+                message.id,
+                message.kind,
+                message.recipient,
+                message.attempts
+            );
+        }
+
+        Ok(())
+    }
+
+    /// List messages by status, newest first (admin)
+    pub async fn list(&self, query: OutboxListQuery) -> ServiceResult<Vec<OutboxMessageInfo>> {
+        let messages = sqlx::query_as::<_, OutboxMessage>(&format!(
+            r#"
+            SELECT {OUTBOX_COLUMNS} FROM outbox
+            WHERE status = $1
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(query.status.unwrap_or(OutboxStatus::Dead).as_str())
+        .bind(query.limit.unwrap_or(50))
+        .bind(query.offset.unwrap_or(0))
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(messages.into_iter().map(OutboxMessageInfo::from).collect())
+    }
+
+    /// Put a dead-lettered message back in the queue with a fresh set of attempts (admin)
+    pub async fn retry(&self, id: Uuid) -> ServiceResult<OutboxMessageInfo> {
+        let message = sqlx::query_as::<_, OutboxMessage>(&format!(
+            r#"
+            UPDATE outbox
+            SET status = $1, attempts = 0, next_attempt_at = NOW(), updated_at = NOW()
+            WHERE id = $2 AND status = $3
+            RETURNING {OUTBOX_COLUMNS}
+            "#
+        ))
+        .bind(OutboxStatus::Pending.as_str())
+        .bind(id)
+        .bind(OutboxStatus::Dead.as_str())
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        match message {
+            Some(message) => Ok(OutboxMessageInfo::from(message)),
+            None => {
+                let exists: bool =
+                    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM outbox WHERE id = $1)")
+                        .bind(id)
+                        .fetch_one(&self.db_pool)
+                        .await?;
+
+                if exists {
+                    Err(ServiceError::BadRequest(
+                        "Only dead-lettered messages can be retried".to_string(),
+                    ))
+                } else {
+                    Err(ServiceError::NotFound)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_pool, unique_email};
+    use tokio::sync::Mutex;
+
+    // Claiming takes every due message, so tests claiming at once would take each other's
+    static QUEUE: Mutex<()> = Mutex::const_new(());
+
+    fn message(to: &str) -> EmailMessage {
+        EmailMessage {
+            template: "verification".to_string(),
+            to: to.to_string(),
+            subject: "Hello".to_string(),
+            text_body: "Body".to_string(),
+            html_body: None,
+        }
+    }
+
+    // Enqueue a message and claim it, along with whatever else other tests left due
+    async fn enqueue_and_claim(outbox: &OutboxService, pool: &Pool<Postgres>) -> OutboxMessage {
+        let recipient = unique_email("outbox");
+        let mut conn = pool.acquire().await.unwrap();
+        OutboxService::enqueue(&mut conn, "verification", &message(&recipient))
+            .await
+            .unwrap();
+
+        outbox
+            .claim_due(1000)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|claimed| claimed.recipient == recipient)
+            .expect("enqueued message was not claimed")
+    }
+
+    async fn fetch(pool: &Pool<Postgres>, id: Uuid) -> OutboxMessage {
+        sqlx::query_as::<_, OutboxMessage>(&format!(
+            "SELECT {OUTBOX_COLUMNS} FROM outbox WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_claimed_message_is_leased_and_sent() {
+        let _queue = QUEUE.lock().await;
+        let pool = test_pool().await;
+        let outbox = OutboxService::new(pool.clone());
+
+        let claimed = enqueue_and_claim(&outbox, &pool).await;
+        assert_eq!(claimed.attempts, 1);
+        assert_eq!(claimed.payload.as_ref().unwrap().0.to, claimed.recipient);
+        assert!(claimed.next_attempt_at > Utc::now() + Duration::seconds(CLAIM_LEASE_SECONDS - 60));
+
+        // Leased messages are not handed to another worker
+        let again = outbox.claim_due(1000).await.unwrap();
+        assert!(again.iter().all(|other| other.id != claimed.id));
+
+        outbox.mark_sent(claimed.id).await.unwrap();
+        let sent = fetch(&pool, claimed.id).await;
+        assert_eq!(sent.status, "sent");
+        assert!(sent.payload.is_none());
+        assert!(sent.sent_at.is_some());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_failed_message_is_retried_with_backoff() {
+        let _queue = QUEUE.lock().await;
+        let pool = test_pool().await;
+        let outbox = OutboxService::new(pool.clone());
+
+        let claimed = enqueue_and_claim(&outbox, &pool).await;
+        let before = Utc::now();
+        outbox
+            .mark_failed(&claimed, "connection refused")
+            .await
+            .unwrap();
+
+        let failed = fetch(&pool, claimed.id).await;
+        let delay = retry_delay(1, CONFIG.outbox_retry_base, CONFIG.outbox_retry_max);
+        assert_eq!(failed.status, "pending");
+        assert_eq!(failed.last_error.as_deref(), Some("connection refused"));
+        assert!(failed.next_attempt_at >= before + Duration::seconds(delay));
+        assert!(failed.next_attempt_at <= Utc::now() + Duration::seconds(delay));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_failed_message_is_dead_lettered_and_retried() {
+        let _queue = QUEUE.lock().await;
+        let pool = test_pool().await;
+        let outbox = OutboxService::new(pool.clone());
+
+        let mut claimed = enqueue_and_claim(&outbox, &pool).await;
+        claimed.attempts = CONFIG.outbox_max_attempts;
+        outbox
+            .mark_failed(&claimed, "connection refused")
+            .await
+            .unwrap();
+
+        let dead = outbox
+            .list(OutboxListQuery {
+                status: Some(OutboxStatus::Dead),
+                limit: Some(200),
+                offset: None,
+            })
+            .await
+            .unwrap();
+        assert!(dead.iter().any(|m| m.id == claimed.id));
+
+        let retried = outbox.retry(claimed.id).await.unwrap();
+        assert_eq!(retried.status, "pending");
+        assert_eq!(retried.attempts, 0);
+
+        // Only dead-lettered messages can be retried
+        assert!(matches!(
+            outbox.retry(claimed.id).await,
+            Err(ServiceError::BadRequest(_))
+        ));
+        assert!(matches!(
+            outbox.retry(Uuid::new_v4()).await,
+            Err(ServiceError::NotFound)
+        ));
+    }
+}
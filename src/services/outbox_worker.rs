@@ -0,0 +1,71 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    mailer::Mailer,
+    services::outbox_service::OutboxService,
+};
+
+/// Background task that delivers queued outbox messages through the mailer
+pub struct OutboxWorker {
+    outbox: OutboxService,
+    mailer: Arc<dyn Mailer>,
+}
+
+impl OutboxWorker {
+    pub fn new(outbox: OutboxService, mailer: Arc<dyn Mailer>) -> Self {
+        Self { outbox, mailer }
+    }
+
+    /// Poll the outbox every `OUTBOX_POLL_INTERVAL` seconds for the lifetime of the process
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(CONFIG.outbox_poll_interval));
+            loop {
+                interval.tick().await;
+
+                // Keep draining while full batches come back
+                loop {
+                    match self.process_batch().await {
+                        Ok(count) if count as i64 >= CONFIG.outbox_batch_size => continue,
+                        Ok(_) => break,
+                        Err(e) => {
+                            log::error!("Outbox processing failed: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Deliver one batch of due messages, returning how many were claimed
+    pub async fn process_batch(&self) -> ServiceResult<usize> {
+        let messages = self.outbox.claim_due(CONFIG.outbox_batch_size).await?;
+
+        for message in &messages {
+            let result = match &message.payload {
+                Some(payload) => self.mailer.send(payload).await,
+                None => Err(ServiceError::MailerError(
+                    "Message has no payload".to_string(),
+                )),
+            };
+
+            match result {
+                Ok(()) => self.outbox.mark_sent(message.id).await?,
+                Err(e) => {
+                    log::warn!(
+                        "Delivery of outbox message {} (attempt {}) failed: {e}",
+                        message.id,
+                        message.attempts
+                    );
+                    self.outbox.mark_failed(message, &e.to_string()).await?;
+                }
+            }
+        }
+
+        Ok(messages.len())
+    }
+}
@@ -0,0 +1,251 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::Mac;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+use crate::{
+    config::{Config, CONFIG},
+    errors::{ServiceError, ServiceResult},
+    models::token::{generate_token, hmac_sha256},
+    services::legacy_hashes::legacy_verifier,
+};
+
+// Prefix of peppered hashes: "pv{version}$" followed by the bcrypt hash
+const PEPPER_PREFIX: &str = "pv";
+
+/// Stored for accounts without a password (e.g. imported ones that must reset it); no
+/// password ever verifies against it
+pub const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+// Hash of a random password with the current settings, checked when there is no real hash
+// so that rejecting unknown accounts takes as long as rejecting a wrong password
+static DUMMY_PASSWORD_HASH: Lazy<String> =
+    Lazy::new(|| hash_password(&generate_token()).expect("Failed to hash the dummy password"));
+
+/// Hashes passwords with bcrypt after applying a versioned HMAC-SHA256 pepper.
+///
+/// The pepper secret lives outside the database, so a database dump alone is not
+/// enough to attack the stored hashes offline. Hashes created without a pepper
+/// (plain bcrypt) are still verified and reported by `needs_rehash`, as are hashes in the
+/// legacy formats of `legacy_hashes` (e.g. imported from another system).
+pub struct PasswordHasher<'a> {
+    peppers: &'a HashMap<u32, String>,
+    pepper_version: u32,
+    cost: u32,
+}
+
+impl<'a> PasswordHasher<'a> {
+    pub fn new(peppers: &'a HashMap<u32, String>, pepper_version: u32, cost: u32) -> Self {
+        Self {
+            peppers,
+            pepper_version,
+            cost,
+        }
+    }
+
+    pub fn from_config(config: &'a Config) -> Self {
+        Self::new(
+            &config.password_peppers,
+            config.password_pepper_version,
+            config.bcrypt_cost,
+        )
+    }
+
+    pub fn hash(&self, password: &str) -> ServiceResult<String> {
+        if self.pepper_version == 0 {
+            return Ok(bcrypt::hash(password, self.cost)?);
+        }
+
+        let peppered = self.pepper(password, self.pepper_version)?;
+        let hash = bcrypt::hash(peppered, self.cost)?;
+        Ok(format!("{PEPPER_PREFIX}{}${hash}", self.pepper_version))
+    }
+
+    pub fn verify(&self, password: &str, stored_hash: &str) -> ServiceResult<bool> {
+        if stored_hash == UNUSABLE_PASSWORD_HASH {
+            return Ok(false);
+        }
+        if let Some(verifier) = legacy_verifier(stored_hash) {
+            return verifier.verify(password, stored_hash);
+        }
+
+        match split_pepper_version(stored_hash) {
+            Some((version, hash)) => {
+                let peppered = self.pepper(password, version)?;
+                Ok(bcrypt::verify(peppered, hash)?)
+            }
+            None => Ok(bcrypt::verify(password, stored_hash)?),
+        }
+    }
+
+    /// Whether a stored hash uses an outdated pepper version and should be upgraded
+    pub fn needs_rehash(&self, stored_hash: &str) -> bool {
+        if legacy_verifier(stored_hash).is_some() {
+            return true;
+        }
+        let version = split_pepper_version(stored_hash).map_or(0, |(version, _)| version);
+        version != self.pepper_version
+    }
+
+    fn pepper(&self, password: &str, version: u32) -> ServiceResult<String> {
+        let secret = self.peppers.get(&version).ok_or_else(|| {
+            log::error!("No password pepper configured for version {version}");
+            ServiceError::PasswordHashError
+        })?;
+
+        let mac = hmac_sha256(secret.as_bytes())
+            .chain_update(password.as_bytes())
+            .finalize();
+        // Base64 keeps the input well below bcrypt's 72-byte limit
+        Ok(STANDARD.encode(mac.into_bytes()))
+    }
+}
+
+fn split_pepper_version(stored_hash: &str) -> Option<(u32, &str)> {
+    let rest = stored_hash.strip_prefix(PEPPER_PREFIX)?;
+    let (version, hash) = rest.split_once('$')?;
+    Some((version.parse().ok()?, hash))
+}
+
+/// Whether a hash from another system can be stored as-is: bcrypt (`$2a$`, `$2b$`, `$2y$`)
+/// or a well-formed hash in one of the legacy formats, which is replaced on first login
+pub fn is_importable_hash(hash: &str) -> bool {
+    match legacy_verifier(hash) {
+        Some(verifier) => verifier.is_valid(hash),
+        None => hash.parse::<bcrypt::HashParts>().is_ok(),
+    }
+}
+
+/// Whether a stored hash uses one of the legacy formats of `legacy_hashes`
+pub fn is_legacy_hash(stored_hash: &str) -> bool {
+    legacy_verifier(stored_hash).is_some()
+}
+
+/// Hash a password using the configured pepper
+pub fn hash_password(password: &str) -> ServiceResult<String> {
+    PasswordHasher::from_config(&CONFIG).hash(password)
+}
+
+/// Verify a password against a stored (possibly peppered) hash
+pub fn verify_password(password: &str, stored_hash: &str) -> ServiceResult<bool> {
+    if stored_hash == UNUSABLE_PASSWORD_HASH {
+        verify_dummy_password(password);
+        return Ok(false);
+    }
+    PasswordHasher::from_config(&CONFIG).verify(password, stored_hash)
+}
+
+/// Spend the time of verifying a password without an account to verify it against
+pub fn verify_dummy_password(password: &str) {
+    let _ = PasswordHasher::from_config(&CONFIG).verify(password, &DUMMY_PASSWORD_HASH);
+}
+
+/// Whether a stored hash should be replaced with one using the current pepper
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    PasswordHasher::from_config(&CONFIG).needs_rehash(stored_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argon2::{
+        password_hash::{PasswordHasher as _, SaltString},
+        Argon2,
+    };
+
+    fn peppers() -> HashMap<u32, String> {
+        HashMap::from([
+            (1, "first_pepper_secret_that_is_long_enough".to_string()),
+            (2, "second_pepper_secret_that_is_long_enough".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_peppered_hash_roundtrip() {
+        let peppers = peppers();
+        let hasher = PasswordHasher::new(&peppers, 2, 4);
+
+        let hash = hasher.hash("Quartz!Lantern7&Meadow").unwrap();
+        assert!(hash.starts_with("pv2$$2"));
+        assert!(hasher.verify("Quartz!Lantern7&Meadow", &hash).unwrap());
+        assert!(!hasher.verify("wrong", &hash).unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+
+        // The bcrypt part alone does not verify without the pepper
+        let (_, bcrypt_hash) = split_pepper_version(&hash).unwrap();
+        assert!(!bcrypt::verify("Quartz!Lantern7&Meadow", bcrypt_hash).unwrap());
+    }
+
+    #[test]
+    fn test_old_pepper_versions_still_verify() {
+        let peppers = peppers();
+        let old = PasswordHasher::new(&peppers, 1, 4);
+        let current = PasswordHasher::new(&peppers, 2, 4);
+
+        let hash = old.hash("Quartz!Lantern7&Meadow").unwrap();
+        assert!(current.verify("Quartz!Lantern7&Meadow", &hash).unwrap());
+        assert!(current.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_unpeppered_hashes() {
+        let peppers = peppers();
+        let hasher = PasswordHasher::new(&peppers, 2, 4);
+
+        let legacy = bcrypt::hash("Quartz!Lantern7&Meadow", 4).unwrap();
+        assert!(hasher.verify("Quartz!Lantern7&Meadow", &legacy).unwrap());
+        assert!(hasher.needs_rehash(&legacy));
+
+        let no_pepper = HashMap::new();
+        let hasher = PasswordHasher::new(&no_pepper, 0, 4);
+        assert!(!hasher.needs_rehash(&legacy));
+    }
+
+    #[test]
+    fn test_imported_hashes() {
+        let peppers = peppers();
+        let hasher = PasswordHasher::new(&peppers, 2, 4);
+
+        let salt = SaltString::from_b64("c29tZXNhbHQ").unwrap();
+        let argon2 = Argon2::default()
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+        assert!(argon2.starts_with("$argon2id$"));
+        assert!(is_importable_hash(&argon2));
+        assert!(hasher.verify("password", &argon2).unwrap());
+        assert!(!hasher.verify("wrong", &argon2).unwrap());
+        assert!(hasher.needs_rehash(&argon2));
+
+        let bcrypt = bcrypt::hash("Quartz!Lantern7&Meadow", 4).unwrap();
+        assert!(is_importable_hash(&bcrypt));
+        assert!(is_importable_hash(&bcrypt.replacen("$2b$", "$2y$", 1)));
+
+        for hash in [
+            "",
+            "plaintext",
+            "$argon2id$garbage",
+            "$1$salt$md5",
+            "sha256$salt$abc",
+            UNUSABLE_PASSWORD_HASH,
+        ] {
+            assert!(!is_importable_hash(hash), "{hash}");
+        }
+        assert!(!hasher.verify("!", UNUSABLE_PASSWORD_HASH).unwrap());
+
+        // md5-crypt of "password", verified once and then replaced
+        let md5_crypt = "$1$5pZSV9va$azfrPr6af3Fc7dLblQXVa0";
+        assert!(is_importable_hash(md5_crypt));
+        assert!(hasher.verify("password", md5_crypt).unwrap());
+        assert!(hasher.needs_rehash(md5_crypt));
+    }
+
+    #[test]
+    fn test_missing_pepper_version_fails() {
+        let no_pepper = HashMap::new();
+        let hasher = PasswordHasher::new(&no_pepper, 0, 4);
+
+        let result = hasher.verify("password", "pv3$$2b$04$abcdefghijklmnopqrstuv");
+        assert!(matches!(result, Err(ServiceError::PasswordHashError)));
+    }
+}
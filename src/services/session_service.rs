@@ -0,0 +1,425 @@
+use sqlx::{PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    models::{
+        auth_user::MessageResponse,
+        login_event::{LoginEvent, LoginHistoryQuery, LoginHistoryResponse, LOGIN_EVENT_COLUMNS},
+        login_risk::LoginBaseline,
+        pagination::PageCursor,
+        session::{DeviceInfo, DeviceListResponse, UserSession},
+    },
+};
+
+// How many recent successful logins new logins are compared against
+const BASELINE_LOGINS: i64 = 20;
+
+/// Login sessions and the login history of each account
+#[derive(Clone)]
+pub struct SessionService {
+    db_pool: Pool<Postgres>,
+}
+
+impl SessionService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Store a session; pass the transaction of the login that starts it
+    pub async fn create_session(
+        conn: &mut PgConnection,
+        session: &UserSession,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_sessions
+                (id, user_id, device_key, device, ip_address, user_agent, created_at, last_seen_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(session.id)
+        .bind(session.user_id)
+        .bind(&session.device_key)
+        .bind(&session.device)
+        .bind(&session.ip_address)
+        .bind(&session.user_agent)
+        .bind(session.created_at)
+        .bind(session.last_seen_at)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Add a login attempt to the account's history
+    pub async fn record_login_event(
+        conn: &mut PgConnection,
+        event: &LoginEvent,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO login_events
+                (id, user_id, success, failure_reason, method, ip_address, user_agent, device,
+                 location, anomalies, session_id, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+        .bind(event.id)
+        .bind(event.user_id)
+        .bind(event.success)
+        .bind(&event.failure_reason)
+        .bind(&event.method)
+        .bind(&event.ip_address)
+        .bind(&event.user_agent)
+        .bind(&event.device)
+        .bind(&event.location)
+        .bind(&event.anomalies)
+        .bind(event.session_id)
+        .bind(event.created_at)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark a session as used when its token is refreshed; fails if it was revoked meanwhile
+    pub async fn touch_session(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> ServiceResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE user_sessions SET last_seen_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServiceError::InvalidToken);
+        }
+        Ok(())
+    }
+
+    /// Earlier successful logins of the account, for the suspicious-login checks
+    pub async fn login_baseline(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        device_key: &str,
+    ) -> ServiceResult<LoginBaseline> {
+        let baseline = sqlx::query_as::<_, LoginBaseline>(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM login_events WHERE user_id = $1 AND success)
+                    AS has_logged_in,
+                EXISTS (SELECT 1 FROM user_sessions WHERE user_id = $1 AND device_key = $2)
+                    AS known_device,
+                ARRAY(
+                    SELECT DISTINCT ip_address FROM (
+                        SELECT ip_address FROM login_events
+                        WHERE user_id = $1 AND success AND ip_address IS NOT NULL
+                        ORDER BY created_at DESC
+                        LIMIT $3
+                    ) recent
+                ) AS ip_addresses
+            "#,
+        )
+        .bind(user_id)
+        .bind(device_key)
+        .bind(BASELINE_LOGINS)
+        .fetch_one(conn)
+        .await?;
+
+        Ok(baseline)
+    }
+
+    /// Whether tokens of the session are still accepted
+    pub async fn is_active(&self, user_id: Uuid, session_id: Uuid) -> ServiceResult<bool> {
+        let active = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM user_sessions
+                WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            )
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(active)
+    }
+
+    /// Login attempts against the account, most recent first
+    pub async fn login_history(
+        &self,
+        user_id: Uuid,
+        query: LoginHistoryQuery,
+    ) -> ServiceResult<LoginHistoryResponse> {
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(PageCursor::decode)
+            .transpose()?;
+        let limit = query.limit.unwrap_or(50);
+
+        // Fetch one extra row to know whether there is a next page
+        let mut events = sqlx::query_as::<_, LoginEvent>(&format!(
+            r#"
+            SELECT {LOGIN_EVENT_COLUMNS} FROM login_events
+            WHERE user_id = $1
+              AND ($2::TIMESTAMPTZ IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#
+        ))
+        .bind(user_id)
+        .bind(cursor.as_ref().map(|cursor| cursor.created_at))
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(limit + 1)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let next_cursor = if events.len() as i64 > limit {
+            events.truncate(limit as usize);
+            events.last().map(|event| {
+                PageCursor {
+                    created_at: event.created_at,
+                    id: event.id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(LoginHistoryResponse {
+            events,
+            next_cursor,
+        })
+    }
+
+    /// Devices with sessions whose tokens may still be valid, most recently used first
+    pub async fn list_devices(
+        &self,
+        user_id: Uuid,
+        current_session: Option<Uuid>,
+    ) -> ServiceResult<DeviceListResponse> {
+        let devices = sqlx::query_as::<_, DeviceInfo>(
+            r#"
+            SELECT device_key AS id,
+                   (ARRAY_AGG(device ORDER BY last_seen_at DESC))[1] AS device,
+                   (ARRAY_AGG(ip_address ORDER BY last_seen_at DESC))[1] AS ip_address,
+                   MIN(created_at) AS first_seen_at,
+                   MAX(last_seen_at) AS last_seen_at,
+                   COUNT(*) AS sessions,
+                   COALESCE(BOOL_OR(id = $3::UUID), false) AS current
+            FROM user_sessions
+            WHERE user_id = $1 AND revoked_at IS NULL
+              AND last_seen_at > NOW() - make_interval(secs => $2)
+            GROUP BY device_key
+            ORDER BY MAX(last_seen_at) DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(session_lifetime_seconds())
+        .bind(current_session)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(DeviceListResponse { devices })
+    }
+
+    /// Revoke every session of a device, logging it out
+    pub async fn revoke_device(
+        &self,
+        user_id: Uuid,
+        device_id: &str,
+    ) -> ServiceResult<MessageResponse> {
+        let result = sqlx::query(
+            r#"
+            UPDATE user_sessions SET revoked_at = NOW()
+            WHERE user_id = $1 AND device_key = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(device_id)
+        .execute(&self.db_pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServiceError::NotFound);
+        }
+
+        log::info!(
+            "User {} revoked {} session(s) of a device",
+            user_id,
+            result.rows_affected()
+        );
+        Ok(MessageResponse::new("Device logged out"))
+    }
+
+    /// Revoke a single session, e.g. on logout
+    pub async fn revoke_session(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> ServiceResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE user_sessions SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revoke every session of the user, e.g. when the account is deactivated
+    pub async fn revoke_all_sessions(conn: &mut PgConnection, user_id: Uuid) -> ServiceResult<u64> {
+        let result = sqlx::query(
+            "UPDATE user_sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Tokens are at most this old when last refreshed, so older sessions can't be in use
+fn session_lifetime_seconds() -> f64 {
+    CONFIG
+        .jwt_expiration
+        .max(CONFIG.password_change_token_expiration) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        auth_user::AuthUser,
+        login_event::{LoginFailure, LoginMethod},
+        request_context::RequestContext,
+    };
+    use crate::test_support::{test_pool, unique_email};
+
+    async fn insert_user(pool: &Pool<Postgres>) -> AuthUser {
+        let user = AuthUser::new(unique_email("sessions"), "hash".to_string());
+        sqlx::query("INSERT INTO auth_users (id, email, password_hash) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(&user.email)
+            .bind(&user.password_hash)
+            .execute(pool)
+            .await
+            .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_revoking_a_device_ends_its_sessions() {
+        let pool = test_pool().await;
+        let sessions = SessionService::new(pool.clone());
+        let user = insert_user(&pool).await;
+
+        let ctx = RequestContext::default();
+        let session = UserSession::new(user.id, &ctx);
+        let mut conn = pool.acquire().await.unwrap();
+        SessionService::create_session(&mut conn, &session)
+            .await
+            .unwrap();
+        for event in [
+            LoginEvent::failed(
+                user.id,
+                LoginMethod::EmailPassword,
+                LoginFailure::InvalidCredentials,
+                &ctx,
+            ),
+            LoginEvent::succeeded(user.id, LoginMethod::EmailPassword, session.id, &ctx),
+        ] {
+            SessionService::record_login_event(&mut conn, &event)
+                .await
+                .unwrap();
+        }
+
+        let history = sessions
+            .login_history(
+                user.id,
+                LoginHistoryQuery {
+                    limit: Some(1),
+                    cursor: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(history.events.len(), 1);
+        assert!(history.next_cursor.is_some());
+
+        let devices = sessions
+            .list_devices(user.id, Some(session.id))
+            .await
+            .unwrap();
+        assert_eq!(devices.devices.len(), 1);
+        assert!(devices.devices[0].current);
+
+        sessions
+            .revoke_device(user.id, &session.device_key)
+            .await
+            .unwrap();
+        assert!(!sessions.is_active(user.id, session.id).await.unwrap());
+        assert!(sessions
+            .list_devices(user.id, None)
+            .await
+            .unwrap()
+            .devices
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_revoking_sessions() {
+        let pool = test_pool().await;
+        let sessions = SessionService::new(pool.clone());
+        let user = insert_user(&pool).await;
+
+        let ctx = RequestContext::default();
+        let (first, second, third) = (
+            UserSession::new(user.id, &ctx),
+            UserSession::new(user.id, &ctx),
+            UserSession::new(user.id, &ctx),
+        );
+        let mut conn = pool.acquire().await.unwrap();
+        for session in [&first, &second, &third] {
+            SessionService::create_session(&mut conn, session)
+                .await
+                .unwrap();
+        }
+
+        SessionService::revoke_session(&mut conn, user.id, first.id)
+            .await
+            .unwrap();
+        assert!(!sessions.is_active(user.id, first.id).await.unwrap());
+        assert!(sessions.is_active(user.id, second.id).await.unwrap());
+
+        // Sessions revoked before are not counted again
+        let revoked = SessionService::revoke_all_sessions(&mut conn, user.id)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 2);
+        assert!(!sessions.is_active(user.id, second.id).await.unwrap());
+        assert!(!sessions.is_active(user.id, third.id).await.unwrap());
+    }
+}
@@ -0,0 +1,548 @@
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use sqlx::{types::Json, PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    errors::{ServiceError, ServiceResult},
+    models::{
+        outbox::retry_delay,
+        pagination::PageCursor,
+        webhook::{
+            generate_webhook_secret, CreateWebhookSubscriptionRequest,
+            UpdateWebhookSubscriptionRequest, WebhookAttemptOutcome, WebhookDelivery,
+            WebhookDeliveryAttempt, WebhookDeliveryDetails, WebhookDeliveryListResponse,
+            WebhookDeliveryQuery, WebhookDeliveryStatus, WebhookDispatch, WebhookEventType,
+            WebhookPayload, WebhookSubscription, WebhookSubscriptionInfo,
+            WebhookSubscriptionWithSecret, WEBHOOK_DELIVERY_COLUMNS, WEBHOOK_SUBSCRIPTION_COLUMNS,
+        },
+    },
+};
+
+// How long a claimed delivery stays invisible to other workers before it is retried
+const CLAIM_LEASE_SECONDS: i64 = 300;
+
+/// Webhook subscriptions and the durable queue of deliveries to them, delivered by
+/// `WebhookWorker`
+#[derive(Clone)]
+pub struct WebhookService {
+    db_pool: Pool<Postgres>,
+}
+
+impl WebhookService {
+    pub fn new(db_pool: Pool<Postgres>) -> Self {
+        Self { db_pool }
+    }
+
+    /// Queue an event for every active subscription to it; pass the transaction that performs
+    /// the change the event reports
+    pub async fn enqueue(
+        conn: &mut PgConnection,
+        event_type: WebhookEventType,
+        data: Value,
+    ) -> ServiceResult<()> {
+        let payload = WebhookPayload::new(event_type, data);
+
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries
+                (subscription_id, event_id, event_type, payload, status, next_attempt_at)
+            SELECT id, $1, $2, $3, $4, NOW() FROM webhook_subscriptions
+            WHERE is_active AND $2 = ANY(events)
+            "#,
+        )
+        .bind(payload.id)
+        .bind(&payload.event_type)
+        .bind(Json(&payload))
+        .bind(WebhookDeliveryStatus::Pending.as_str())
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Subscribe an endpoint to events, generating a secret unless one is given (admin)
+    pub async fn create_subscription(
+        &self,
+        request: CreateWebhookSubscriptionRequest,
+    ) -> ServiceResult<WebhookSubscriptionWithSecret> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(&format!(
+            r#"
+            INSERT INTO webhook_subscriptions (url, events, secret, description)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {WEBHOOK_SUBSCRIPTION_COLUMNS}
+            "#
+        ))
+        .bind(&request.url)
+        .bind(event_names(&request.events))
+        .bind(request.secret.unwrap_or_else(generate_webhook_secret))
+        .bind(&request.description)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(WebhookSubscriptionWithSecret::from(subscription))
+    }
+
+    /// All subscriptions, oldest first (admin)
+    pub async fn list_subscriptions(&self) -> ServiceResult<Vec<WebhookSubscriptionInfo>> {
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(&format!(
+            "SELECT {WEBHOOK_SUBSCRIPTION_COLUMNS} FROM webhook_subscriptions ORDER BY created_at, id"
+        ))
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(subscriptions
+            .into_iter()
+            .map(WebhookSubscriptionInfo::from)
+            .collect())
+    }
+
+    pub async fn get_subscription(&self, id: Uuid) -> ServiceResult<WebhookSubscriptionInfo> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(&format!(
+            "SELECT {WEBHOOK_SUBSCRIPTION_COLUMNS} FROM webhook_subscriptions WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db_pool)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+        Ok(WebhookSubscriptionInfo::from(subscription))
+    }
+
+    /// Change the given fields of a subscription (admin)
+    pub async fn update_subscription(
+        &self,
+        id: Uuid,
+        request: UpdateWebhookSubscriptionRequest,
+    ) -> ServiceResult<WebhookSubscriptionInfo> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(&format!(
+            r#"
+            UPDATE webhook_subscriptions
+            SET url = COALESCE($2, url),
+                events = COALESCE($3, events),
+                secret = COALESCE($4, secret),
+                description = CASE WHEN $5 THEN $6 ELSE description END,
+                is_active = COALESCE($7, is_active),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {WEBHOOK_SUBSCRIPTION_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(&request.url)
+        .bind(request.events.as_deref().map(event_names))
+        .bind(&request.secret)
+        .bind(request.description.is_some())
+        .bind(request.description.flatten())
+        .bind(request.is_active)
+        .fetch_optional(&self.db_pool)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+        Ok(WebhookSubscriptionInfo::from(subscription))
+    }
+
+    /// Delete a subscription with its deliveries and their log (admin)
+    pub async fn delete_subscription(&self, id: Uuid) -> ServiceResult<()> {
+        let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1")
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServiceError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Deliveries of a subscription matching the filters, most recent first (admin)
+    pub async fn list_deliveries(
+        &self,
+        subscription_id: Uuid,
+        query: WebhookDeliveryQuery,
+    ) -> ServiceResult<WebhookDeliveryListResponse> {
+        // Tell an unknown subscription from one without deliveries
+        self.get_subscription(subscription_id).await?;
+
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(PageCursor::decode)
+            .transpose()?;
+        let limit = query.limit.unwrap_or(50);
+
+        // Fetch one extra row to know whether there is a next page
+        let mut deliveries = sqlx::query_as::<_, WebhookDelivery>(&format!(
+            r#"
+            SELECT {WEBHOOK_DELIVERY_COLUMNS} FROM webhook_deliveries
+            WHERE subscription_id = $1
+              AND ($2::TEXT IS NULL OR status = $2)
+              AND ($3::TEXT IS NULL OR event_type = $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR (created_at, id) < ($4, $5))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $6
+            "#
+        ))
+        .bind(subscription_id)
+        .bind(query.status.map(WebhookDeliveryStatus::as_str))
+        .bind(query.event_type.map(WebhookEventType::as_str))
+        .bind(cursor.as_ref().map(|cursor| cursor.created_at))
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(limit + 1)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let next_cursor = if deliveries.len() as i64 > limit {
+            deliveries.truncate(limit as usize);
+            deliveries.last().map(|delivery| {
+                PageCursor {
+                    created_at: delivery.created_at,
+                    id: delivery.id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(WebhookDeliveryListResponse {
+            deliveries,
+            next_cursor,
+        })
+    }
+
+    /// A delivery with the log of its attempts (admin)
+    pub async fn get_delivery(&self, id: Uuid) -> ServiceResult<WebhookDeliveryDetails> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(&format!(
+            "SELECT {WEBHOOK_DELIVERY_COLUMNS} FROM webhook_deliveries WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db_pool)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+        let attempt_log = sqlx::query_as::<_, WebhookDeliveryAttempt>(
+            r#"
+            SELECT attempt, status_code, error, response_body, duration_ms, created_at
+            FROM webhook_delivery_attempts
+            WHERE delivery_id = $1
+            ORDER BY attempt
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(WebhookDeliveryDetails {
+            delivery,
+            attempt_log,
+        })
+    }
+
+    /// Queue a new delivery of the same event to the same subscription, keeping the original
+    /// and its log (admin)
+    pub async fn replay_delivery(&self, id: Uuid) -> ServiceResult<WebhookDelivery> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(&format!(
+            r#"
+            INSERT INTO webhook_deliveries
+                (subscription_id, event_id, event_type, payload, status, next_attempt_at, replay_of)
+            SELECT subscription_id, event_id, event_type, payload, $2, NOW(), id
+            FROM webhook_deliveries
+            WHERE id = $1
+            RETURNING {WEBHOOK_DELIVERY_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(WebhookDeliveryStatus::Pending.as_str())
+        .fetch_optional(&self.db_pool)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+        Ok(delivery)
+    }
+
+    /// Claim due deliveries of active subscriptions, counting the attempt and hiding them from
+    /// other workers for a lease
+    pub async fn claim_due(&self, batch_size: i64) -> ServiceResult<Vec<WebhookDispatch>> {
+        let dispatches = sqlx::query_as::<_, WebhookDispatch>(
+            r#"
+            WITH claimed AS (
+                UPDATE webhook_deliveries
+                SET attempts = attempts + 1, next_attempt_at = $1, updated_at = NOW()
+                WHERE id IN (
+                    SELECT delivery.id FROM webhook_deliveries delivery
+                    JOIN webhook_subscriptions subscription
+                        ON subscription.id = delivery.subscription_id
+                    WHERE delivery.status = $2
+                      AND delivery.next_attempt_at <= NOW()
+                      AND subscription.is_active
+                    ORDER BY delivery.next_attempt_at
+                    LIMIT $3
+                    FOR UPDATE OF delivery SKIP LOCKED
+                )
+                RETURNING id, subscription_id, event_id, event_type, payload, attempts
+            )
+            SELECT claimed.id, claimed.event_id, claimed.event_type, claimed.payload,
+                   claimed.attempts, subscription.url, subscription.secret
+            FROM claimed
+            JOIN webhook_subscriptions subscription ON subscription.id = claimed.subscription_id
+            "#,
+        )
+        .bind(Utc::now() + Duration::seconds(CLAIM_LEASE_SECONDS))
+        .bind(WebhookDeliveryStatus::Pending.as_str())
+        .bind(batch_size)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(dispatches)
+    }
+
+    /// Log an attempt and mark the delivery as delivered, schedule a retry with exponential
+    /// backoff, or dead-letter it after the last attempt
+    pub async fn record_attempt(
+        &self,
+        dispatch: &WebhookDispatch,
+        outcome: &WebhookAttemptOutcome,
+    ) -> ServiceResult<()> {
+        let (status, next_attempt_at) = if outcome.succeeded() {
+            (WebhookDeliveryStatus::Delivered, Utc::now())
+        } else if dispatch.attempts >= CONFIG.webhook_max_attempts {
+            (WebhookDeliveryStatus::Dead, Utc::now())
+        } else {
+            let delay = retry_delay(
+                dispatch.attempts,
+                CONFIG.webhook_retry_base,
+                CONFIG.webhook_retry_max,
+            );
+            (
+                WebhookDeliveryStatus::Pending,
+                Utc::now() + Duration::seconds(delay),
+            )
+        };
+        let status_code = outcome.status_code.map(i32::from);
+
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_delivery_attempts
+                (delivery_id, attempt, status_code, error, response_body, duration_ms)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(dispatch.id)
+        .bind(dispatch.attempts)
+        .bind(status_code)
+        .bind(&outcome.error)
+        .bind(&outcome.response_body)
+        .bind(outcome.duration_ms)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $1, next_attempt_at = $2, last_status_code = $3, last_error = $4,
+                delivered_at = CASE WHEN $1 = 'delivered' THEN NOW() END, updated_at = NOW()
+            WHERE id = $5
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(next_attempt_at)
+        .bind(status_code)
+        .bind(&outcome.error)
+        .bind(dispatch.id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        if status == WebhookDeliveryStatus::Dead {
+            log::error!(
+                "Webhook delivery {} ({} to {}) dead-lettered after {} attempts: {}",
+                dispatch.id,
+                dispatch.event_type,
+                dispatch.url,
+                dispatch.attempts,
+                outcome.error.as_deref().unwrap_or_default()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// Stored form of the subscribed events, without duplicates
+fn event_names(events: &[WebhookEventType]) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = events.iter().map(|event| event.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+    use serde_json::json;
+    use tokio::sync::Mutex;
+
+    // Claiming takes every due delivery, so tests claiming at once would take each other's
+    static QUEUE: Mutex<()> = Mutex::const_new(());
+
+    fn query() -> WebhookDeliveryQuery {
+        WebhookDeliveryQuery {
+            status: None,
+            event_type: None,
+            limit: Some(100),
+            cursor: None,
+        }
+    }
+
+    fn about_user(delivery: &WebhookDelivery, user_id: Uuid) -> bool {
+        delivery.payload.0["data"]["user_id"] == json!(user_id)
+    }
+
+    #[test]
+    fn test_event_names_are_deduplicated() {
+        assert_eq!(
+            event_names(&[
+                WebhookEventType::UserLocked,
+                WebhookEventType::UserRegistered,
+                WebhookEventType::UserLocked,
+            ]),
+            vec!["user.locked", "user.registered"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_events_are_queued_for_subscribers_and_replayed() {
+        let pool = test_pool().await;
+        let webhooks = WebhookService::new(pool.clone());
+
+        let subscription = webhooks
+            .create_subscription(CreateWebhookSubscriptionRequest {
+                url: "http://localhost:9000/webhook".to_string(),
+                events: vec![WebhookEventType::UserRegistered],
+                secret: None,
+                description: None,
+            })
+            .await
+            .unwrap();
+        assert!(subscription.secret.starts_with("whsec_"));
+        let subscription_id = subscription.subscription.id;
+
+        // Other tests may register users while the subscription exists
+        let user_id = Uuid::new_v4();
+        let mut conn = pool.acquire().await.unwrap();
+        WebhookService::enqueue(
+            &mut conn,
+            WebhookEventType::UserRegistered,
+            json!({ "user_id": user_id }),
+        )
+        .await
+        .unwrap();
+        WebhookService::enqueue(
+            &mut conn,
+            WebhookEventType::UserLocked,
+            json!({ "user_id": user_id }),
+        )
+        .await
+        .unwrap();
+
+        let page = webhooks
+            .list_deliveries(subscription_id, query())
+            .await
+            .unwrap();
+        let delivered: Vec<&WebhookDelivery> = page
+            .deliveries
+            .iter()
+            .filter(|delivery| about_user(delivery, user_id))
+            .collect();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].event_type, "user.registered");
+        let original = delivered[0].clone();
+
+        let replay = webhooks.replay_delivery(original.id).await.unwrap();
+        assert_eq!(replay.event_id, original.event_id);
+        assert_eq!(replay.replay_of, Some(original.id));
+
+        webhooks.delete_subscription(subscription_id).await.unwrap();
+        assert!(matches!(
+            webhooks.get_delivery(replay.id).await,
+            Err(ServiceError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires test database
+    async fn test_failed_deliveries_back_off_and_are_dead_lettered() {
+        let _queue = QUEUE.lock().await;
+        let pool = test_pool().await;
+        let webhooks = WebhookService::new(pool.clone());
+
+        let subscription = webhooks
+            .create_subscription(CreateWebhookSubscriptionRequest {
+                url: "http://localhost:9000/retries".to_string(),
+                events: vec![WebhookEventType::UserLocked],
+                secret: None,
+                description: None,
+            })
+            .await
+            .unwrap();
+        let subscription_id = subscription.subscription.id;
+
+        let user_id = Uuid::new_v4();
+        let mut conn = pool.acquire().await.unwrap();
+        WebhookService::enqueue(
+            &mut conn,
+            WebhookEventType::UserLocked,
+            json!({ "user_id": user_id }),
+        )
+        .await
+        .unwrap();
+
+        let mut dispatch = webhooks
+            .claim_due(1000)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|dispatch| dispatch.payload.0["data"]["user_id"] == json!(user_id))
+            .expect("queued delivery was not claimed");
+        assert_eq!(dispatch.attempts, 1);
+        assert_eq!(dispatch.url, "http://localhost:9000/retries");
+        assert_eq!(dispatch.secret, subscription.secret);
+
+        let outcome = WebhookAttemptOutcome {
+            status_code: Some(500),
+            error: Some("HTTP 500 Internal Server Error".to_string()),
+            response_body: Some("down".to_string()),
+            duration_ms: 12,
+        };
+        let before = Utc::now();
+        webhooks.record_attempt(&dispatch, &outcome).await.unwrap();
+
+        let details = webhooks.get_delivery(dispatch.id).await.unwrap();
+        let delay = retry_delay(1, CONFIG.webhook_retry_base, CONFIG.webhook_retry_max);
+        assert_eq!(details.delivery.status, "pending");
+        assert_eq!(details.delivery.last_status_code, Some(500));
+        assert!(details.delivery.next_attempt_at >= before + Duration::seconds(delay));
+        assert_eq!(details.attempt_log.len(), 1);
+        assert_eq!(
+            details.attempt_log[0].response_body.as_deref(),
+            Some("down")
+        );
+
+        // Retries are not claimed before they are due
+        let claimed = webhooks.claim_due(1000).await.unwrap();
+        assert!(claimed.iter().all(|other| other.id != dispatch.id));
+
+        dispatch.attempts = CONFIG.webhook_max_attempts;
+        webhooks.record_attempt(&dispatch, &outcome).await.unwrap();
+        let details = webhooks.get_delivery(dispatch.id).await.unwrap();
+        assert_eq!(details.delivery.status, "dead");
+        assert_eq!(details.attempt_log.len(), 2);
+
+        webhooks.delete_subscription(subscription_id).await.unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use chrono::Utc;
+use futures_util::future::join_all;
+use reqwest::{header::CONTENT_TYPE, redirect, Client, Response};
+use std::time::{Duration, Instant};
+
+use crate::{
+    config::CONFIG,
+    errors::ServiceResult,
+    models::webhook::{
+        webhook_signature, WebhookAttemptOutcome, WebhookDispatch, WEBHOOK_EVENT_HEADER,
+        WEBHOOK_ID_HEADER, WEBHOOK_SIGNATURE_HEADER, WEBHOOK_TIMESTAMP_HEADER,
+    },
+    services::webhook_service::WebhookService,
+};
+
+// Characters of the response body kept in the delivery log
+const RESPONSE_BODY_MAX_CHARS: usize = 1024;
+
+// Bytes of the response body read, enough for the kept characters; the rest is never read
+const RESPONSE_BODY_MAX_BYTES: usize = 4 * RESPONSE_BODY_MAX_CHARS;
+
+/// Background task that sends queued webhook deliveries to their subscriptions
+pub struct WebhookWorker {
+    webhooks: WebhookService,
+    client: Client,
+}
+
+impl WebhookWorker {
+    pub fn new(webhooks: WebhookService) -> reqwest::Result<Self> {
+        Ok(Self {
+            webhooks,
+            client: webhook_client()?,
+        })
+    }
+
+    /// Poll the queue every `WEBHOOK_POLL_INTERVAL` seconds for the lifetime of the process
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(CONFIG.webhook_poll_interval));
+            loop {
+                interval.tick().await;
+
+                // Keep draining while full batches come back
+                loop {
+                    match self.process_batch().await {
+                        Ok(count) if count as i64 >= CONFIG.webhook_batch_size => continue,
+                        Ok(_) => break,
+                        Err(e) => {
+                            log::error!("Webhook processing failed: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Send one batch of due deliveries, returning how many were claimed
+    pub async fn process_batch(&self) -> ServiceResult<usize> {
+        let dispatches = self.webhooks.claim_due(CONFIG.webhook_batch_size).await?;
+
+        let outcomes = join_all(
+            dispatches
+                .iter()
+                .map(|dispatch| deliver(&self.client, dispatch)),
+        )
+        .await;
+
+        for (dispatch, outcome) in dispatches.iter().zip(&outcomes) {
+            if let Some(error) = &outcome.error {
+                log::warn!(
+                    "Webhook delivery {} to {} (attempt {}) failed: {error}",
+                    dispatch.id,
+                    dispatch.url,
+                    dispatch.attempts
+                );
+            }
+            self.webhooks.record_attempt(dispatch, outcome).await?;
+        }
+
+        Ok(dispatches.len())
+    }
+}
+
+// Redirects are not followed, so a delivery only ever goes to the subscribed URL
+fn webhook_client() -> reqwest::Result<Client> {
+    Client::builder()
+        .timeout(Duration::from_secs(CONFIG.webhook_timeout))
+        .redirect(redirect::Policy::none())
+        .user_agent(concat!("rust-web-service/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// POST the payload, signed with the subscription secret and the current time
+pub async fn deliver(client: &Client, dispatch: &WebhookDispatch) -> WebhookAttemptOutcome {
+    let body = match serde_json::to_vec(&dispatch.payload.0) {
+        Ok(body) => body,
+        Err(e) => {
+            return WebhookAttemptOutcome {
+                error: Some(format!("Payload serialization failed: {e}")),
+                ..Default::default()
+            }
+        }
+    };
+    let timestamp = Utc::now().timestamp();
+    let signature = webhook_signature(&dispatch.secret, timestamp, &body);
+
+    let started = Instant::now();
+    let response = client
+        .post(&dispatch.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_ID_HEADER, dispatch.event_id.to_string())
+        .header(WEBHOOK_EVENT_HEADER, &dispatch.event_type)
+        .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+        .header(WEBHOOK_SIGNATURE_HEADER, signature)
+        .body(body)
+        .send()
+        .await;
+
+    let mut outcome = match response {
+        Ok(response) => {
+            let status = response.status();
+            let response_body = read_body_start(response).await;
+            WebhookAttemptOutcome {
+                status_code: Some(status.as_u16()),
+                error: (!status.is_success()).then(|| format!("HTTP {status}")),
+                response_body: (!response_body.is_empty()).then_some(response_body),
+                duration_ms: 0,
+            }
+        }
+        Err(e) => WebhookAttemptOutcome {
+            error: Some(e.to_string()),
+            ..Default::default()
+        },
+    };
+    outcome.duration_ms = i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX);
+    outcome
+}
+
+// Start of the response body, without reading more of it than the delivery log keeps
+async fn read_body_start(mut response: Response) -> String {
+    let mut body = Vec::new();
+    while body.len() < RESPONSE_BODY_MAX_BYTES {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            Ok(None) | Err(_) => break,
+        }
+    }
+    body.truncate(RESPONSE_BODY_MAX_BYTES);
+
+    String::from_utf8_lossy(&body)
+        .chars()
+        .take(RESPONSE_BODY_MAX_CHARS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::types::Json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use uuid::Uuid;
+
+    // Local receiver answering one request with `status` and `body`, returning the raw request
+    async fn receive_one(
+        status: &'static str,
+        body: String,
+    ) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+
+        let receiver = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            // Read the headers, then the body up to its Content-Length
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            // The client may hang up without reading all of a long body
+            let _ = socket.write_all(response.as_bytes()).await;
+            String::from_utf8(request).unwrap()
+        });
+
+        (url, receiver)
+    }
+
+    fn dispatch(url: String) -> WebhookDispatch {
+        WebhookDispatch {
+            id: Uuid::new_v4(),
+            event_id: Uuid::new_v4(),
+            event_type: "user.registered".to_string(),
+            payload: Json(json!({ "type": "user.registered", "data": { "user_id": Uuid::nil() } })),
+            attempts: 1,
+            url,
+            secret: "whsec_test_secret".to_string(),
+        }
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request.lines().find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.eq_ignore_ascii_case(name).then_some(value.trim())
+        })
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed() {
+        let (url, receiver) = receive_one("200 OK", "ok".to_string()).await;
+        let dispatch = dispatch(url);
+
+        let outcome = deliver(&webhook_client().unwrap(), &dispatch).await;
+        let request = receiver.await.unwrap();
+
+        assert!(outcome.succeeded(), "{:?}", outcome.error);
+        assert_eq!(outcome.status_code, Some(200));
+        assert_eq!(outcome.response_body.as_deref(), Some("ok"));
+
+        let (_, body) = request.split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(body).unwrap(),
+            dispatch.payload.0
+        );
+        assert_eq!(
+            header(&request, WEBHOOK_ID_HEADER),
+            Some(dispatch.event_id.to_string().as_str())
+        );
+        assert_eq!(
+            header(&request, WEBHOOK_EVENT_HEADER),
+            Some("user.registered")
+        );
+        let timestamp: i64 = header(&request, WEBHOOK_TIMESTAMP_HEADER)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((Utc::now().timestamp() - timestamp).abs() < 60);
+        assert_eq!(
+            header(&request, WEBHOOK_SIGNATURE_HEADER),
+            Some(webhook_signature(&dispatch.secret, timestamp, body.as_bytes()).as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries() {
+        let (url, receiver) = receive_one("500 Internal Server Error", "ok".to_string()).await;
+        let outcome = deliver(&webhook_client().unwrap(), &dispatch(url)).await;
+        receiver.await.unwrap();
+
+        assert!(!outcome.succeeded());
+        assert_eq!(outcome.status_code, Some(500));
+        assert_eq!(
+            outcome.error.as_deref(),
+            Some("HTTP 500 Internal Server Error")
+        );
+
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+        drop(listener);
+        let outcome = deliver(&webhook_client().unwrap(), &dispatch(url)).await;
+
+        assert!(!outcome.succeeded());
+        assert_eq!(outcome.status_code, None);
+    }
+
+    #[tokio::test]
+    async fn test_long_response_body_is_cut_short() {
+        let (url, receiver) = receive_one("200 OK", "x".repeat(16 * 1024 * 1024)).await;
+        let outcome = deliver(&webhook_client().unwrap(), &dispatch(url)).await;
+        receiver.await.unwrap();
+
+        assert!(outcome.succeeded(), "{:?}", outcome.error);
+        assert_eq!(
+            outcome.response_body,
+            Some("x".repeat(RESPONSE_BODY_MAX_CHARS))
+        );
+    }
+}
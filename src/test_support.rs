@@ -0,0 +1,28 @@
+//! Helpers for the tests marked `#[ignore] // Requires test database`, which run against the
+//! database at `DATABASE_URL` with `cargo test -- --ignored`
+
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::config::CONFIG;
+
+/// Connect to `DATABASE_URL` and bring its schema up to date
+pub async fn test_pool() -> Pool<Postgres> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&CONFIG.database_url)
+        .await
+        .expect("Failed to connect to the test database at DATABASE_URL");
+
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to run migrations on the test database");
+
+    pool
+}
+
+/// Email address not used by any other test or earlier run, as tests share the database
+pub fn unique_email(prefix: &str) -> String {
+    format!("{prefix}-{}@example.com", Uuid::new_v4().simple())
+}